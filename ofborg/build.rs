@@ -220,6 +220,16 @@ fn events() -> Vec<MetricType> {
             "Number of timed evaluations performed",
             Some(vec![("branch", "String")]),
         ),
+        Metric::counter(
+            "EvalCheckerDuration",
+            "Amount of time spent running evaluation checks",
+            Some(vec![("checker", "String")]),
+        ),
+        Metric::ticker(
+            "EvalCheckerDurationCount",
+            "Number of timed evaluation checks performed",
+            Some(vec![("checker", "String")]),
+        ),
         Metric::ticker(
             "TargetBranchFailsEvaluation",
             "Number of PR evaluations which failed because the target branch failed",
@@ -240,6 +250,16 @@ fn events() -> Vec<MetricType> {
             "Number of jobs for issues which are already closed",
             None,
         ),
+        Metric::ticker(
+            "EvaluationSuperseded",
+            "Number of jobs abandoned because a newer commit was pushed",
+            None,
+        ),
+        Metric::ticker(
+            "BuildRequestThrottled",
+            "Number of comment-triggered builds refused by a quota",
+            Some(vec![("limit", "String")]),
+        ),
         Metric::ticker(
             "IssueFetchFailed",
             "Number of failed fetches for GitHub issues",
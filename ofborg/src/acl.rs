@@ -1,45 +1,233 @@
-use ofborg::systems::System;
+use ofborg::membership::{Group, Membership};
+use ofborg::quota::QuotaPolicy;
+use ofborg::systems::{System, Trust};
+use std::collections::HashMap;
+use std::fmt;
+
+/// The repository key whose policy applies to the repositories in
+/// `runner.repos` without one of their own.
+pub const ANY_REPO: &str = "*";
+
+/// Who may do what on which repository, as configured in the `acl`
+/// section of the configuration.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Policy {
+    /// Named groups of users, which tiers can refer to.
+    #[serde(default)]
+    pub teams: HashMap<String, Vec<String>>,
+
+    /// The policy of each repository, by full name. Only the
+    /// repositories in `runner.repos` are used.
+    #[serde(default)]
+    pub repos: HashMap<String, RepoPolicy>,
+
+    /// How long answers from GitHub about membership of organizations,
+    /// teams and collaborators are remembered. Defaults to ten minutes.
+    #[serde(default)]
+    pub github_membership_ttl_seconds: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RepoPolicy {
+    #[serde(default)]
+    pub trusted: TierPolicy,
+    #[serde(default)]
+    pub known: TierPolicy,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TierPolicy {
+    #[serde(default)]
+    pub users: Vec<String>,
+
+    #[serde(default)]
+    pub teams: Vec<String>,
+
+    /// GitHub organizations whose members belong to the tier.
+    #[serde(default)]
+    pub github_orgs: Vec<String>,
+
+    /// GitHub teams, as `org/team-slug`, whose members belong to the
+    /// tier.
+    #[serde(default)]
+    pub github_teams: Vec<String>,
+
+    /// If the collaborators of the repository belong to the tier.
+    #[serde(default)]
+    pub collaborators: bool,
+
+    /// The systems the tier may build on. When not set, the tier may
+    /// build on every system whose `trust` it satisfies.
+    #[serde(default)]
+    pub systems: Option<Vec<String>>,
+
+    /// The commands the tier may use. When not set, the tier may use
+    /// every command.
+    #[serde(default)]
+    pub commands: Option<Vec<Command>>,
+
+    /// How many builds the tier may request through comments. When
+    /// not set, there is no limit.
+    #[serde(default)]
+    pub quota: Option<QuotaPolicy>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Command {
+    Build,
+    Test,
+    Eval,
+    Cancel,
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Command::Build => "build",
+            Command::Test => "test",
+            Command::Eval => "eval",
+            Command::Cancel => "cancel",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Policy {
+    /// The policy implied by the old `runner` settings: the trusted
+    /// and known users apply to nixos/nixpkgs, and nobody may use
+    /// ofborg elsewhere.
+    pub fn from_user_lists(trusted_users: Vec<String>, known_users: Vec<String>) -> Policy {
+        let mut policies: HashMap<String, RepoPolicy> = HashMap::new();
+        policies.insert(
+            String::from("nixos/nixpkgs"),
+            RepoPolicy {
+                trusted: TierPolicy {
+                    users: trusted_users,
+                    ..Default::default()
+                },
+                known: TierPolicy {
+                    users: known_users,
+                    ..Default::default()
+                },
+            },
+        );
+
+        Policy {
+            teams: HashMap::new(),
+            repos: policies,
+            github_membership_ttl_seconds: None,
+        }
+    }
+
+    /// If any tier refers to GitHub organizations, teams or
+    /// collaborators.
+    pub fn uses_github_membership(&self) -> bool {
+        self.repos.values().any(|policy| {
+            [&policy.trusted, &policy.known].iter().any(|tier| {
+                !tier.github_orgs.is_empty() || !tier.github_teams.is_empty() || tier.collaborators
+            })
+        })
+    }
+}
 
 pub struct ACL {
-    trusted_users: Vec<String>,
-    known_users: Vec<String>,
-    repos: Vec<String>,
+    /// The repositories ofborg acts on.
+    eligible_repos: Vec<String>,
+    teams: HashMap<String, Vec<String>>,
+    repos: HashMap<String, RepoPolicy>,
+    systems: Vec<System>,
+    membership: Option<Box<dyn Membership>>,
 }
 
 impl ACL {
-    pub fn new(
-        repos: Vec<String>,
-        mut trusted_users: Vec<String>,
-        mut known_users: Vec<String>,
-    ) -> ACL {
-        trusted_users
-            .iter_mut()
-            .map(|x| *x = x.to_lowercase())
-            .last();
-        known_users.iter_mut().map(|x| *x = x.to_lowercase()).last();
+    /// An ACL for the `repos` ofborg acts on. Policies of other
+    /// repositories are ignored.
+    pub fn new(repos: Vec<String>, policy: Policy, systems: Vec<System>) -> ACL {
+        let eligible_repos = lowercase(repos);
+        let teams = policy
+            .teams
+            .into_iter()
+            .map(|(team, members)| (team, lowercase(members)))
+            .collect();
+
+        let repos: HashMap<String, RepoPolicy> = policy
+            .repos
+            .into_iter()
+            .map(|(repo, mut policy)| {
+                for tier in [&mut policy.trusted, &mut policy.known].iter_mut() {
+                    tier.users = lowercase(tier.users.clone());
+                    tier.github_orgs = lowercase(tier.github_orgs.clone());
+                }
+                (repo.to_lowercase(), policy)
+            })
+            .collect();
+
+        for repo in repos.keys() {
+            if repo != ANY_REPO && !eligible_repos.contains(repo) {
+                warn!(
+                    "The ACL has a policy for {}, which isn't in runner.repos",
+                    repo
+                );
+            }
+        }
 
         ACL {
-            trusted_users,
-            known_users,
+            eligible_repos,
+            teams,
             repos,
+            systems,
+            membership: None,
         }
     }
 
+    /// Also consult GitHub for the organizations, teams and
+    /// collaborators tiers refer to. Without it, or when GitHub can't
+    /// be reached, only the static user and team lists count.
+    pub fn with_membership(mut self, membership: Box<dyn Membership>) -> ACL {
+        self.membership = Some(membership);
+        self
+    }
+
     pub fn is_repo_eligible(&self, name: &str) -> bool {
-        self.repos.contains(&name.to_lowercase())
+        self.eligible_repos.contains(&name.to_lowercase())
     }
 
-    pub fn build_job_architectures_for_user_repo(&self, user: &str, repo: &str) -> Vec<System> {
-        if self.can_build_unrestricted(user, repo) {
-            vec![
-                System::X8664Darwin,
-                System::X8664Linux,
-                System::Aarch64Linux,
-            ]
-        } else if self.can_build_restricted(user, repo) {
-            vec![System::X8664Linux, System::Aarch64Linux]
+    /// The most trusted tier the user belongs to on the repository.
+    pub fn user_trust(&self, user: &str, repo: &str) -> Option<Trust> {
+        let policy = self.repo_policy(repo)?;
+
+        if self.is_in_tier(user, repo, &policy.trusted) {
+            Some(Trust::Trusted)
+        } else if self.is_in_tier(user, repo, &policy.known) {
+            Some(Trust::Known)
         } else {
-            vec![]
+            None
+        }
+    }
+
+    pub fn build_job_architectures_for_user_repo(&self, user: &str, repo: &str) -> Vec<System> {
+        let trust = match self.user_trust(user, repo) {
+            Some(trust) => trust,
+            None => return vec![],
+        };
+
+        match self
+            .tier_policy(repo, trust)
+            .and_then(|t| t.systems.as_ref())
+        {
+            Some(names) => self
+                .systems
+                .iter()
+                .filter(|system| names.contains(&system.name))
+                .cloned()
+                .collect(),
+            None => self
+                .systems
+                .iter()
+                .filter(|system| system.allows(trust))
+                .cloned()
+                .collect(),
         }
     }
 
@@ -54,19 +242,369 @@ impl ACL {
             .collect()
     }
 
-    pub fn can_build_restricted(&self, user: &str, repo: &str) -> bool {
-        if repo.to_lowercase() != "nixos/nixpkgs" {
-            return false;
+    pub fn can_use_command(&self, user: &str, repo: &str, command: Command) -> bool {
+        let trust = match self.user_trust(user, repo) {
+            Some(trust) => trust,
+            None => return false,
+        };
+
+        match self
+            .tier_policy(repo, trust)
+            .and_then(|t| t.commands.as_ref())
+        {
+            Some(commands) => commands.contains(&command),
+            None => true,
         }
+    }
 
-        self.known_users.contains(&user.to_lowercase())
+    /// The limits on the builds the user may request on the
+    /// repository, if any.
+    pub fn quota_for_user_repo(&self, user: &str, repo: &str) -> Option<&QuotaPolicy> {
+        let trust = self.user_trust(user, repo)?;
+        self.tier_policy(repo, trust)
+            .and_then(|tier| tier.quota.as_ref())
+    }
+
+    pub fn can_build_restricted(&self, user: &str, repo: &str) -> bool {
+        self.user_trust(user, repo) == Some(Trust::Known)
     }
 
     pub fn can_build_unrestricted(&self, user: &str, repo: &str) -> bool {
-        if repo.to_lowercase() == "nixos/nixpkgs" {
-            self.trusted_users.contains(&user.to_lowercase())
-        } else {
-            user == "grahamc"
+        self.user_trust(user, repo) == Some(Trust::Trusted)
+    }
+
+    fn repo_policy(&self, repo: &str) -> Option<&RepoPolicy> {
+        if !self.is_repo_eligible(repo) {
+            return None;
         }
+
+        self.repos
+            .get(&repo.to_lowercase())
+            .or_else(|| self.repos.get(ANY_REPO))
+    }
+
+    fn tier_policy(&self, repo: &str, trust: Trust) -> Option<&TierPolicy> {
+        self.repo_policy(repo).map(|policy| match trust {
+            Trust::Trusted => &policy.trusted,
+            Trust::Known => &policy.known,
+        })
+    }
+
+    fn is_in_tier(&self, user: &str, repo: &str, tier: &TierPolicy) -> bool {
+        let user = user.to_lowercase();
+
+        tier.users.contains(&user)
+            || tier.teams.iter().any(|team| match self.teams.get(team) {
+                Some(members) => members.contains(&user),
+                None => {
+                    warn!("ACL refers to the unknown team {}", team);
+                    false
+                }
+            })
+            || self.is_in_github_groups(&user, repo, tier)
+    }
+
+    fn is_in_github_groups(&self, user: &str, repo: &str, tier: &TierPolicy) -> bool {
+        let membership = match self.membership {
+            Some(ref membership) => membership,
+            None => return false,
+        };
+        let repo = repo.to_lowercase();
+
+        let mut groups: Vec<Group> = tier
+            .github_orgs
+            .iter()
+            .map(|org| Group::Org(org.clone()))
+            .collect();
+        for team in tier.github_teams.iter() {
+            match Group::team(team) {
+                Some(group) => groups.push(group),
+                None => warn!("ACL refers to the malformed GitHub team {}", team),
+            }
+        }
+        if tier.collaborators {
+            groups.push(Group::Collaborators);
+        }
+
+        groups
+            .iter()
+            .any(|group| match membership.is_member(&repo, group, user) {
+                Ok(is_member) => is_member,
+                Err(e) => {
+                    warn!("Falling back to the static ACL: {}", e);
+                    false
+                }
+            })
+    }
+}
+
+fn lowercase(names: Vec<String>) -> Vec<String> {
+    names.into_iter().map(|name| name.to_lowercase()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ofborg::systems;
+    use serde_json;
+
+    fn system_names(systems: Vec<System>) -> Vec<String> {
+        systems.into_iter().map(|system| system.name).collect()
+    }
+
+    fn legacy_acl() -> ACL {
+        ACL::new(
+            vec![String::from("nixos/nixpkgs"), String::from("nixos/ofborg")],
+            Policy::from_user_lists(
+                vec![String::from("Trusted")],
+                vec![String::from("known"), String::from("trusted")],
+            ),
+            systems::default_systems(),
+        )
+    }
+
+    fn policy_acl() -> ACL {
+        let policy: Policy = serde_json::from_str(
+            r#"{
+                "teams": {
+                    "committers": ["Alice", "bob"],
+                    "contributors": ["carol"]
+                },
+                "repos": {
+                    "NixOS/nixpkgs": {
+                        "trusted": {
+                            "users": ["dave"],
+                            "teams": ["committers"]
+                        },
+                        "known": {
+                            "users": ["bob"],
+                            "teams": ["contributors", "missing"],
+                            "systems": ["aarch64-linux"],
+                            "commands": ["build", "eval"],
+                            "quota": {
+                                "window_seconds": 3600,
+                                "builds_per_user": 5
+                            }
+                        }
+                    },
+                    "*": {
+                        "known": {
+                            "users": ["carol"],
+                            "commands": ["eval"]
+                        }
+                    },
+                    "nixos/ofborg": {
+                        "trusted": {
+                            "users": ["carol"]
+                        }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        ACL::new(
+            vec![String::from("nixos/nixpkgs"), String::from("nixos/nix")],
+            policy,
+            systems::default_systems(),
+        )
+    }
+
+    #[test]
+    fn legacy_repo_eligibility() {
+        let acl = legacy_acl();
+        assert!(acl.is_repo_eligible("NixOS/nixpkgs"));
+        assert!(acl.is_repo_eligible("nixos/ofborg"));
+        assert!(!acl.is_repo_eligible("nixos/nix"));
+        assert!(!acl.is_repo_eligible("*"));
+    }
+
+    #[test]
+    fn legacy_build_architectures() {
+        let acl = legacy_acl();
+        assert_eq!(
+            system_names(acl.build_job_architectures_for_user_repo("trusted", "nixos/nixpkgs")),
+            vec!["x86_64-darwin", "x86_64-linux", "aarch64-linux"]
+        );
+        assert_eq!(
+            system_names(acl.build_job_architectures_for_user_repo("known", "nixos/nixpkgs")),
+            vec!["x86_64-linux", "aarch64-linux"]
+        );
+        assert!(acl
+            .build_job_architectures_for_user_repo("someone", "nixos/nixpkgs")
+            .is_empty());
+        assert!(acl
+            .build_job_architectures_for_user_repo("trusted", "nixos/ofborg")
+            .is_empty());
+        assert!(acl
+            .build_job_architectures_for_user_repo("grahamc", "nixos/ofborg")
+            .is_empty());
+    }
+
+    #[test]
+    fn policy_repo_eligibility() {
+        let acl = policy_acl();
+        assert!(acl.is_repo_eligible("nixos/nix"));
+        // Neither the fallback nor a policy of their own makes
+        // repositories eligible.
+        assert!(!acl.is_repo_eligible("nixos/hydra"));
+        assert!(!acl.is_repo_eligible("nixos/ofborg"));
+        assert!(!acl.is_repo_eligible("*"));
+        assert_eq!(acl.user_trust("carol", "nixos/hydra"), None);
+        assert_eq!(acl.user_trust("carol", "nixos/ofborg"), None);
+    }
+
+    #[test]
+    fn trusted_takes_precedence_over_known() {
+        let acl = policy_acl();
+        assert_eq!(acl.user_trust("bob", "nixos/nixpkgs"), Some(Trust::Trusted));
+        assert_eq!(
+            acl.user_trust("ALICE", "nixos/nixpkgs"),
+            Some(Trust::Trusted)
+        );
+        assert_eq!(
+            acl.user_trust("dave", "nixos/nixpkgs"),
+            Some(Trust::Trusted)
+        );
+        assert_eq!(acl.user_trust("carol", "nixos/nixpkgs"), Some(Trust::Known));
+        assert_eq!(acl.user_trust("eve", "nixos/nixpkgs"), None);
+    }
+
+    #[test]
+    fn repo_policy_takes_precedence_over_fallback() {
+        let acl = policy_acl();
+        assert_eq!(acl.user_trust("carol", "nixos/nix"), Some(Trust::Known));
+        assert_eq!(acl.user_trust("bob", "nixos/nix"), None);
+        assert!(acl.can_use_command("carol", "nixos/nix", Command::Eval));
+        assert!(!acl.can_use_command("carol", "nixos/nix", Command::Build));
+        assert_eq!(
+            system_names(acl.build_job_architectures_for_user_repo("carol", "nixos/nix")),
+            vec!["x86_64-linux", "aarch64-linux"]
+        );
+    }
+
+    #[test]
+    fn tier_systems() {
+        let acl = policy_acl();
+        assert_eq!(
+            system_names(acl.build_job_architectures_for_user_repo("carol", "nixos/nixpkgs")),
+            vec!["aarch64-linux"]
+        );
+        assert_eq!(
+            system_names(acl.build_job_architectures_for_user_repo("bob", "nixos/nixpkgs")),
+            vec!["x86_64-darwin", "x86_64-linux", "aarch64-linux"]
+        );
+    }
+
+    #[test]
+    fn tier_commands() {
+        let acl = policy_acl();
+        assert!(acl.can_use_command("carol", "nixos/nixpkgs", Command::Build));
+        assert!(acl.can_use_command("carol", "nixos/nixpkgs", Command::Eval));
+        assert!(!acl.can_use_command("carol", "nixos/nixpkgs", Command::Test));
+        assert!(!acl.can_use_command("carol", "nixos/nixpkgs", Command::Cancel));
+        assert!(acl.can_use_command("bob", "nixos/nixpkgs", Command::Test));
+        assert!(!acl.can_use_command("eve", "nixos/nixpkgs", Command::Eval));
+    }
+
+    #[test]
+    fn tier_quota() {
+        let acl = policy_acl();
+        assert_eq!(
+            acl.quota_for_user_repo("carol", "nixos/nixpkgs"),
+            Some(&QuotaPolicy {
+                window_seconds: 3600,
+                builds_per_user: Some(5),
+                builds_per_pr: None,
+            })
+        );
+        assert_eq!(acl.quota_for_user_repo("bob", "nixos/nixpkgs"), None);
+        assert_eq!(acl.quota_for_user_repo("eve", "nixos/nixpkgs"), None);
+    }
+
+    struct FakeMembership {
+        up: bool,
+    }
+
+    impl Membership for FakeMembership {
+        fn is_member(&self, repo: &str, group: &Group, user: &str) -> Result<bool, String> {
+            if !self.up {
+                return Err(String::from("GitHub is down"));
+            }
+
+            Ok(match *group {
+                Group::Org(ref org) => org == "nixos" && user == "frank",
+                Group::Team(ref org, ref slug) => {
+                    org == "nixos" && slug == "security" && user == "grace"
+                }
+                Group::Collaborators => repo == "nixos/nixpkgs" && user == "heidi",
+            })
+        }
+    }
+
+    fn github_acl(up: bool) -> ACL {
+        let policy: Policy = serde_json::from_str(
+            r#"{
+                "teams": {
+                    "committers": ["alice"]
+                },
+                "repos": {
+                    "nixos/nixpkgs": {
+                        "trusted": {
+                            "teams": ["committers"],
+                            "github_teams": ["NixOS/security"],
+                            "collaborators": true
+                        },
+                        "known": {
+                            "github_orgs": ["NixOS"]
+                        }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert!(policy.uses_github_membership());
+        ACL::new(
+            vec![String::from("nixos/nixpkgs")],
+            policy,
+            systems::default_systems(),
+        )
+        .with_membership(Box::new(FakeMembership { up }))
+    }
+
+    #[test]
+    fn github_membership() {
+        let acl = github_acl(true);
+        assert_eq!(
+            acl.user_trust("alice", "nixos/nixpkgs"),
+            Some(Trust::Trusted)
+        );
+        assert_eq!(
+            acl.user_trust("Grace", "nixos/nixpkgs"),
+            Some(Trust::Trusted)
+        );
+        assert_eq!(
+            acl.user_trust("heidi", "NixOS/nixpkgs"),
+            Some(Trust::Trusted)
+        );
+        assert_eq!(acl.user_trust("frank", "nixos/nixpkgs"), Some(Trust::Known));
+        assert_eq!(acl.user_trust("eve", "nixos/nixpkgs"), None);
+    }
+
+    #[test]
+    fn github_membership_fallback() {
+        let acl = github_acl(false);
+        assert_eq!(
+            acl.user_trust("alice", "nixos/nixpkgs"),
+            Some(Trust::Trusted)
+        );
+        assert_eq!(acl.user_trust("grace", "nixos/nixpkgs"), None);
+        assert_eq!(acl.user_trust("frank", "nixos/nixpkgs"), None);
+    }
+
+    #[test]
+    fn legacy_policy_does_not_use_github_membership() {
+        assert!(!Policy::from_user_lists(vec![], vec![]).uses_github_membership());
     }
 }
@@ -0,0 +1,177 @@
+use hubcaps;
+use ofborg::message::evaluationjob::EvaluationJob;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+/// Somewhere to keep the output of evaluations, like failure logs and
+/// lists of changed paths, so check runs can link to it.
+pub trait ArtifactStore: Send {
+    /// Store the contents at the relative path, returning the URL they
+    /// can be viewed at.
+    fn store(&self, path: &str, contents: &str) -> Result<String, String>;
+}
+
+/// Where an artifact of the evaluation of a job is stored. The path
+/// is the same each time the same commit is evaluated.
+pub fn evaluation_artifact_path(job: &EvaluationJob, name: &str) -> String {
+    format!(
+        "evaluation-artifacts/{}/{}/{}/{}.txt",
+        job.repo.full_name,
+        job.pr.number,
+        job.pr.head_sha,
+        slug(name)
+    )
+}
+
+/// Writes artifacts below the log storage, which is served at `url`.
+pub struct FilesystemArtifactStore {
+    root: PathBuf,
+    url: String,
+}
+
+impl FilesystemArtifactStore {
+    pub fn new(root: PathBuf, url: &str) -> FilesystemArtifactStore {
+        FilesystemArtifactStore {
+            root,
+            url: url.trim_end_matches('/').to_owned(),
+        }
+    }
+}
+
+impl ArtifactStore for FilesystemArtifactStore {
+    fn store(&self, path: &str, contents: &str) -> Result<String, String> {
+        let relative = Path::new(path);
+        let is_relative = relative.is_relative()
+            && relative
+                .components()
+                .all(|component| component != Component::ParentDir);
+        if !is_relative {
+            return Err(format!("{:?} is not a valid artifact path", path));
+        }
+
+        let location = self.root.join(relative);
+        if let Some(dir) = location.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Creating {:?}: {:?}", dir, e))?;
+        }
+
+        File::create(&location)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .map_err(|e| format!("Writing {:?}: {:?}", location, e))?;
+
+        Ok(format!("{}/{}", self.url, path))
+    }
+}
+
+/// Uploads each artifact as a public gist, for deployments without a
+/// publicly served log storage.
+pub struct GistArtifactStore {
+    github: hubcaps::Github,
+}
+
+impl GistArtifactStore {
+    pub fn new(github: hubcaps::Github) -> GistArtifactStore {
+        GistArtifactStore { github }
+    }
+}
+
+impl ArtifactStore for GistArtifactStore {
+    fn store(&self, path: &str, contents: &str) -> Result<String, String> {
+        let name = path.rsplit('/').next().unwrap_or(path).to_owned();
+
+        let mut files: HashMap<String, hubcaps::gists::Content> = HashMap::new();
+        files.insert(
+            name.clone(),
+            hubcaps::gists::Content {
+                filename: Some(name),
+                content: contents.to_owned(),
+            },
+        );
+
+        self.github
+            .gists()
+            .create(&hubcaps::gists::GistOptions {
+                description: None,
+                public: Some(true),
+                files,
+            })
+            .map(|gist| gist.html_url)
+            .map_err(|e| format!("Creating a gist: {:?}", e))
+    }
+}
+
+/// Make the name of an artifact safe to use in paths and URLs.
+fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        String::from("artifact")
+    } else {
+        slug.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ofborg::message::evaluationjob::test_job;
+    use ofborg::test_scratch::TestScratch;
+    use std::io::Read;
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(slug("Changed Paths"), "changed-paths");
+        assert_eq!(
+            slug("nixos/release.nix -A manual"),
+            "nixos-release-nix-a-manual"
+        );
+        assert_eq!(slug("../.."), "artifact");
+    }
+
+    #[test]
+    fn test_evaluation_artifact_path() {
+        assert_eq!(
+            evaluation_artifact_path(&test_job(), "Meta Check"),
+            "evaluation-artifacts/NixOS/nixpkgs/42/abc123/meta-check.txt"
+        );
+    }
+
+    #[test]
+    fn test_filesystem_store() {
+        let scratch = TestScratch::new_dir("artifacts-filesystem-store");
+        let store = FilesystemArtifactStore::new(scratch.path(), "https://logs.example.com/");
+        let path = evaluation_artifact_path(&test_job(), "Meta Check");
+
+        assert_eq!(
+            store.store(&path, "it broke"),
+            Ok(String::from(
+                "https://logs.example.com/evaluation-artifacts/NixOS/nixpkgs/42/abc123/meta-check.txt"
+            ))
+        );
+
+        let mut contents = String::new();
+        File::open(scratch.path().join(&path))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "it broke");
+    }
+
+    #[test]
+    fn test_filesystem_store_rejects_escapes() {
+        let scratch = TestScratch::new_dir("artifacts-filesystem-escape");
+        let store = FilesystemArtifactStore::new(scratch.path(), "https://logs.example.com");
+
+        assert!(store.store("../outside.txt", "nope").is_err());
+        assert!(store.store("/etc/passwd", "nope").is_err());
+    }
+}
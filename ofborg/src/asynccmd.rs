@@ -9,10 +9,13 @@ use std::process::Child;
 use std::process::Command;
 use std::process::ExitStatus;
 use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::sync::mpsc::sync_channel;
 use std::sync::mpsc::{Receiver, SyncSender};
+use std::sync::Arc;
 use std::thread::JoinHandle;
+use std::time::Duration;
 
 // Specifically set to fall under 1/2 of the AMQP library's
 // SyncSender limitation.
@@ -23,6 +26,10 @@ const OUT_CHANNEL_BUFFER_SIZE: usize = 30;
 // the heck.
 const WAITER_CHANNEL_BUFFER_SIZE: usize = 10;
 
+// How often the child waiter checks if the child has exited or has
+// been cancelled.
+const CHILD_POLL_INTERVAL_MS: u64 = 250;
+
 pub struct AsyncCmd {
     command: Command,
 }
@@ -30,6 +37,30 @@ pub struct AsyncCmd {
 pub struct SpawnedAsyncCmd {
     waiter: JoinHandle<(Option<Result<ExitStatus, io::Error>>)>,
     rx: Receiver<String>,
+    cancel: CancelHandle,
+}
+
+/// A handle which can be sent to other threads to kill a spawned
+/// command.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    pub fn new() -> CancelHandle {
+        Default::default()
+    }
+
+    /// Ask for the command to be killed. The command's output ends
+    /// and its exit status becomes available shortly afterwards.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
 }
 
 #[derive(Debug, Hash, PartialEq, Eq)]
@@ -81,9 +112,26 @@ fn child_wait<T: Send + 'static>(
     id: WaitTarget,
     tx: SyncSender<(WaitTarget, WaitResult<T>)>,
     mut waiting_on: Child,
+    cancel: CancelHandle,
 ) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        if let Err(e) = tx.send((id, WaitResult::Process(waiting_on.wait()))) {
+        let status = loop {
+            if cancel.is_cancelled() {
+                info!("Killing cancelled child {}", waiting_on.id());
+                if let Err(e) = waiting_on.kill() {
+                    error!("Failed to kill cancelled child: {:?}", e);
+                }
+                break waiting_on.wait();
+            }
+
+            match waiting_on.try_wait() {
+                Ok(Some(status)) => break Ok(status),
+                Ok(None) => thread::sleep(Duration::from_millis(CHILD_POLL_INTERVAL_MS)),
+                Err(e) => break Err(e),
+            }
+        };
+
+        if let Err(e) = tx.send((id, WaitResult::Process(status))) {
             error!("Failed to send message to the thread waiter: {:?}", e);
         }
     })
@@ -94,7 +142,13 @@ impl AsyncCmd {
         AsyncCmd { command: cmd }
     }
 
-    pub fn spawn(mut self) -> SpawnedAsyncCmd {
+    pub fn spawn(self) -> SpawnedAsyncCmd {
+        self.spawn_with_cancel(CancelHandle::new())
+    }
+
+    /// Spawn the command, killing it once the handle is cancelled. One
+    /// handle can be shared by several commands, which are all killed.
+    pub fn spawn_with_cancel(mut self, cancel: CancelHandle) -> SpawnedAsyncCmd {
         let mut child = self
             .command
             .stdin(Stdio::null())
@@ -127,7 +181,7 @@ impl AsyncCmd {
 
         waiters.insert(
             WaitTarget::Child,
-            child_wait(WaitTarget::Child, monitor_tx.clone(), child),
+            child_wait(WaitTarget::Child, monitor_tx.clone(), child, cancel.clone()),
         );
 
         let head_waiter = thread::spawn(move || {
@@ -176,6 +230,7 @@ impl AsyncCmd {
         SpawnedAsyncCmd {
             waiter: head_waiter,
             rx: proc_rx,
+            cancel,
         }
     }
 }
@@ -185,6 +240,10 @@ impl SpawnedAsyncCmd {
         self.rx.iter()
     }
 
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
     pub fn wait(self) -> Result<ExitStatus, io::Error> {
         self.waiter
             .join()
@@ -269,6 +328,25 @@ mod tests {
         assert_eq!(true, exit_status.success());
     }
 
+    #[test]
+    fn cancel_test() {
+        let mut cmd = Command::new("/bin/sh");
+        cmd.arg("-c");
+        cmd.arg("echo hi; exec sleep 60");
+        let acmd = AsyncCmd::new(cmd);
+
+        let mut spawned = acmd.spawn();
+        let cancel = spawned.cancel_handle();
+        let mut lines: Vec<String> = vec![];
+        for line in spawned.lines() {
+            lines.push(line);
+            cancel.cancel();
+        }
+        assert_eq!(lines, vec!["hi"]);
+        assert!(cancel.is_cancelled());
+        assert!(!spawned.wait().unwrap().success());
+    }
+
     #[test]
     fn bad_utf8_test() {
         let mut echos = OsString::from("echo hi; echo ");
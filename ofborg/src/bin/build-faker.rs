@@ -47,10 +47,12 @@ fn main() {
         repo: repo_msg.clone(),
         pr: pr_msg.clone(),
         subset: Some(commentparser::Subset::Nixpkgs),
+        systems: None,
         attrs: vec!["success".to_owned()],
         logs: Some((Some("logs".to_owned()), Some(logbackrk.to_lowercase()))),
         statusreport: Some((None, Some("scratch".to_owned()))),
         request_id: "bogus-request-id".to_owned(),
+        requested_at: None,
     };
 
     {
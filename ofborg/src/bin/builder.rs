@@ -6,6 +6,7 @@ extern crate ofborg;
 extern crate log;
 
 use std::env;
+use std::thread;
 
 use amqp::Basic;
 use ofborg::checkout;
@@ -14,6 +15,7 @@ use ofborg::easyamqp;
 use ofborg::easyamqp::TypedWrappers;
 use ofborg::notifyworker;
 use ofborg::tasks;
+use ofborg::worker;
 use std::path::Path;
 
 fn main() {
@@ -90,6 +92,122 @@ fn main() {
         })
         .unwrap();
 
+    // Cancellations and head updates are received on their own
+    // channel, so they can be handled while the build channel is busy
+    // running a build.
+    let running = tasks::buildcancel::RunningBuilds::new();
+    let heads = ofborg::latestheads::LatestHeads::new();
+    let mut cancel_channel = session.open_channel(2).unwrap();
+    cancel_channel
+        .declare_exchange(easyamqp::ExchangeConfig {
+            exchange: "build-cancels".to_owned(),
+            exchange_type: easyamqp::ExchangeType::Fanout,
+            passive: false,
+            durable: true,
+            auto_delete: false,
+            no_wait: false,
+            internal: false,
+            arguments: None,
+        })
+        .unwrap();
+
+    let cancel_queue_name = cancel_channel
+        .declare_queue(easyamqp::QueueConfig {
+            queue: "".to_owned(),
+            passive: false,
+            durable: false,
+            exclusive: true,
+            auto_delete: true,
+            no_wait: false,
+            arguments: None,
+        })
+        .unwrap()
+        .queue;
+
+    cancel_channel
+        .bind_queue(easyamqp::BindQueueConfig {
+            queue: cancel_queue_name.clone(),
+            exchange: "build-cancels".to_owned(),
+            routing_key: None,
+            no_wait: false,
+            arguments: None,
+        })
+        .unwrap();
+
+    cancel_channel
+        .consume(
+            worker::new(tasks::buildcancel::BuildCancelWorker::new(running.clone())),
+            easyamqp::ConsumeConfig {
+                queue: cancel_queue_name,
+                consumer_tag: format!("{}-build-cancel", cfg.whoami()),
+                no_local: false,
+                no_ack: false,
+                no_wait: false,
+                exclusive: false,
+                arguments: None,
+            },
+        )
+        .unwrap();
+
+    cancel_channel
+        .declare_exchange(easyamqp::ExchangeConfig {
+            exchange: "pr-head-updates".to_owned(),
+            exchange_type: easyamqp::ExchangeType::Fanout,
+            passive: false,
+            durable: true,
+            auto_delete: false,
+            no_wait: false,
+            internal: false,
+            arguments: None,
+        })
+        .unwrap();
+
+    let head_queue_name = cancel_channel
+        .declare_queue(easyamqp::QueueConfig {
+            queue: "".to_owned(),
+            passive: false,
+            durable: false,
+            exclusive: true,
+            auto_delete: true,
+            no_wait: false,
+            arguments: None,
+        })
+        .unwrap()
+        .queue;
+
+    cancel_channel
+        .bind_queue(easyamqp::BindQueueConfig {
+            queue: head_queue_name.clone(),
+            exchange: "pr-head-updates".to_owned(),
+            routing_key: None,
+            no_wait: false,
+            arguments: None,
+        })
+        .unwrap();
+
+    cancel_channel
+        .consume(
+            worker::new(tasks::headupdate::HeadUpdateWorker::cancelling_builds(
+                heads.clone(),
+                running.clone(),
+            )),
+            easyamqp::ConsumeConfig {
+                queue: head_queue_name,
+                consumer_tag: format!("{}-builder-head-updates", cfg.whoami()),
+                no_local: false,
+                no_ack: false,
+                no_wait: false,
+                exclusive: false,
+                arguments: None,
+            },
+        )
+        .unwrap();
+
+    thread::spawn(move || {
+        cancel_channel.start_consuming();
+        println!("Finished consuming cancellations?");
+    });
+
     channel
         .consume(
             notifyworker::new(tasks::build::BuildWorker::new(
@@ -97,6 +215,12 @@ fn main() {
                 nix,
                 cfg.nix.system.clone(),
                 cfg.runner.identity.clone(),
+                running,
+                heads,
+                tasks::build::BuildOptions {
+                    binary_cache: cfg.binary_cache.clone(),
+                    target_sizes: cfg.builder.clone().unwrap_or_default().target_sizes(),
+                },
             )),
             easyamqp::ConsumeConfig {
                 queue: queue_name.clone(),
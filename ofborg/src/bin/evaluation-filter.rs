@@ -40,6 +40,19 @@ fn main() {
         })
         .unwrap();
 
+    channel
+        .declare_exchange(easyamqp::ExchangeConfig {
+            exchange: "pr-head-updates".to_owned(),
+            exchange_type: easyamqp::ExchangeType::Fanout,
+            passive: false,
+            durable: true,
+            auto_delete: false,
+            no_wait: false,
+            internal: false,
+            arguments: None,
+        })
+        .unwrap();
+
     channel
         .declare_queue(easyamqp::QueueConfig {
             queue: "mass-rebuild-check-jobs".to_owned(),
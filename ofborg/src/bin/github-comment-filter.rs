@@ -13,6 +13,7 @@ use amqp::Basic;
 use ofborg::config;
 use ofborg::easyamqp;
 use ofborg::easyamqp::TypedWrappers;
+use ofborg::stats;
 use ofborg::tasks;
 use ofborg::worker;
 
@@ -26,6 +27,9 @@ fn main() {
     println!("Connected to rabbitmq");
 
     let mut channel = session.open_channel(1).unwrap();
+
+    let events = stats::RabbitMQ::new(&cfg.whoami(), session.open_channel(3).unwrap());
+
     channel
         .declare_exchange(easyamqp::ExchangeConfig {
             exchange: "github-events".to_owned(),
@@ -52,6 +56,19 @@ fn main() {
         })
         .unwrap();
 
+    channel
+        .declare_exchange(easyamqp::ExchangeConfig {
+            exchange: "build-cancels".to_owned(),
+            exchange_type: easyamqp::ExchangeType::Fanout,
+            passive: false,
+            durable: true,
+            auto_delete: false,
+            no_wait: false,
+            internal: false,
+            arguments: None,
+        })
+        .unwrap();
+
     channel
         .declare_queue(easyamqp::QueueConfig {
             queue: "build-inputs".to_owned(),
@@ -80,6 +97,7 @@ fn main() {
             worker::new(tasks::githubcommentfilter::GitHubCommentWorker::new(
                 cfg.acl(),
                 cfg.github(),
+                events,
             )),
             easyamqp::ConsumeConfig {
                 queue: "build-inputs".to_owned(),
@@ -39,9 +39,11 @@ fn main() {
             owner: "ofborg-test".to_owned(),
         },
         subset: None,
+        systems: None,
         logs: Some((Some(String::from("logs")), Some(String::from("build.log")))),
         statusreport: Some((Some(String::from("build-results")), None)),
         request_id: "bogus-request-id".to_owned(),
+        requested_at: None,
     };
 
     loop {
@@ -7,8 +7,9 @@ use ofborg::checkout;
 use ofborg::config;
 use ofborg::tasks;
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::thread;
 
 use amqp::Basic;
 use ofborg::easyamqp;
@@ -47,17 +48,102 @@ fn main() {
         session.open_channel(3).unwrap(),
     );
 
+    // Head updates are received on their own channel, so they can be
+    // handled while an evaluation is running.
+    let heads = ofborg::latestheads::LatestHeads::new();
+    let mut head_channel = session.open_channel(2).unwrap();
+    head_channel
+        .declare_exchange(easyamqp::ExchangeConfig {
+            exchange: "pr-head-updates".to_owned(),
+            exchange_type: easyamqp::ExchangeType::Fanout,
+            passive: false,
+            durable: true,
+            auto_delete: false,
+            no_wait: false,
+            internal: false,
+            arguments: None,
+        })
+        .unwrap();
+
+    let head_queue_name = head_channel
+        .declare_queue(easyamqp::QueueConfig {
+            queue: "".to_owned(),
+            passive: false,
+            durable: false,
+            exclusive: true,
+            auto_delete: true,
+            no_wait: false,
+            arguments: None,
+        })
+        .unwrap()
+        .queue;
+
+    head_channel
+        .bind_queue(easyamqp::BindQueueConfig {
+            queue: head_queue_name.clone(),
+            exchange: "pr-head-updates".to_owned(),
+            routing_key: None,
+            no_wait: false,
+            arguments: None,
+        })
+        .unwrap();
+
+    head_channel
+        .consume(
+            worker::new(tasks::headupdate::HeadUpdateWorker::new(heads.clone())),
+            easyamqp::ConsumeConfig {
+                queue: head_queue_name,
+                consumer_tag: format!("{}-mass-rebuild-head-updates", cfg.whoami()),
+                no_local: false,
+                no_ack: false,
+                no_wait: false,
+                exclusive: false,
+                arguments: None,
+            },
+        )
+        .unwrap();
+
+    thread::spawn(move || {
+        head_channel.start_consuming();
+        println!("Finished consuming head updates?");
+    });
+
     let mrw = tasks::evaluate::EvaluationWorker::new(
         cloner,
         &nix,
-        cfg.github(),
+        cfg.artifact_store(),
         cfg.github_app_vendingmachine(),
         cfg.acl(),
         cfg.runner.identity.clone(),
         events,
         cfg.tag_paths.clone().unwrap(),
+        cfg.systems(),
+        cfg.auto_build(),
+        cfg.rebuild_buckets(),
+        cfg.performance(),
+        cfg.closure_size(),
+        heads,
+        cfg.log_storage
+            .clone()
+            .map(|storage| PathBuf::from(storage.path)),
+        cfg.checker_pool(),
+        cfg.eval_checks(),
+        cfg.outpath_cache(),
     );
 
+    channel
+        .declare_exchange(easyamqp::ExchangeConfig {
+            exchange: "evaluation-reports".to_owned(),
+            exchange_type: easyamqp::ExchangeType::Fanout,
+            passive: false,
+            durable: true,
+            auto_delete: false,
+            no_wait: false,
+            internal: false,
+            arguments: None,
+        })
+        .unwrap();
+
     channel
         .declare_queue(easyamqp::QueueConfig {
             queue: "mass-rebuild-check-jobs".to_owned(),
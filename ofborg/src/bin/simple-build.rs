@@ -17,16 +17,13 @@ fn main() {
 
     let nix = cfg.nix();
 
-    match nix.safely_build_attrs(
+    let (outcome, mut out) = nix.safely_build_attrs(
         &Path::new("./"),
         nix::File::DefaultNixpkgs,
         vec![String::from("hello")],
-    ) {
-        Ok(mut out) => {
-            print!("{}", file_to_str(&mut out));
-        }
-        Err(mut out) => print!("{}", file_to_str(&mut out)),
-    }
+    );
+    print!("{}", file_to_str(&mut out));
+    println!("{:?}", outcome);
 }
 
 fn file_to_str(f: &mut File) -> String {
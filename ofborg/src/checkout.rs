@@ -150,6 +150,27 @@ impl CachedProjectCo {
         }
     }
 
+    pub fn head_commit(&self) -> Result<String, Error> {
+        let mut lock = self.lock()?;
+
+        let result = Command::new("git")
+            .arg("rev-parse")
+            .arg("HEAD")
+            .current_dir(self.clone_to())
+            .output()?;
+
+        lock.unlock();
+
+        if result.status.success() {
+            Ok(String::from_utf8_lossy(&result.stdout).trim().to_owned())
+        } else {
+            Err(Error::new(
+                ErrorKind::Other,
+                String::from_utf8_lossy(&result.stderr).to_lowercase(),
+            ))
+        }
+    }
+
     pub fn commit_messages_from_head(&self, commit: &str) -> Result<Vec<String>, Error> {
         let mut lock = self.lock()?;
 
@@ -0,0 +1,542 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use hubcaps;
+use hubcaps::checks::Output;
+use hubcaps::checks::{
+    Annotation, AnnotationLevel, CheckRunOptions, CheckRunState, CheckRunUpdateOptions, Conclusion,
+};
+use ofborg::message::evaluationreport::{EvaluationReport, EvaluationStatus};
+use ofborg::nix;
+use ofborg::outpathdiff::PackageArch;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+/// GitHub refuses summaries and texts longer than this.
+const MAX_OUTPUT_LENGTH: usize = 65535;
+
+/// GitHub accepts at most this many annotations per request.
+const MAX_ANNOTATIONS: usize = 50;
+
+/// How many of the errors Nix reported are listed.
+const MAX_ERRORS: usize = 10;
+
+/// How many lines of a failing command's output are shown in its
+/// check run. The full output is linked from it.
+pub const EXCERPT_LINES: usize = 50;
+
+/// A check run reporting on part of an evaluation. The check run is
+/// created by its first update, and later updates edit it.
+pub struct EvalCheckRun<'a> {
+    api: hubcaps::checks::CheckRuns<'a>,
+    /// The check run's id, once it was created.
+    id: Option<u64>,
+    sha: String,
+    name: String,
+    summary: String,
+    text: Option<String>,
+    url: Option<String>,
+    annotations: Vec<Annotation>,
+    started_at: DateTime<Utc>,
+}
+
+impl<'a> EvalCheckRun<'a> {
+    pub fn new(
+        api: hubcaps::checks::CheckRuns<'a>,
+        sha: String,
+        name: String,
+        summary: String,
+    ) -> EvalCheckRun<'a> {
+        EvalCheckRun {
+            api,
+            id: None,
+            sha,
+            name,
+            summary,
+            text: None,
+            url: None,
+            annotations: vec![],
+            started_at: Utc::now(),
+        }
+    }
+
+    pub fn set_url(&mut self, url: Option<String>) {
+        self.url = url;
+    }
+
+    pub fn set_summary(&mut self, summary: String) {
+        self.summary = summary;
+    }
+
+    pub fn set_text(&mut self, text: Option<String>) {
+        self.text = text;
+    }
+
+    pub fn set_annotations(&mut self, annotations: Vec<Annotation>) {
+        self.annotations = annotations;
+    }
+
+    /// Mark the check run as queued, waiting for something else to
+    /// finish.
+    pub fn set_queued(&mut self, title: &str) {
+        self.send(CheckRunState::Queued, None, title);
+    }
+
+    /// Mark the check run as in progress, with the title describing
+    /// what is being done.
+    pub fn set_progress(&mut self, title: &str) {
+        self.send(CheckRunState::InProgress, None, title);
+    }
+
+    pub fn complete(&mut self, conclusion: Conclusion, title: &str) {
+        self.send(CheckRunState::Completed, Some(conclusion), title);
+    }
+
+    fn send(&mut self, state: CheckRunState, conclusion: Option<Conclusion>, title: &str) {
+        let completed_at = if state == CheckRunState::Completed {
+            Some(Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true))
+        } else {
+            None
+        };
+
+        let annotations = if self.annotations.is_empty() {
+            None
+        } else {
+            Some(self.annotations.clone())
+        };
+
+        let output = Output {
+            title: title.to_owned(),
+            summary: truncate(&self.summary, MAX_OUTPUT_LENGTH),
+            text: self
+                .text
+                .as_ref()
+                .map(|text| truncate(text, MAX_OUTPUT_LENGTH)),
+            annotations,
+            images: None,
+        };
+
+        if let Some(id) = self.id {
+            let options = CheckRunUpdateOptions {
+                name: None,
+                actions: None,
+                started_at: None,
+                completed_at,
+                conclusion,
+                details_url: self.url.clone(),
+                external_id: None,
+                output: Some(output),
+                status: Some(state),
+            };
+
+            if let Err(e) = self.api.update(&id.to_string(), &options) {
+                warn!("Failed to update the {} check run: {:?}", self.name, e);
+            }
+            return;
+        }
+
+        let options = CheckRunOptions {
+            name: self.name.clone(),
+            actions: None,
+            started_at: Some(self.started_at.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            completed_at,
+            conclusion,
+            details_url: self.url.clone(),
+            external_id: None,
+            head_sha: self.sha.clone(),
+            output: Some(output),
+            status: Some(state),
+        };
+
+        // A check run which failed to be created is created again by
+        // the next update.
+        match self.api.create(&options) {
+            Ok(run) => self.id = Some(run.id),
+            Err(e) => warn!("Failed to send the {} check run: {:?}", self.name, e),
+        }
+    }
+}
+
+/// The errors Nix reported as a Markdown list, or nothing if none were
+/// recognised.
+pub fn nix_errors(errors: &[nix::NixError]) -> Vec<String> {
+    if errors.is_empty() {
+        return vec![];
+    }
+
+    let mut lines = vec![String::from("Nix reported:"), String::from("")];
+    for error in errors.iter().take(MAX_ERRORS) {
+        lines.push(format!("- {}", error));
+    }
+    if errors.len() > MAX_ERRORS {
+        lines.push(format!("- and {} more", errors.len() - MAX_ERRORS));
+    }
+    lines.push(String::from(""));
+    lines
+}
+
+/// The errors Nix reported, followed by the end of the output.
+pub fn failure_text(output: &str) -> String {
+    let mut text = nix_errors(&nix::parse_errors(output));
+    text.push(output_excerpt(output));
+    truncate(&text.join("\n"), MAX_OUTPUT_LENGTH)
+}
+
+/// The end of a command's output, as Markdown.
+pub fn output_excerpt(output: &str) -> String {
+    let lines: Vec<&str> = output.lines().collect();
+    let skipped = lines.len().saturating_sub(EXCERPT_LINES);
+
+    let mut excerpt: Vec<String> = vec![];
+    if skipped > 0 {
+        excerpt.push(format!(
+            "## Last {} lines of the output",
+            lines.len() - skipped
+        ));
+    } else {
+        excerpt.push(String::from("## Output"));
+    }
+    excerpt.push(String::from(""));
+    excerpt.push(String::from("```"));
+    excerpt.extend(lines[skipped..].iter().map(|line| (*line).to_owned()));
+    excerpt.push(String::from("```"));
+
+    excerpt.join("\n")
+}
+
+/// Annotate the files Nix reported errors in. Each error is placed at
+/// the first location within the checkout at `root` which follows it.
+pub fn annotations(output: &str, root: &Path, title: &str) -> Vec<Annotation> {
+    let prefix = format!("{}/", root.display().to_string().trim_end_matches('/'));
+    let mut seen: HashSet<(String, u32)> = HashSet::new();
+    let mut annotations: Vec<Annotation> = vec![];
+    let mut error: Option<&str> = None;
+
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("error:") {
+            error = Some(trimmed);
+        }
+
+        let message = match error {
+            Some(message) => message,
+            None => continue,
+        };
+
+        if let Some((path, start_line, start_column)) = find_location(line, &prefix) {
+            error = None;
+            if !seen.insert((path.clone(), start_line)) {
+                continue;
+            }
+
+            annotations.push(Annotation {
+                path,
+                start_line,
+                end_line: start_line,
+                start_column,
+                end_column: start_column,
+                annotation_level: AnnotationLevel::Failure,
+                message: message.to_owned(),
+                title: Some(title.to_owned()),
+                raw_details: None,
+            });
+
+            if annotations.len() == MAX_ANNOTATIONS {
+                break;
+            }
+        }
+    }
+
+    annotations
+}
+
+/// Find a `<prefix><path>:<line>[:<column>]` location in the line.
+fn find_location(line: &str, prefix: &str) -> Option<(String, u32, Option<u32>)> {
+    let start = line.find(prefix)? + prefix.len();
+    let mut parts = line[start..].splitn(3, ':');
+
+    let path = parts.next()?;
+    let line_number = parts.next()?.parse::<u32>().ok()?;
+    let column = parts.next().and_then(|rest| {
+        let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+        digits.parse::<u32>().ok()
+    });
+
+    if path.is_empty() || path.contains(char::is_whitespace) {
+        return None;
+    }
+
+    Some((path.to_owned(), line_number, column))
+}
+
+/// The conclusion and title of the overall evaluation check run.
+pub fn evaluation_conclusion(status: &EvaluationStatus) -> (Conclusion, String) {
+    match *status {
+        EvaluationStatus::Success => (Conclusion::Success, String::from("Success")),
+        EvaluationStatus::Failure { ref reason } => (Conclusion::Failure, reason.clone()),
+        EvaluationStatus::MergeConflict => (Conclusion::Failure, String::from("Failed to merge")),
+        EvaluationStatus::Superseded => (
+            Conclusion::Cancelled,
+            String::from("Superseded by a newer commit"),
+        ),
+    }
+}
+
+/// Summarize the report of an evaluation as Markdown.
+pub fn evaluation_summary(report: &EvaluationReport) -> String {
+    let mut summary: Vec<String> = vec![];
+
+    if !report.checks.is_empty() {
+        summary.push(String::from("| Check | Result |"));
+        summary.push(String::from("|-|-|"));
+        for check in &report.checks {
+            summary.push(format!(
+                "| {} | {} |",
+                check.name,
+                if check.success { "Passed" } else { "Failed" }
+            ));
+        }
+        summary.push(String::from(""));
+    }
+
+    if let Some(ref rebuilds) = report.rebuilds {
+        if rebuilds.is_empty() {
+            summary.push(String::from("No packages are rebuilt."));
+        } else {
+            summary.extend(rebuild_counts(rebuilds));
+        }
+        summary.push(String::from(""));
+    }
+
+    if let (Some(added), Some(removed)) = (report.added.as_ref(), report.removed.as_ref()) {
+        summary.push(format!(
+            "{} packages added, {} packages removed.",
+            added.len(),
+            removed.len()
+        ));
+        summary.push(String::from(""));
+    }
+
+    if let Some(ref stdenvs) = report.stdenv_changes {
+        if !stdenvs.is_empty() {
+            summary.push(format!("The stdenv changes on {}.", stdenvs.join(", ")));
+            summary.push(String::from(""));
+        }
+    }
+
+    summary.join("\n").trim_end().to_owned()
+}
+
+/// A Markdown table of how many attributes are rebuilt on each system.
+pub fn rebuild_counts(rebuilds: &[PackageArch]) -> Vec<String> {
+    let mut by_system: BTreeMap<&str, usize> = BTreeMap::new();
+    for rebuild in rebuilds {
+        *by_system.entry(&rebuild.architecture).or_insert(0) += 1;
+    }
+
+    let mut table = vec![String::from("| System | Rebuilds |"), String::from("|-|-|")];
+    for (system, count) in by_system {
+        table.push(format!("| {} | {} |", system, count));
+    }
+    table
+}
+
+/// A size in bytes, for people.
+pub fn human_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+/// How much a size changed, like `+1.5 MiB`.
+pub fn size_change(before: u64, after: u64) -> String {
+    if after >= before {
+        format!("+{}", human_size(after - before))
+    } else {
+        format!("-{}", human_size(before - after))
+    }
+}
+
+/// The text cut to at most `max` bytes, on a character boundary.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_owned();
+    }
+
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text[..end].to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ofborg::message::evaluationjob::EvaluationJob;
+    use ofborg::message::evaluationreport::CheckOutcome;
+    use ofborg::message::{Pr, Repo};
+
+    #[test]
+    fn test_annotations() {
+        let output = "evaluating...
+error: while evaluating the attribute 'hello' at /tmp/co/pkgs/top-level/all-packages.nix:12:3:
+while evaluating 'callPackage' at /tmp/co/lib/customisation.nix:117:5:
+error: undefined variable 'fetchurll'
+
+       at /tmp/co/pkgs/hello/default.nix:5:9:
+
+            4|
+            5|   src = fetchurll {
+error: undefined variable 'fetchurll' at /tmp/co/pkgs/hello/default.nix:5:9
+error: outside the checkout at /nix/store/abc-source/default.nix:1:1
+";
+
+        assert_eq!(
+            annotations(output, Path::new("/tmp/co"), "eval"),
+            vec![
+                Annotation {
+                    path: String::from("pkgs/top-level/all-packages.nix"),
+                    start_line: 12,
+                    end_line: 12,
+                    start_column: Some(3),
+                    end_column: Some(3),
+                    annotation_level: AnnotationLevel::Failure,
+                    message: String::from(
+                        "error: while evaluating the attribute 'hello' at /tmp/co/pkgs/top-level/all-packages.nix:12:3:"
+                    ),
+                    title: Some(String::from("eval")),
+                    raw_details: None,
+                },
+                Annotation {
+                    path: String::from("pkgs/hello/default.nix"),
+                    start_line: 5,
+                    end_line: 5,
+                    start_column: Some(9),
+                    end_column: Some(9),
+                    annotation_level: AnnotationLevel::Failure,
+                    message: String::from("error: undefined variable 'fetchurll'"),
+                    title: Some(String::from("eval")),
+                    raw_details: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_output_excerpt() {
+        assert_eq!(
+            output_excerpt("one\ntwo\n"),
+            "## Output\n\n```\none\ntwo\n```"
+        );
+
+        let long: Vec<String> = (0..60).map(|i| format!("line {}", i)).collect();
+        let excerpt = output_excerpt(&long.join("\n"));
+        assert!(excerpt.starts_with("## Last 50 lines of the output\n\n```\nline 10\n"));
+        assert!(excerpt.ends_with("line 59\n```"));
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+        assert_eq!(size_change(1024, 3072), "+2.0 KiB");
+        assert_eq!(size_change(3072, 1024), "-2.0 KiB");
+    }
+
+    #[test]
+    fn test_failure_text() {
+        assert_eq!(failure_text("one\n"), "## Output\n\n```\none\n```");
+
+        assert_eq!(
+            failure_text("error: infinite recursion encountered, at undefined position\n"),
+            "Nix reported:
+
+- Infinite recursion encountered
+
+## Output
+
+```
+error: infinite recursion encountered, at undefined position
+```"
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        let long = "é".repeat(MAX_OUTPUT_LENGTH);
+        let truncated = truncate(&long, MAX_OUTPUT_LENGTH);
+        assert!(truncated.len() <= MAX_OUTPUT_LENGTH);
+        assert_eq!(truncate("short", MAX_OUTPUT_LENGTH), "short");
+    }
+
+    #[test]
+    fn test_evaluation_summary() {
+        let job = EvaluationJob {
+            repo: Repo {
+                clone_url: "https://github.com/nixos/nixpkgs.git".to_owned(),
+                full_name: "NixOS/nixpkgs".to_owned(),
+                owner: "NixOS".to_owned(),
+                name: "nixpkgs".to_owned(),
+            },
+            pr: Pr {
+                head_sha: "abc123".to_owned(),
+                number: 42,
+                target_branch: Some("master".to_owned()),
+            },
+        };
+        let attr = |package: &str, architecture: &str| PackageArch {
+            package: package.to_owned(),
+            architecture: architecture.to_owned(),
+        };
+
+        let mut report = EvaluationReport::new(&job, "evaluator");
+        assert_eq!(evaluation_summary(&report), "");
+
+        report.checks = vec![
+            CheckOutcome {
+                name: String::from("grahamcofborg-eval-package-list"),
+                success: true,
+            },
+            CheckOutcome {
+                name: String::from("grahamcofborg-eval-nixos"),
+                success: false,
+            },
+        ];
+        report.rebuilds = Some(vec![
+            attr("hello", "x86_64-linux"),
+            attr("hello", "aarch64-linux"),
+            attr("cowsay", "x86_64-linux"),
+        ]);
+        report.added = Some(vec![attr("cowsay", "x86_64-linux")]);
+        report.removed = Some(vec![]);
+        report.stdenv_changes = Some(vec![String::from("x86_64-linux")]);
+
+        assert_eq!(
+            evaluation_summary(&report),
+            "| Check | Result |
+|-|-|
+| grahamcofborg-eval-package-list | Passed |
+| grahamcofborg-eval-nixos | Failed |
+
+| System | Rebuilds |
+|-|-|
+| aarch64-linux | 1 |
+| x86_64-linux | 2 |
+
+1 packages added, 0 packages removed.
+
+The stdenv changes on x86_64-linux."
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use ofborg::checkrun::{human_size, size_change};
+use ofborg::config::ClosureSizeConfig;
+use ofborg::nix::{Nix, PathInfo};
+use ofborg::outpathdiff::{OutPathDiff, PackageArch};
+use std::collections::HashSet;
+use std::slice;
+
+/// How many of the new dependencies of a closure are listed.
+const MAX_NEW_DEPENDENCIES: usize = 5;
+
+/// How the closure of a rebuilt package changed.
+#[derive(Debug, PartialEq)]
+pub struct ClosureChange {
+    pub attr: PackageArch,
+    pub before: u64,
+    pub after: u64,
+    /// The paths of the new closure whose name isn't in the old one,
+    /// largest first.
+    pub new_paths: Vec<PathInfo>,
+}
+
+impl ClosureChange {
+    pub fn compare(attr: PackageArch, before: &[PathInfo], after: &[PathInfo]) -> ClosureChange {
+        // Rebuilt dependencies have new hashes, so only a new name
+        // makes a dependency new.
+        let names: HashSet<&str> = before.iter().map(|info| path_name(&info.path)).collect();
+        let mut new_paths: Vec<PathInfo> = after
+            .iter()
+            .filter(|info| !names.contains(path_name(&info.path)))
+            .cloned()
+            .collect();
+        new_paths.sort_by(|a, b| b.nar_size.cmp(&a.nar_size).then(a.path.cmp(&b.path)));
+
+        ClosureChange {
+            attr,
+            before: before.iter().map(|info| info.nar_size).sum(),
+            after: after.iter().map(|info| info.nar_size).sum(),
+            new_paths,
+        }
+    }
+
+    /// Whether the closure grew by more than both limits.
+    pub fn is_regression(&self, config: &ClosureSizeConfig) -> bool {
+        if self.after <= self.before {
+            return false;
+        }
+
+        let increase = self.after - self.before;
+        let percent = increase as f64 * 100.0 / self.before.max(1) as f64;
+        increase > config.max_increase_mb() * 1024 * 1024 && percent > config.max_increase_percent()
+    }
+
+    /// A list entry with the sizes and the largest new dependencies.
+    pub fn markdown(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "- `{}` on {}: {}, {} from {}",
+            self.attr.package,
+            self.attr.architecture,
+            human_size(self.after),
+            size_change(self.before, self.after),
+            human_size(self.before)
+        )];
+
+        for info in self.new_paths.iter().take(MAX_NEW_DEPENDENCIES) {
+            lines.push(format!(
+                "  - `{}`: {}",
+                path_name(&info.path),
+                human_size(info.nar_size)
+            ));
+        }
+        if self.new_paths.len() > MAX_NEW_DEPENDENCIES {
+            lines.push(format!(
+                "  - and {} more new dependencies",
+                self.new_paths.len() - MAX_NEW_DEPENDENCIES
+            ));
+        }
+
+        lines
+    }
+}
+
+/// Compares the closures of up to `max_attrs` rebuilt packages whose
+/// out paths before and after are both in the store. Evaluating
+/// doesn't build anything, so these were built or substituted by
+/// something else, like a builder sharing the store.
+pub fn compare(nix: &Nix, diff: &OutPathDiff, max_attrs: usize) -> Vec<ClosureChange> {
+    let (original, current) = match (&diff.original, &diff.current) {
+        (&Some((ref original, _)), &Some((ref current, _))) => (original, current),
+        _ => return vec![],
+    };
+
+    let mut rebuilds = diff.calculate_rebuild().unwrap_or_default();
+    rebuilds.sort_by(|a, b| {
+        a.package
+            .cmp(&b.package)
+            .then(a.architecture.cmp(&b.architecture))
+    });
+
+    let rebuilds: Vec<(PackageArch, &String, &String)> = rebuilds
+        .into_iter()
+        .filter_map(|attr| {
+            let before = original.get(&attr)?;
+            let after = current.get(&attr)?;
+            Some((attr, before, after))
+        })
+        .collect();
+
+    // Most rebuilt packages aren't in the store, so those which are
+    // are found first, and only then capped.
+    let paths: Vec<String> = rebuilds
+        .iter()
+        .flat_map(|&(_, before, after)| vec![before.clone(), after.clone()])
+        .collect();
+    let valid = match nix.valid_paths(&paths) {
+        Ok(valid) => valid,
+        Err(e) => {
+            warn!("Failed to check which out paths are in the store: {}", e);
+            return vec![];
+        }
+    };
+
+    rebuilds
+        .into_iter()
+        .filter(|&(_, before, after)| valid.contains(before) && valid.contains(after))
+        .take(max_attrs)
+        .filter_map(|(attr, before, after)| {
+            let after = nix.closure(slice::from_ref(after)).ok()?;
+            let before = nix.closure(slice::from_ref(before)).ok()?;
+            Some(ClosureChange::compare(attr, &before, &after))
+        })
+        .collect()
+}
+
+/// The name of a store path, without its hash.
+fn path_name(path: &str) -> &str {
+    let base = path.rsplit('/').next().unwrap_or(path);
+    match base.find('-') {
+        Some(i) => &base[i + 1..],
+        None => base,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    fn info(path: &str, nar_size: u64) -> PathInfo {
+        PathInfo {
+            path: path.to_owned(),
+            nar_size,
+        }
+    }
+
+    fn hello() -> PackageArch {
+        PackageArch {
+            package: String::from("hello"),
+            architecture: String::from("x86_64-linux"),
+        }
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(path_name("/nix/store/abc123-hello-2.10"), "hello-2.10");
+        assert_eq!(path_name("hello"), "hello");
+    }
+
+    #[test]
+    fn new_dependencies() {
+        let change = ClosureChange::compare(
+            hello(),
+            &[
+                info("/nix/store/aaa-hello-2.10", MIB),
+                info("/nix/store/bbb-glibc-2.33", 30 * MIB),
+            ],
+            &[
+                info("/nix/store/ccc-hello-2.10", MIB),
+                info("/nix/store/ddd-glibc-2.33", 30 * MIB),
+                info("/nix/store/eee-python3-3.9.6", 100 * MIB),
+                info("/nix/store/fff-openssl-1.1.1l", 5 * MIB),
+            ],
+        );
+
+        assert_eq!(change.before, 31 * MIB);
+        assert_eq!(change.after, 136 * MIB);
+        assert_eq!(
+            change.new_paths,
+            vec![
+                info("/nix/store/eee-python3-3.9.6", 100 * MIB),
+                info("/nix/store/fff-openssl-1.1.1l", 5 * MIB),
+            ]
+        );
+        assert_eq!(
+            change.markdown(),
+            vec![
+                "- `hello` on x86_64-linux: 136.0 MiB, +105.0 MiB from 31.0 MiB",
+                "  - `python3-3.9.6`: 100.0 MiB",
+                "  - `openssl-1.1.1l`: 5.0 MiB",
+            ]
+        );
+    }
+
+    #[test]
+    fn regressions() {
+        let config = ClosureSizeConfig::default();
+        let change = |before: u64, after: u64| ClosureChange {
+            attr: hello(),
+            before,
+            after,
+            new_paths: vec![],
+        };
+
+        assert!(change(100 * MIB, 200 * MIB).is_regression(&config));
+        assert!(!change(200 * MIB, 100 * MIB).is_regression(&config));
+        // Large, but not relative to the closure.
+        assert!(!change(2000 * MIB, 2060 * MIB).is_regression(&config));
+        // Relatively large, but small.
+        assert!(!change(MIB, 10 * MIB).is_regression(&config));
+    }
+}
@@ -1,13 +1,9 @@
 use nom::types::CompleteStr;
+use ofborg::partition_result;
+use std::fmt;
 
 pub fn parse(text: &str) -> Option<Vec<Instruction>> {
-    let instructions: Vec<Instruction> = text
-        .lines()
-        .flat_map(|s| match parse_line(s) {
-            Some(instructions) => instructions.into_iter(),
-            None => Vec::new().into_iter(),
-        })
-        .collect();
+    let (instructions, _) = parse_with_errors(text);
 
     if instructions.is_empty() {
         None
@@ -16,39 +12,45 @@ pub fn parse(text: &str) -> Option<Vec<Instruction>> {
     }
 }
 
-named!(normal_token(CompleteStr) -> CompleteStr,
-    verify!(take_while1!(|c: char| c.is_ascii_graphic()),
-            |s: CompleteStr| !s.0.eq_ignore_ascii_case("@grahamcofborg"))
+/// Parse every line of a comment, returning the instructions which
+/// were understood alongside the commands which were not.
+pub fn parse_with_errors(text: &str) -> (Vec<Instruction>, Vec<ParseError>) {
+    let results: Vec<Result<Instruction, ParseError>> = text
+        .lines()
+        .flat_map(|s| match parse_line(s) {
+            Some(results) => results.into_iter(),
+            None => Vec::new().into_iter(),
+        })
+        .collect();
+
+    partition_result(results)
+}
+
+fn is_mention(word: &str) -> bool {
+    word.eq_ignore_ascii_case("@grahamcofborg") || word.eq_ignore_ascii_case("@ofborg")
+}
+
+named!(mention(CompleteStr) -> CompleteStr,
+    verify!(take_till1!(char::is_whitespace), |s: CompleteStr| is_mention(s.0))
+);
+named!(word(CompleteStr) -> CompleteStr,
+    verify!(take_till1!(char::is_whitespace), |s: CompleteStr| !is_mention(s.0))
 );
-named!(parse_line_impl(CompleteStr) -> Option<Vec<Instruction>>, alt!(
+named!(command(CompleteStr) -> Result<Instruction, ParseError>, do_parse!(
+    mention >>
+    words: many0!(ws!(word)) >>
+    (parse_command(&words.iter().map(|w| w.0).collect::<Vec<&str>>()))
+));
+named!(parse_line_impl(CompleteStr) -> Option<Vec<Result<Instruction, ParseError>>>, alt!(
     do_parse!(
-        res: ws!(many1!(ws!(preceded!(
-            alt!(tag_no_case!("@grahamcofborg") | tag_no_case!("@ofborg")),
-            alt!(
-                ws!(do_parse!(
-                    tag!("build") >>
-                    pkgs: ws!(many1!(map!(normal_token, |s| s.0.to_owned()))) >>
-                    (Some(Instruction::Build(Subset::Nixpkgs, pkgs)))
-                )) |
-                ws!(do_parse!(
-                    tag!("test") >>
-                    tests: ws!(many1!(map!(normal_token, |s| format!("tests.{}", s.0)))) >>
-                    (Some(Instruction::Build(Subset::NixOS, tests)))
-                )) |
-                value!(Some(Instruction::Eval), tag!("eval")) |
-                // TODO: Currently keeping previous behaviour of ignoring unknown commands. Maybe
-                // it would be better to return an error so that the caller would know one of the
-                // commands couldn't be handled?
-                value!(None, many_till!(take!(1), tag_no_case!("@grahamcofborg")))
-            )
-        )))) >>
+        res: ws!(many1!(ws!(command))) >>
         eof!() >>
-        (Some(res.into_iter().filter_map(|x| x).collect()))
+        (Some(res))
     ) |
     value!(None)
 ));
 
-pub fn parse_line(text: &str) -> Option<Vec<Instruction>> {
+pub fn parse_line(text: &str) -> Option<Vec<Result<Instruction, ParseError>>> {
     match parse_line_impl(CompleteStr(text)) {
         Ok((_, res)) => res,
         Err(e) => {
@@ -59,10 +61,109 @@ pub fn parse_line(text: &str) -> Option<Vec<Instruction>> {
     }
 }
 
+fn parse_command(words: &[&str]) -> Result<Instruction, ParseError> {
+    let (verb, args) = match words.split_first() {
+        Some((verb, args)) => (*verb, args),
+        None => return Err(ParseError::MissingCommand),
+    };
+
+    match verb {
+        "build" => {
+            let (attrs, systems) = parse_build_args(verb, args)?;
+            Ok(Instruction::Build(
+                Subset::Nixpkgs,
+                attrs.into_iter().map(|attr| attr.to_owned()).collect(),
+                systems,
+            ))
+        }
+        "test" => {
+            let (tests, systems) = parse_build_args(verb, args)?;
+            Ok(Instruction::Build(
+                Subset::NixOS,
+                tests
+                    .into_iter()
+                    .map(|test| format!("tests.{}", test))
+                    .collect(),
+                systems,
+            ))
+        }
+        "eval" => Ok(Instruction::Eval),
+        "cancel" => Ok(Instruction::Cancel),
+        _ => Err(ParseError::UnknownCommand(verb.to_owned())),
+    }
+}
+
+/// Split the arguments of `build` and `test` in to the attributes and
+/// the systems requested with `--system`, if any.
+fn parse_build_args<'a>(
+    verb: &str,
+    args: &[&'a str],
+) -> Result<(Vec<&'a str>, Option<Vec<String>>), ParseError> {
+    let mut attrs: Vec<&str> = vec![];
+    let mut systems: Vec<&str> = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if *arg == "--system" {
+            match args.next() {
+                Some(system) => systems.push(system),
+                None => {
+                    return Err(ParseError::MissingOptionValue(
+                        verb.to_owned(),
+                        (*arg).to_owned(),
+                    ));
+                }
+            }
+        } else if arg.starts_with("--") {
+            // Convert "--system=foo" in to "foo"
+            let parts: Vec<&str> = arg.splitn(2, '=').collect();
+            if parts.len() == 2 && parts[0] == "--system" {
+                systems.push(parts[1]);
+            } else {
+                return Err(ParseError::UnknownOption(
+                    verb.to_owned(),
+                    (*arg).to_owned(),
+                ));
+            }
+        } else {
+            attrs.push(arg);
+        }
+    }
+
+    let attrs = parse_attrs(verb, &attrs)?;
+    if systems.is_empty() {
+        Ok((attrs, None))
+    } else {
+        let systems = parse_attrs(verb, &systems)?;
+        Ok((
+            attrs,
+            Some(systems.into_iter().map(|s| s.to_owned()).collect()),
+        ))
+    }
+}
+
+fn parse_attrs<'a>(verb: &str, args: &[&'a str]) -> Result<Vec<&'a str>, ParseError> {
+    if args.is_empty() {
+        return Err(ParseError::EmptyAttrList(verb.to_owned()));
+    }
+
+    if let Some(bad) = args
+        .iter()
+        .find(|arg| !arg.chars().all(|c| c.is_ascii_graphic()))
+    {
+        return Err(ParseError::BadToken(verb.to_owned(), (*bad).to_owned()));
+    }
+
+    Ok(args.to_vec())
+}
+
 #[derive(PartialEq, Debug)]
 pub enum Instruction {
-    Build(Subset, Vec<String>),
+    /// Build the attributes, optionally on only the listed systems.
+    Build(Subset, Vec<String>, Option<Vec<String>>),
     Eval,
+    /// Stop the builds which are running for the PR.
+    Cancel,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -71,6 +172,50 @@ pub enum Subset {
     NixOS,
 }
 
+#[derive(PartialEq, Debug)]
+pub enum ParseError {
+    /// The bot was mentioned, but no command followed.
+    MissingCommand,
+    UnknownCommand(String),
+    /// The command requires at least one attribute.
+    EmptyAttrList(String),
+    /// The command was given an argument which cannot be an attribute.
+    BadToken(String, String),
+    UnknownOption(String, String),
+    /// The option requires a value, but none was given.
+    MissingOptionValue(String, String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::MissingCommand => write!(f, "No command was given."),
+            ParseError::UnknownCommand(ref verb) => write!(f, "`{}` is not a known command.", verb),
+            ParseError::EmptyAttrList(ref verb) => {
+                write!(f, "`{}` needs at least one attribute.", verb)
+            }
+            ParseError::BadToken(ref verb, ref token) => {
+                write!(f, "`{}` is not a valid attribute for `{}`.", token, verb)
+            }
+            ParseError::UnknownOption(ref verb, ref option) => {
+                write!(f, "`{}` is not a known option for `{}`.", option, verb)
+            }
+            ParseError::MissingOptionValue(ref verb, ref option) => {
+                write!(f, "`{}` for `{}` needs a value.", option, verb)
+            }
+        }
+    }
+}
+
+/// The commands understood by the parser, for replying to comments
+/// which could not be parsed.
+pub const USAGE: &[&str] = &[
+    "`@ofborg build [--system <system>] <attr> [<attr> ...]`",
+    "`@ofborg test [--system <system>] <test> [<test> ...]`",
+    "`@ofborg eval`",
+    "`@ofborg cancel`",
+];
+
 #[cfg(test)]
 mod tests {
 
@@ -107,12 +252,17 @@ mod tests {
         assert_eq!(Some(vec![Instruction::Eval]), parse("@grahamcofborg eval"));
     }
 
+    #[test]
+    fn cancel_comment() {
+        assert_eq!(Some(vec![Instruction::Cancel]), parse("@ofborg cancel"));
+    }
+
     #[test]
     fn eval_and_build_comment() {
         assert_eq!(
             Some(vec![
                 Instruction::Eval,
-                Instruction::Build(Subset::Nixpkgs, vec![String::from("foo")]),
+                Instruction::Build(Subset::Nixpkgs, vec![String::from("foo")], None),
             ]),
             parse("@grahamcofborg eval @grahamcofborg build foo")
         );
@@ -122,9 +272,9 @@ mod tests {
     fn build_and_eval_and_build_comment() {
         assert_eq!(
             Some(vec![
-                Instruction::Build(Subset::Nixpkgs, vec![String::from("bar")]),
+                Instruction::Build(Subset::Nixpkgs, vec![String::from("bar")], None),
                 Instruction::Eval,
-                Instruction::Build(Subset::Nixpkgs, vec![String::from("foo")]),
+                Instruction::Build(Subset::Nixpkgs, vec![String::from("foo")], None),
             ]),
             parse(
                 "
@@ -139,9 +289,9 @@ mod tests {
     fn complex_comment_with_paragraphs() {
         assert_eq!(
             Some(vec![
-                Instruction::Build(Subset::Nixpkgs, vec![String::from("bar")]),
+                Instruction::Build(Subset::Nixpkgs, vec![String::from("bar")], None),
                 Instruction::Eval,
-                Instruction::Build(Subset::Nixpkgs, vec![String::from("foo")]),
+                Instruction::Build(Subset::Nixpkgs, vec![String::from("foo")], None),
             ]),
             parse(
                 "
@@ -163,7 +313,7 @@ Also, just in case, let's try foo
     fn build_and_eval_comment() {
         assert_eq!(
             Some(vec![
-                Instruction::Build(Subset::Nixpkgs, vec![String::from("foo")]),
+                Instruction::Build(Subset::Nixpkgs, vec![String::from("foo")], None),
                 Instruction::Eval,
             ]),
             parse("@grahamcofborg build foo @grahamcofborg eval")
@@ -175,7 +325,8 @@ Also, just in case, let's try foo
         assert_eq!(
             Some(vec![Instruction::Build(
                 Subset::Nixpkgs,
-                vec![String::from("foo"), String::from("bar")]
+                vec![String::from("foo"), String::from("bar")],
+                None
             ),]),
             parse(
                 "@OfBorg build foo bar
@@ -194,7 +345,8 @@ baz",
                     String::from("tests.foo"),
                     String::from("tests.bar"),
                     String::from("tests.baz"),
-                ]
+                ],
+                None
             ),]),
             parse("@GrahamCOfBorg test foo bar baz")
         );
@@ -209,7 +361,8 @@ baz",
                     String::from("foo"),
                     String::from("bar"),
                     String::from("baz"),
-                ]
+                ],
+                None
             ),]),
             parse("@OfBorg build foo bar baz")
         );
@@ -224,7 +377,8 @@ baz",
                     String::from("foo"),
                     String::from("bar"),
                     String::from("baz"),
-                ]
+                ],
+                None
             ),]),
             parse("@grahamcofborg build foo bar baz")
         );
@@ -239,10 +393,126 @@ baz",
                     String::from("foo"),
                     String::from("bar"),
                     String::from("baz.Baz"),
-                ]
+                ],
+                None
             ),]),
             parse("@ofborg build foo bar baz.Baz")
         );
     }
 
+    #[test]
+    fn unknown_command() {
+        assert_eq!(
+            (
+                vec![],
+                vec![ParseError::UnknownCommand(String::from("biuld"))]
+            ),
+            parse_with_errors("@ofborg biuld foo")
+        );
+    }
+
+    #[test]
+    fn unknown_command_keeps_valid_instructions() {
+        assert_eq!(
+            (
+                vec![
+                    Instruction::Eval,
+                    Instruction::Build(Subset::Nixpkgs, vec![String::from("foo")], None),
+                ],
+                vec![ParseError::UnknownCommand(String::from("rebuild"))]
+            ),
+            parse_with_errors("@ofborg eval @ofborg rebuild bar @grahamcofborg build foo")
+        );
+    }
+
+    #[test]
+    fn empty_attr_list() {
+        assert_eq!(
+            (
+                vec![Instruction::Eval],
+                vec![
+                    ParseError::EmptyAttrList(String::from("build")),
+                    ParseError::EmptyAttrList(String::from("test")),
+                ]
+            ),
+            parse_with_errors(
+                "@ofborg build
+@ofborg test @ofborg eval"
+            )
+        );
+    }
+
+    #[test]
+    fn bad_token() {
+        assert_eq!(
+            (
+                vec![],
+                vec![ParseError::BadToken(
+                    String::from("build"),
+                    String::from("föo")
+                )]
+            ),
+            parse_with_errors("@ofborg build bar föo")
+        );
+    }
+
+    #[test]
+    fn missing_command() {
+        assert_eq!(
+            (vec![], vec![ParseError::MissingCommand]),
+            parse_with_errors("@ofborg")
+        );
+    }
+
+    #[test]
+    fn prose_mentions_are_not_errors() {
+        assert_eq!(
+            (vec![], vec![]),
+            parse_with_errors("thanks @ofborg, that build was quick")
+        );
+    }
+
+    #[test]
+    fn build_comment_with_system() {
+        assert_eq!(
+            Some(vec![Instruction::Build(
+                Subset::Nixpkgs,
+                vec![String::from("foo"), String::from("bar")],
+                Some(vec![String::from("aarch64-linux")])
+            )]),
+            parse("@ofborg build --system aarch64-linux foo bar")
+        );
+    }
+
+    #[test]
+    fn test_comment_with_systems() {
+        assert_eq!(
+            Some(vec![Instruction::Build(
+                Subset::NixOS,
+                vec![String::from("tests.foo")],
+                Some(vec![
+                    String::from("x86_64-linux"),
+                    String::from("aarch64-linux")
+                ])
+            )]),
+            parse("@ofborg test foo --system x86_64-linux --system=aarch64-linux")
+        );
+    }
+
+    #[test]
+    fn build_comment_with_bad_options() {
+        assert_eq!(
+            (
+                vec![],
+                vec![
+                    ParseError::MissingOptionValue(String::from("build"), String::from("--system")),
+                    ParseError::UnknownOption(String::from("test"), String::from("--sytsem")),
+                ]
+            ),
+            parse_with_errors(
+                "@ofborg build foo --system
+@ofborg test --sytsem aarch64-linux foo"
+            )
+        );
+    }
 }
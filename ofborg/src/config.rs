@@ -4,11 +4,18 @@ use hyper::Client;
 use hyper_native_tls::NativeTlsClient;
 use nix::Nix;
 use ofborg::acl;
+use ofborg::artifacts::{ArtifactStore, FilesystemArtifactStore, GistArtifactStore};
+use ofborg::evalchecker::{CheckerPool, EvalCheckSpec};
+use ofborg::membership::{CachedMembership, GithubMembership};
+use ofborg::outpathcache::OutPathCache;
+use ofborg::systems::{self, System};
+use ofborg::tagger::{self, RebuildBucket};
 use serde_json;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
@@ -21,6 +28,43 @@ pub struct Config {
     pub github_app: Option<GithubAppConfig>,
     pub log_storage: Option<LogStorage>,
     pub tag_paths: Option<HashMap<String, Vec<String>>>,
+    /// The systems builds may be scheduled on. Defaults to
+    /// `systems::default_systems()`.
+    pub systems: Option<Vec<System>>,
+    /// Who may use ofborg on each repository of `runner.repos`.
+    /// Defaults to the policy implied by `runner.trusted_users` and
+    /// `runner.known_users`.
+    pub acl: Option<acl::Policy>,
+    /// How the attributes built automatically after an evaluation are
+    /// picked. Defaults to the commit message prefixes.
+    pub auto_build: Option<AutoBuildConfig>,
+    /// The `10.rebuild-<platform>` labels applied for the number of
+    /// rebuilds. Defaults to `tagger::default_rebuild_buckets()`.
+    pub rebuild_buckets: Option<Vec<RebuildBucket>>,
+    /// When the evaluation statistics of a PR count as a performance
+    /// regression. By default, they never do.
+    pub performance: Option<PerformanceConfig>,
+    /// When the closure of a rebuilt package grew too much. Defaults
+    /// to more than 50 MiB and 10%.
+    pub closure_size: Option<ClosureSizeConfig>,
+    /// How many evaluation checks run at once. Defaults to one at a
+    /// time.
+    pub evaluation: Option<EvaluationConfig>,
+    /// The evaluation checks of each repository, by full name. The
+    /// checks declared on a repository's target branch take
+    /// precedence. Defaults to ofborg's checks for nixpkgs and none
+    /// for other repositories.
+    pub eval_checks: Option<HashMap<String, Vec<EvalCheckSpec>>>,
+    /// Where the out paths of evaluated target branch commits are
+    /// kept. Defaults to the `outpath-cache` directory of the checkout
+    /// root.
+    pub outpath_cache: Option<OutPathCacheConfig>,
+    /// Where builders copy the outputs of successful builds. By
+    /// default, they are kept only in the builder's store.
+    pub binary_cache: Option<BinaryCacheConfig>,
+    /// What builders report besides the out paths of the attributes
+    /// and their sizes. By default, nothing.
+    pub builder: Option<BuilderConfig>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -60,6 +104,10 @@ pub struct GithubAppConfig {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LogStorage {
     pub path: String,
+    /// The public URL the log storage is served at. Without it,
+    /// evaluation artifacts are uploaded as gists instead.
+    #[serde(default)]
+    pub url: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -78,6 +126,174 @@ pub struct RunnerConfig {
     pub build_all_jobs: Option<bool>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AutoBuildConfig {
+    pub mode: AutoBuildMode,
+
+    /// The most attributes built automatically. Defaults to 10.
+    #[serde(default)]
+    pub max_attrs: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoBuildMode {
+    /// The attributes named before the `:` in commit titles. Nothing
+    /// is built if there are more than `max_attrs` of them.
+    CommitMessages,
+
+    /// The attributes whose out paths change, the ones rebuilt on the
+    /// most systems first, up to `max_attrs` of them.
+    ChangedAttrs,
+}
+
+impl Default for AutoBuildConfig {
+    fn default() -> AutoBuildConfig {
+        AutoBuildConfig {
+            mode: AutoBuildMode::CommitMessages,
+            max_attrs: None,
+        }
+    }
+}
+
+impl AutoBuildConfig {
+    pub fn max_attrs(&self) -> usize {
+        self.max_attrs.unwrap_or(10)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EvaluationConfig {
+    /// The most evaluation checks run at once. Defaults to 1.
+    #[serde(default)]
+    pub parallelism: Option<usize>,
+
+    /// The memory, in megabytes, the checks running at once may use
+    /// together. Unlimited by default.
+    #[serde(default)]
+    pub memory_budget_mb: Option<u64>,
+
+    /// The memory, in megabytes, each check is expected to use.
+    /// Defaults to 4096.
+    #[serde(default)]
+    pub checker_memory_mb: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PerformanceConfig {
+    /// How many percent each evaluation statistic, named as in the
+    /// performance report, may increase before it is a regression.
+    #[serde(default)]
+    pub thresholds: HashMap<String, f64>,
+
+    /// The label of PRs with a regression. Defaults to
+    /// `performance-regression`.
+    #[serde(default)]
+    pub label: Option<String>,
+
+    /// Conclude the performance check run as "action required"
+    /// instead of as failed when there is a regression.
+    #[serde(default)]
+    pub action_required: bool,
+}
+
+impl PerformanceConfig {
+    pub fn label(&self) -> String {
+        self.label
+            .clone()
+            .unwrap_or_else(|| String::from("performance-regression"))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ClosureSizeConfig {
+    /// How many MiB a closure may grow by. Defaults to 50.
+    #[serde(default)]
+    pub max_increase_mb: Option<u64>,
+
+    /// How many percent a closure may grow by. Defaults to 10.
+    #[serde(default)]
+    pub max_increase_percent: Option<f64>,
+
+    /// How many rebuilt packages are compared. Defaults to 100, and 0
+    /// disables the check.
+    #[serde(default)]
+    pub max_attrs: Option<usize>,
+}
+
+impl ClosureSizeConfig {
+    pub fn max_increase_mb(&self) -> u64 {
+        self.max_increase_mb.unwrap_or(50)
+    }
+
+    pub fn max_increase_percent(&self) -> f64 {
+        self.max_increase_percent.unwrap_or(10.0)
+    }
+
+    pub fn max_attrs(&self) -> usize {
+        self.max_attrs.unwrap_or(100)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OutPathCacheConfig {
+    /// Defaults to `<checkout root>/outpath-cache`.
+    #[serde(default)]
+    pub path: Option<String>,
+
+    /// How many target branch commits are remembered. Defaults to 10,
+    /// and 0 disables the cache.
+    #[serde(default)]
+    pub max_entries: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BuilderConfig {
+    /// Whether the sizes of the attributes which built are compared
+    /// with their sizes on the target branch, which instantiates them
+    /// there too. Defaults to false.
+    #[serde(default)]
+    pub target_sizes: Option<bool>,
+}
+
+impl BuilderConfig {
+    pub fn target_sizes(&self) -> bool {
+        self.target_sizes.unwrap_or(false)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BinaryCacheConfig {
+    /// The store to copy to, like `file:///var/cache/ofborg` or
+    /// `s3://ofborg-cache`.
+    pub url: String,
+
+    /// The key the outputs are signed with.
+    #[serde(default)]
+    pub secret_key_file: Option<String>,
+
+    /// Where reviewers fetch the outputs from, if not `url`.
+    #[serde(default)]
+    pub public_url: Option<String>,
+}
+
+impl BinaryCacheConfig {
+    /// The store URL `nix copy` copies to, which signs the outputs.
+    pub fn store_url(&self) -> String {
+        match self.secret_key_file {
+            Some(ref key) => {
+                let separator = if self.url.contains('?') { '&' } else { '?' };
+                format!("{}{}secret-key={}", self.url, separator, key)
+            }
+            None => self.url.clone(),
+        }
+    }
+
+    pub fn public_url(&self) -> String {
+        self.public_url.clone().unwrap_or_else(|| self.url.clone())
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CheckoutConfig {
     pub root: String,
@@ -89,22 +305,100 @@ impl Config {
     }
 
     pub fn acl(&self) -> acl::ACL {
-        acl::ACL::new(
-            self.runner
-                .repos
-                .clone()
-                .expect("fetching config's runner.repos"),
-            self.runner
-                .trusted_users
-                .clone()
-                .expect("fetching config's runner.trusted_users"),
-            self.runner
-                .known_users
-                .clone()
-                .expect("fetching config's runner.known_users"),
+        let repos = self
+            .runner
+            .repos
+            .clone()
+            .expect("fetching config's runner.repos");
+        let policy = match self.acl {
+            Some(ref policy) => policy.clone(),
+            None => acl::Policy::from_user_lists(
+                self.runner
+                    .trusted_users
+                    .clone()
+                    .expect("fetching config's runner.trusted_users"),
+                self.runner
+                    .known_users
+                    .clone()
+                    .expect("fetching config's runner.known_users"),
+            ),
+        };
+
+        let membership_ttl =
+            Duration::from_secs(policy.github_membership_ttl_seconds.unwrap_or(600));
+        if !policy.uses_github_membership() {
+            acl::ACL::new(repos, policy, self.systems())
+        } else if self.github_app.is_none() {
+            warn!("The ACL refers to GitHub groups, but no github_app is configured");
+            acl::ACL::new(repos, policy, self.systems())
+        } else {
+            acl::ACL::new(repos, policy, self.systems()).with_membership(Box::new(
+                CachedMembership::new(
+                    GithubMembership::new(self.github_app.clone().unwrap()),
+                    membership_ttl,
+                ),
+            ))
+        }
+    }
+
+    pub fn auto_build(&self) -> AutoBuildConfig {
+        self.auto_build.clone().unwrap_or_default()
+    }
+
+    pub fn rebuild_buckets(&self) -> Vec<RebuildBucket> {
+        self.rebuild_buckets
+            .clone()
+            .unwrap_or_else(tagger::default_rebuild_buckets)
+    }
+
+    pub fn performance(&self) -> PerformanceConfig {
+        self.performance.clone().unwrap_or_default()
+    }
+
+    pub fn closure_size(&self) -> ClosureSizeConfig {
+        self.closure_size.clone().unwrap_or_default()
+    }
+
+    pub fn checker_pool(&self) -> CheckerPool {
+        let evaluation = self.evaluation.clone().unwrap_or_default();
+        CheckerPool::new(
+            evaluation.parallelism.unwrap_or(1),
+            evaluation.memory_budget_mb,
+            evaluation.checker_memory_mb.unwrap_or(4096),
         )
     }
 
+    pub fn outpath_cache(&self) -> Option<OutPathCache> {
+        let config = self.outpath_cache.clone().unwrap_or_default();
+        let max_entries = config.max_entries.unwrap_or(10);
+        if max_entries == 0 {
+            return None;
+        }
+
+        let path = match config.path {
+            Some(path) => PathBuf::from(path),
+            None => Path::new(&self.checkout.root).join("outpath-cache"),
+        };
+
+        Some(OutPathCache::new(path, max_entries))
+    }
+
+    /// The configured evaluation checks, by lowercased repository name.
+    pub fn eval_checks(&self) -> HashMap<String, Vec<EvalCheckSpec>> {
+        self.eval_checks
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(repo, checks)| (repo.to_lowercase(), checks))
+            .collect()
+    }
+
+    pub fn systems(&self) -> Vec<System> {
+        self.systems
+            .clone()
+            .unwrap_or_else(systems::default_systems)
+    }
+
     pub fn github(&self) -> Github {
         Github::new(
             "github.com/grahamc/ofborg",
@@ -114,14 +408,22 @@ impl Config {
         )
     }
 
-    pub fn github_app_vendingmachine(&self) -> GithubAppVendingMachine {
-        GithubAppVendingMachine {
-            conf: self.github_app.clone().unwrap(),
-            id_cache: HashMap::new(),
-            client_cache: HashMap::new(),
+    /// Where evaluation artifacts are stored: below the log storage if
+    /// it is served publicly, and as gists otherwise.
+    pub fn artifact_store(&self) -> Box<dyn ArtifactStore> {
+        match self.log_storage {
+            Some(LogStorage {
+                ref path,
+                url: Some(ref url),
+            }) => Box::new(FilesystemArtifactStore::new(PathBuf::from(path), url)),
+            _ => Box::new(GistArtifactStore::new(self.github())),
         }
     }
 
+    pub fn github_app_vendingmachine(&self) -> GithubAppVendingMachine {
+        GithubAppVendingMachine::new(self.github_app.clone().unwrap())
+    }
+
     pub fn nix(&self) -> Nix {
         if self.nix.build_timeout_seconds < 1200 {
             error!(
@@ -171,6 +473,14 @@ pub struct GithubAppVendingMachine {
 }
 
 impl GithubAppVendingMachine {
+    pub fn new(conf: GithubAppConfig) -> GithubAppVendingMachine {
+        GithubAppVendingMachine {
+            conf,
+            id_cache: HashMap::new(),
+            client_cache: HashMap::new(),
+        }
+    }
+
     fn useragent(&self) -> &'static str {
         "github.com/grahamc/ofborg (app)"
     }
@@ -2,9 +2,18 @@ extern crate amqp;
 extern crate env_logger;
 
 use ofborg::nix;
+use serde_json;
+use std::cmp;
 use std::fs::File;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
+#[derive(Clone)]
 pub struct EvalChecker {
     name: String,
     op: nix::Operation,
@@ -36,3 +45,369 @@ impl EvalChecker {
         cli.join(" ")
     }
 }
+
+/// Where a repository can declare its own evaluation checks, relative
+/// to the root of its target branch.
+pub const REPO_CHECKS_FILE: &str = ".ofborg/eval-checks.json";
+
+/// An evaluation check, as declared in the config or in the
+/// repository's `REPO_CHECKS_FILE`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EvalCheckSpec {
+    /// The name of the check, reported as `grahamcofborg-eval-<name>`.
+    pub name: String,
+    pub operation: CheckOperation,
+    pub args: Vec<String>,
+
+    /// The target branches the check runs on. A trailing `*` matches
+    /// any suffix, as in `release-*`. Runs on every branch if unset.
+    #[serde(default)]
+    pub branches: Option<Vec<String>>,
+}
+
+/// The Nix operations evaluation checks may run.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckOperation {
+    Instantiate,
+    QueryPackagesJson,
+}
+
+impl From<CheckOperation> for nix::Operation {
+    fn from(operation: CheckOperation) -> nix::Operation {
+        match operation {
+            CheckOperation::Instantiate => nix::Operation::Instantiate,
+            CheckOperation::QueryPackagesJson => nix::Operation::QueryPackagesJSON,
+        }
+    }
+}
+
+impl EvalCheckSpec {
+    pub fn new(name: &str, operation: CheckOperation, args: &[&str]) -> EvalCheckSpec {
+        EvalCheckSpec {
+            name: name.to_owned(),
+            operation,
+            args: args.iter().map(|arg| (*arg).to_owned()).collect(),
+            branches: None,
+        }
+    }
+
+    pub fn applies_to(&self, branch: &str) -> bool {
+        match self.branches {
+            None => true,
+            Some(ref branches) => branches.iter().any(|pattern| {
+                if pattern.ends_with('*') {
+                    branch.starts_with(pattern.trim_end_matches('*'))
+                } else {
+                    branch == pattern
+                }
+            }),
+        }
+    }
+}
+
+/// The checkers of the checks which apply to the target branch.
+pub fn checkers_for_branch(
+    specs: &[EvalCheckSpec],
+    branch: &str,
+    nix: &nix::Nix,
+) -> Vec<EvalChecker> {
+    specs
+        .iter()
+        .filter(|spec| spec.applies_to(branch))
+        .map(|spec| {
+            EvalChecker::new(
+                &spec.name,
+                spec.operation.into(),
+                spec.args.clone(),
+                nix.clone(),
+            )
+        })
+        .collect()
+}
+
+/// The checks declared in the checkout's `REPO_CHECKS_FILE`, if it has
+/// one.
+pub fn load_repo_checks(checkout: &Path) -> Result<Option<Vec<EvalCheckSpec>>, String> {
+    let path = checkout.join(REPO_CHECKS_FILE);
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {}: {}", REPO_CHECKS_FILE, e)),
+    };
+
+    serde_json::from_reader(file)
+        .map(Some)
+        .map_err(|e| format!("Failed to parse {}: {}", REPO_CHECKS_FILE, e))
+}
+
+/// What happened to a task run by the pool.
+pub enum Progress<R> {
+    Started,
+    Finished(R),
+}
+
+/// Runs evaluation checks concurrently, up to `parallelism` at once
+/// and only as many as fit in the memory budget.
+#[derive(Clone, Debug)]
+pub struct CheckerPool {
+    parallelism: usize,
+    memory_budget_mb: Option<u64>,
+    checker_memory_mb: u64,
+}
+
+impl CheckerPool {
+    pub fn new(
+        parallelism: usize,
+        memory_budget_mb: Option<u64>,
+        checker_memory_mb: u64,
+    ) -> CheckerPool {
+        CheckerPool {
+            parallelism,
+            memory_budget_mb,
+            checker_memory_mb,
+        }
+    }
+
+    /// How many checks may run at once. At least one check runs, even
+    /// if it doesn't fit in the memory budget.
+    pub fn capacity(&self) -> usize {
+        let by_memory = match self.memory_budget_mb {
+            Some(budget) => (budget / cmp::max(self.checker_memory_mb, 1)) as usize,
+            None => self.parallelism,
+        };
+
+        cmp::max(1, cmp::min(self.parallelism, by_memory))
+    }
+
+    /// Run the checks on the checkout at `path`. `progress` is called
+    /// with the index of each check when it starts, and when it
+    /// finishes with its result and how long it took.
+    pub fn run<F>(&self, checkers: Vec<EvalChecker>, path: &Path, progress: F)
+    where
+        F: FnMut(usize, &EvalChecker, Progress<(Result<File, File>, Duration)>),
+    {
+        let path = path.to_path_buf();
+        run_bounded(
+            checkers,
+            self.capacity(),
+            move |checker: &EvalChecker| {
+                let start = Instant::now();
+                let result = checker.execute(&path);
+                (result, start.elapsed())
+            },
+            progress,
+        );
+    }
+}
+
+/// Run `work` on each task on its own thread, at most `capacity` at
+/// once, starting them in order. `progress` is called on the calling
+/// thread.
+fn run_bounded<T, R, W, F>(tasks: Vec<T>, capacity: usize, work: W, mut progress: F)
+where
+    T: Clone + Send + 'static,
+    R: Send + 'static,
+    W: Fn(&T) -> R + Send + Sync + 'static,
+    F: FnMut(usize, &T, Progress<R>),
+{
+    let work = Arc::new(work);
+    let (tx, rx) = mpsc::channel();
+    let mut next = 0;
+    let mut running = 0;
+
+    loop {
+        while running < capacity && next < tasks.len() {
+            let index = next;
+            next += 1;
+            running += 1;
+
+            progress(index, &tasks[index], Progress::Started);
+
+            let task = tasks[index].clone();
+            let work = work.clone();
+            let tx = tx.clone();
+            thread::spawn(move || {
+                // A task which panics is still reported, so we don't
+                // wait for it forever.
+                let result = panic::catch_unwind(AssertUnwindSafe(|| work(&task)));
+                tx.send((index, result.ok()))
+                    .expect("the pool to wait for its tasks");
+            });
+        }
+
+        if running == 0 {
+            break;
+        }
+
+        let (index, result) = rx.recv().expect("a running task to report back");
+        running -= 1;
+        match result {
+            Some(result) => progress(index, &tasks[index], Progress::Finished(result)),
+            None => warn!("Task {} of the pool panicked", index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ofborg::test_scratch::TestScratch;
+    use std::fs;
+    use std::io::Write;
+    use std::sync::Mutex;
+
+    #[test]
+    fn branch_patterns() {
+        let mut spec = EvalCheckSpec::new("darwin", CheckOperation::Instantiate, &[]);
+        assert!(spec.applies_to("staging"));
+
+        spec.branches = Some(vec![String::from("master"), String::from("release-*")]);
+        assert!(spec.applies_to("master"));
+        assert!(spec.applies_to("release-19.03"));
+        assert!(!spec.applies_to("staging"));
+        assert!(!spec.applies_to("master-backport"));
+    }
+
+    #[test]
+    fn checkers_for_staging() {
+        let nix = nix::Nix::new("x86_64-linux".to_owned(), "daemon".to_owned(), 1800, None);
+        let mut darwin =
+            EvalCheckSpec::new("darwin", CheckOperation::Instantiate, &["-A", "darwin"]);
+        darwin.branches = Some(vec![String::from("master")]);
+        let specs = vec![
+            EvalCheckSpec::new(
+                "package-list",
+                CheckOperation::QueryPackagesJson,
+                &["--file", "."],
+            ),
+            darwin,
+        ];
+
+        let names = |branch| {
+            checkers_for_branch(&specs, branch, &nix)
+                .iter()
+                .map(|checker| checker.name())
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(
+            names("master"),
+            vec![
+                "grahamcofborg-eval-package-list",
+                "grahamcofborg-eval-darwin"
+            ]
+        );
+        assert_eq!(names("staging"), vec!["grahamcofborg-eval-package-list"]);
+        assert_eq!(
+            checkers_for_branch(&specs, "master", &nix)[1].cli_cmd(),
+            "nix-instantiate -A darwin"
+        );
+    }
+
+    #[test]
+    fn repo_checks() {
+        let scratch = TestScratch::new_dir("evalchecker-repo-checks");
+        assert_eq!(load_repo_checks(&scratch.path()), Ok(None));
+
+        fs::create_dir_all(scratch.path().join(".ofborg")).unwrap();
+        File::create(scratch.path().join(REPO_CHECKS_FILE))
+            .unwrap()
+            .write_all(
+                br#"[
+                    {
+                        "name": "nixos",
+                        "operation": "instantiate",
+                        "args": ["./nixos/release.nix", "-A", "tested"],
+                        "branches": ["master"]
+                    }
+                ]"#,
+            )
+            .unwrap();
+
+        let mut nixos = EvalCheckSpec::new(
+            "nixos",
+            CheckOperation::Instantiate,
+            &["./nixos/release.nix", "-A", "tested"],
+        );
+        nixos.branches = Some(vec![String::from("master")]);
+        assert_eq!(load_repo_checks(&scratch.path()), Ok(Some(vec![nixos])));
+
+        File::create(scratch.path().join(REPO_CHECKS_FILE))
+            .unwrap()
+            .write_all(br#"[{"name": "build", "operation": "build", "args": []}]"#)
+            .unwrap();
+        assert!(load_repo_checks(&scratch.path()).is_err());
+    }
+
+    #[test]
+    fn capacity() {
+        assert_eq!(CheckerPool::new(1, None, 4096).capacity(), 1);
+        assert_eq!(CheckerPool::new(4, None, 4096).capacity(), 4);
+        assert_eq!(CheckerPool::new(4, Some(8192), 4096).capacity(), 2);
+        assert_eq!(CheckerPool::new(4, Some(1024), 4096).capacity(), 1);
+        assert_eq!(CheckerPool::new(0, None, 4096).capacity(), 1);
+    }
+
+    #[test]
+    fn bounded_concurrency() {
+        // How many tasks are running, and the most which ran at once.
+        let counts = Arc::new(Mutex::new((0, 0)));
+        let work_counts = counts.clone();
+        let mut started = vec![];
+        let mut finished = vec![];
+
+        run_bounded(
+            (0..8).collect::<Vec<u64>>(),
+            3,
+            move |task: &u64| {
+                {
+                    let mut counts = work_counts.lock().unwrap();
+                    counts.0 += 1;
+                    counts.1 = cmp::max(counts.0, counts.1);
+                }
+                thread::sleep(Duration::from_millis(20 + (task % 3) * 10));
+                work_counts.lock().unwrap().0 -= 1;
+                task * 2
+            },
+            |index, task, progress| match progress {
+                Progress::Started => started.push(index),
+                Progress::Finished(result) => {
+                    assert_eq!(result, task * 2);
+                    finished.push(index);
+                }
+            },
+        );
+
+        assert_eq!(started, (0..8).collect::<Vec<usize>>());
+        finished.sort();
+        assert_eq!(finished, (0..8).collect::<Vec<usize>>());
+        let most = counts.lock().unwrap().1;
+        assert!(most <= 3);
+        assert!(most > 1);
+    }
+
+    #[test]
+    fn panicking_task() {
+        let mut finished = vec![];
+
+        run_bounded(
+            vec![1, 2, 3],
+            2,
+            |task: &u32| {
+                if *task == 2 {
+                    panic!("task 2 fails");
+                }
+                *task
+            },
+            |_, _, progress| {
+                if let Progress::Finished(result) = progress {
+                    finished.push(result);
+                }
+            },
+        );
+
+        finished.sort();
+        assert_eq!(finished, vec![1, 3]);
+    }
+}
@@ -0,0 +1,92 @@
+use lru_cache::LruCache;
+use ofborg::message::{Pr, Repo};
+use std::sync::{Arc, Mutex};
+
+// Only PRs which are being worked on need to be remembered, so this
+// is plenty.
+const REMEMBERED_PRS: usize = 10_000;
+
+/// The newest head commit seen for each PR, shared between a worker
+/// and the consumer of `HeadUpdate` messages.
+#[derive(Clone)]
+pub struct LatestHeads {
+    heads: Arc<Mutex<LruCache<(String, u64), String>>>,
+}
+
+impl Default for LatestHeads {
+    fn default() -> LatestHeads {
+        LatestHeads::new()
+    }
+}
+
+impl LatestHeads {
+    pub fn new() -> LatestHeads {
+        LatestHeads {
+            heads: Arc::new(Mutex::new(LruCache::new(REMEMBERED_PRS))),
+        }
+    }
+
+    pub fn update(&self, repo: &Repo, pr: &Pr) {
+        self.heads
+            .lock()
+            .unwrap()
+            .insert(key(repo, pr), pr.head_sha.clone());
+    }
+
+    pub fn latest(&self, repo: &Repo, pr: &Pr) -> Option<String> {
+        self.heads.lock().unwrap().get_mut(&key(repo, pr)).cloned()
+    }
+
+    /// True if a newer commit than `pr.head_sha` has been pushed to
+    /// the PR. PRs we have never heard about are not superseded.
+    pub fn is_superseded(&self, repo: &Repo, pr: &Pr) -> bool {
+        match self.latest(repo, pr) {
+            Some(sha) => sha != pr.head_sha,
+            None => false,
+        }
+    }
+}
+
+fn key(repo: &Repo, pr: &Pr) -> (String, u64) {
+    (repo.full_name.to_lowercase(), pr.number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo() -> Repo {
+        Repo {
+            clone_url: "https://github.com/nixos/nixpkgs.git".to_owned(),
+            full_name: "NixOS/nixpkgs".to_owned(),
+            owner: "NixOS".to_owned(),
+            name: "nixpkgs".to_owned(),
+        }
+    }
+
+    fn pr(number: u64, head_sha: &str) -> Pr {
+        Pr {
+            head_sha: head_sha.to_owned(),
+            number,
+            target_branch: Some("master".to_owned()),
+        }
+    }
+
+    #[test]
+    fn unknown_pr_is_not_superseded() {
+        let heads = LatestHeads::new();
+        assert!(!heads.is_superseded(&repo(), &pr(1, "abc123")));
+    }
+
+    #[test]
+    fn newer_head_supersedes() {
+        let heads = LatestHeads::new();
+        heads.update(&repo(), &pr(1, "abc123"));
+        assert!(!heads.is_superseded(&repo(), &pr(1, "abc123")));
+
+        heads.update(&repo(), &pr(1, "def456"));
+        assert!(heads.is_superseded(&repo(), &pr(1, "abc123")));
+        assert!(!heads.is_superseded(&repo(), &pr(1, "def456")));
+        assert!(!heads.is_superseded(&repo(), &pr(2, "abc123")));
+    }
+}
@@ -34,24 +34,32 @@ extern crate uuid;
 use std::env;
 
 pub mod acl;
+pub mod artifacts;
 pub mod asynccmd;
 pub mod checkout;
+pub mod checkrun;
 pub mod clone;
+pub mod closuresize;
 pub mod commentparser;
-pub mod commitstatus;
 pub mod config;
 pub mod easyamqp;
 pub mod evalchecker;
 pub mod files;
 pub mod ghevent;
+pub mod latestheads;
 pub mod locks;
 pub mod maintainers;
+pub mod manifest;
+pub mod membership;
 pub mod message;
 pub mod nix;
 pub mod nixenv;
 pub mod nixstats;
 pub mod notifyworker;
+pub mod outpathcache;
 pub mod outpathdiff;
+pub mod quota;
+pub mod rebuildsummary;
 pub mod stats;
 pub mod systems;
 pub mod tagger;
@@ -62,21 +70,29 @@ pub mod writetoline;
 
 pub mod ofborg {
     pub use acl;
+    pub use artifacts;
     pub use asynccmd;
     pub use checkout;
+    pub use checkrun;
     pub use clone;
+    pub use closuresize;
     pub use commentparser;
-    pub use commitstatus;
     pub use config;
     pub use easyamqp;
     pub use evalchecker;
     pub use files;
     pub use ghevent;
+    pub use latestheads;
     pub use locks;
+    pub use manifest;
+    pub use membership;
     pub use message;
     pub use nix;
     pub use notifyworker;
+    pub use outpathcache;
     pub use outpathdiff;
+    pub use quota;
+    pub use rebuildsummary;
     pub use stats;
     pub use systems;
     pub use tagger;
@@ -1,5 +1,5 @@
 use ofborg::nix::Nix;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::Write;
 use std::path::Path;
 use tempfile::NamedTempFile;
@@ -83,6 +83,18 @@ impl ImpactedMaintainers {
             .collect()
     }
 
+    /// The names of the impacted packages of each maintainer, sorted.
+    pub fn packages_by_maintainer(&self) -> BTreeMap<String, Vec<String>> {
+        self.0
+            .iter()
+            .map(|(maintainer, packages)| {
+                let mut packages: Vec<String> = packages.iter().map(|pkg| pkg.0.clone()).collect();
+                packages.sort();
+                (maintainer.0.clone(), packages)
+            })
+            .collect()
+    }
+
     pub fn maintainers_by_package(&self) -> MaintainersByPackage {
         let mut bypkg = MaintainersByPackage(HashMap::new());
 
@@ -0,0 +1,191 @@
+use ofborg::commentparser::Subset;
+use ofborg::evalchecker::EvalChecker;
+use ofborg::message::buildjob::BuildJob;
+use ofborg::message::evaluationjob::EvaluationJob;
+use ofborg::nix;
+use serde_json;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use uuid::Uuid;
+
+/// Where a repository which isn't nixpkgs describes what ofborg should
+/// do with its PRs, relative to the root of the PR's checkout.
+pub const MANIFEST_FILE: &str = ".ofborg/manifest.json";
+
+/// What to evaluate, build and label for a PR to a repository which
+/// isn't nixpkgs.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Manifest {
+    /// Expressions which must instantiate.
+    #[serde(default)]
+    pub instantiate: Vec<Instantiate>,
+
+    /// The attributes of `./default.nix` to build, by system.
+    #[serde(default)]
+    pub build: BTreeMap<String, Vec<String>>,
+
+    /// The labels to add when the PR changes paths containing any of
+    /// the listed strings, like the `tag_paths` of the config.
+    #[serde(default)]
+    pub labels: HashMap<String, Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Instantiate {
+    /// The name of the check, reported as `grahamcofborg-eval-<name>`.
+    pub name: String,
+    /// The file to instantiate, relative to the root of the checkout.
+    pub file: String,
+    /// The attributes of the file to instantiate, or all of it if
+    /// there are none.
+    #[serde(default)]
+    pub attrs: Vec<String>,
+}
+
+impl Manifest {
+    /// The manifest of the checkout, if it has one.
+    pub fn load(checkout: &Path) -> Result<Option<Manifest>, String> {
+        let file = match File::open(checkout.join(MANIFEST_FILE)) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read {}: {}", MANIFEST_FILE, e)),
+        };
+
+        serde_json::from_reader(file)
+            .map(Some)
+            .map_err(|e| format!("Failed to parse {}: {}", MANIFEST_FILE, e))
+    }
+
+    pub fn checkers(&self, nix: &nix::Nix) -> Vec<EvalChecker> {
+        self.instantiate
+            .iter()
+            .map(|instantiate| {
+                let mut args = vec![instantiate.file.clone()];
+                for attr in &instantiate.attrs {
+                    args.push(String::from("-A"));
+                    args.push(attr.clone());
+                }
+
+                EvalChecker::new(
+                    &instantiate.name,
+                    nix::Operation::Instantiate,
+                    args,
+                    nix.clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// One build job for each system with attributes to build.
+    pub fn build_jobs(&self, job: &EvaluationJob) -> Vec<BuildJob> {
+        self.build
+            .iter()
+            .filter(|&(_, attrs)| !attrs.is_empty())
+            .map(|(system, attrs)| {
+                let mut build = BuildJob::new(
+                    job.repo.clone(),
+                    job.pr.clone(),
+                    Subset::Nixpkgs,
+                    attrs.clone(),
+                    None,
+                    None,
+                    format!("{}", Uuid::new_v4()),
+                );
+                build.systems = Some(vec![system.clone()]);
+                build
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ofborg::message::{Pr, Repo};
+    use ofborg::test_scratch::TestScratch;
+    use std::fs;
+    use std::io::Write;
+
+    fn write_manifest(scratch: &TestScratch, contents: &[u8]) {
+        fs::create_dir_all(scratch.path().join(".ofborg")).unwrap();
+        File::create(scratch.path().join(MANIFEST_FILE))
+            .unwrap()
+            .write_all(contents)
+            .unwrap();
+    }
+
+    fn nix() -> nix::Nix {
+        nix::Nix::new("x86_64-linux".to_owned(), "daemon".to_owned(), 1800, None)
+    }
+
+    #[test]
+    fn missing_manifest() {
+        let scratch = TestScratch::new_dir("manifest-missing");
+        assert_eq!(Manifest::load(&scratch.path()), Ok(None));
+    }
+
+    #[test]
+    fn invalid_manifest() {
+        let scratch = TestScratch::new_dir("manifest-invalid");
+        write_manifest(&scratch, br#"{"build": ["hello"]}"#);
+        assert!(Manifest::load(&scratch.path()).is_err());
+    }
+
+    #[test]
+    fn full_manifest() {
+        let scratch = TestScratch::new_dir("manifest-full");
+        write_manifest(
+            &scratch,
+            br#"{
+                "instantiate": [
+                    {"name": "tests", "file": "./release.nix", "attrs": ["tests", "docs"]},
+                    {"name": "default", "file": "./default.nix"}
+                ],
+                "build": {
+                    "x86_64-linux": ["hello", "tests.simple"],
+                    "aarch64-linux": ["hello"],
+                    "x86_64-darwin": []
+                },
+                "labels": {"documentation": ["doc/"]}
+            }"#,
+        );
+
+        let manifest = Manifest::load(&scratch.path()).unwrap().unwrap();
+
+        let checkers = manifest.checkers(&nix());
+        assert_eq!(checkers.len(), 2);
+        assert_eq!(checkers[0].name(), "grahamcofborg-eval-tests");
+        assert_eq!(
+            checkers[0].cli_cmd(),
+            "nix-instantiate ./release.nix -A tests -A docs"
+        );
+        assert_eq!(checkers[1].cli_cmd(), "nix-instantiate ./default.nix");
+
+        let job = EvaluationJob {
+            repo: Repo {
+                clone_url: "https://github.com/example/project.git".to_owned(),
+                full_name: "example/project".to_owned(),
+                owner: "example".to_owned(),
+                name: "project".to_owned(),
+            },
+            pr: Pr {
+                head_sha: "abc123".to_owned(),
+                number: 7,
+                target_branch: Some("master".to_owned()),
+            },
+        };
+        let builds = manifest.build_jobs(&job);
+        assert_eq!(builds.len(), 2);
+        assert_eq!(builds[0].attrs, vec!["hello"]);
+        assert_eq!(builds[0].systems, Some(vec![String::from("aarch64-linux")]));
+        assert_eq!(builds[1].attrs, vec!["hello", "tests.simple"]);
+        assert_eq!(builds[1].systems, Some(vec![String::from("x86_64-linux")]));
+
+        assert_eq!(
+            manifest.labels.get("documentation"),
+            Some(&vec![String::from("doc/")])
+        );
+    }
+}
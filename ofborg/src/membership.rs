@@ -0,0 +1,241 @@
+use ofborg::config::{GithubAppConfig, GithubAppVendingMachine};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A group of GitHub users a tier of the ACL can include.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Group {
+    /// The members of an organization.
+    Org(String),
+    /// The members of a team, by organization and team slug.
+    Team(String, String),
+    /// The collaborators of the repository being acted on.
+    Collaborators,
+}
+
+impl Group {
+    /// Parse an `org/team-slug` reference to a team.
+    pub fn team(reference: &str) -> Option<Group> {
+        let mut parts = reference.splitn(2, '/');
+        match (parts.next(), parts.next()) {
+            (Some(org), Some(slug)) if !org.is_empty() && !slug.is_empty() => {
+                Some(Group::Team(org.to_lowercase(), slug.to_lowercase()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Answers whether users belong to GitHub groups.
+pub trait Membership: Send {
+    /// If the user belongs to the group in the context of the
+    /// repository, or an error if that couldn't be determined.
+    fn is_member(&self, repo: &str, group: &Group, user: &str) -> Result<bool, String>;
+}
+
+/// Asks the GitHub API, using the installation of the app on the
+/// repository being acted on.
+pub struct GithubMembership {
+    conf: GithubAppConfig,
+    /// Vending machines no lookup is using. Each lookup takes one, so
+    /// lookups don't wait for each other's API requests.
+    idle: Mutex<Vec<GithubAppVendingMachine>>,
+}
+
+impl GithubMembership {
+    pub fn new(conf: GithubAppConfig) -> GithubMembership {
+        GithubMembership {
+            conf,
+            idle: Mutex::new(vec![]),
+        }
+    }
+
+    fn ask(
+        github_vend: &mut GithubAppVendingMachine,
+        owner: &str,
+        name: &str,
+        group: &Group,
+        user: &str,
+    ) -> Result<bool, String> {
+        let github = github_vend
+            .for_repo(owner, name)
+            .ok_or_else(|| format!("No GitHub app installation for {}/{}", owner, name))?;
+
+        let result = match *group {
+            Group::Org(ref org) => github.org(org.clone()).is_member(user),
+            Group::Team(ref org, ref slug) => {
+                let org_ref = github.org(org.clone());
+                let teams = org_ref.teams();
+                let team = teams
+                    .iter()
+                    .map_err(|e| format!("Listing the teams of {}: {:?}", org, e))?
+                    .find(|team| team.slug.to_lowercase() == *slug)
+                    .ok_or_else(|| format!("No team {}/{}", org, slug))?;
+
+                teams.get(team.id).is_member(user)
+            }
+            Group::Collaborators => github
+                .repo(owner.to_owned(), name.to_owned())
+                .collaborators()
+                .is_collaborator(user),
+        };
+
+        result.map_err(|e| format!("Checking {} is in {:?}: {:?}", user, group, e))
+    }
+}
+
+impl Membership for GithubMembership {
+    fn is_member(&self, repo: &str, group: &Group, user: &str) -> Result<bool, String> {
+        let mut parts = repo.splitn(2, '/');
+        let (owner, name) = match (parts.next(), parts.next()) {
+            (Some(owner), Some(name)) => (owner, name),
+            _ => return Err(format!("{} is not a repository full name", repo)),
+        };
+
+        let idle = self.idle.lock().unwrap().pop();
+        let mut github_vend =
+            idle.unwrap_or_else(|| GithubAppVendingMachine::new(self.conf.clone()));
+
+        let result = GithubMembership::ask(&mut github_vend, owner, name, group, user);
+        self.idle.lock().unwrap().push(github_vend);
+        result
+    }
+}
+
+type Answers = HashMap<(String, Group, String), Instant>;
+
+/// Remembers the memberships another `Membership` found for a while, so
+/// each comment doesn't cost a round of API requests. Users who aren't
+/// members are asked about again, so new members are recognised right
+/// away, and failures are not remembered either.
+pub struct CachedMembership<M: Membership> {
+    inner: M,
+    ttl: Duration,
+    answers: Mutex<Answers>,
+}
+
+impl<M: Membership> CachedMembership<M> {
+    pub fn new(inner: M, ttl: Duration) -> CachedMembership<M> {
+        CachedMembership {
+            inner,
+            ttl,
+            answers: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<M: Membership> Membership for CachedMembership<M> {
+    fn is_member(&self, repo: &str, group: &Group, user: &str) -> Result<bool, String> {
+        let key = (repo.to_lowercase(), group.clone(), user.to_lowercase());
+
+        if let Some(at) = self.answers.lock().unwrap().get(&key) {
+            if at.elapsed() < self.ttl {
+                return Ok(true);
+            }
+        }
+
+        let answer = self.inner.is_member(repo, group, user)?;
+        let mut answers = self.answers.lock().unwrap();
+        if answer {
+            answers.insert(key, Instant::now());
+        } else {
+            answers.remove(&key);
+        }
+
+        Ok(answer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct FakeMembership {
+        members: Vec<(Group, String)>,
+        up: bool,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Membership for FakeMembership {
+        fn is_member(&self, _repo: &str, group: &Group, user: &str) -> Result<bool, String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.up {
+                Ok(self.members.contains(&(group.clone(), user.to_owned())))
+            } else {
+                Err(String::from("GitHub is down"))
+            }
+        }
+    }
+
+    fn fake(up: bool) -> (FakeMembership, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        (
+            FakeMembership {
+                members: vec![(Group::Org(String::from("nixos")), String::from("alice"))],
+                up,
+                calls: calls.clone(),
+            },
+            calls,
+        )
+    }
+
+    #[test]
+    fn parse_team() {
+        assert_eq!(
+            Group::team("NixOS/Nixpkgs-Maintainers"),
+            Some(Group::Team(
+                String::from("nixos"),
+                String::from("nixpkgs-maintainers")
+            ))
+        );
+        assert_eq!(Group::team("nixos"), None);
+        assert_eq!(Group::team("nixos/"), None);
+    }
+
+    #[test]
+    fn cached_answers() {
+        let (inner, calls) = fake(true);
+        let cache = CachedMembership::new(inner, Duration::from_secs(600));
+        let nixos = Group::Org(String::from("nixos"));
+
+        assert_eq!(cache.is_member("NixOS/nixpkgs", &nixos, "alice"), Ok(true));
+        assert_eq!(cache.is_member("nixos/nixpkgs", &nixos, "Alice"), Ok(true));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn non_members_are_not_cached() {
+        let (inner, calls) = fake(true);
+        let cache = CachedMembership::new(inner, Duration::from_secs(600));
+        let nixos = Group::Org(String::from("nixos"));
+
+        assert_eq!(cache.is_member("nixos/nixpkgs", &nixos, "bob"), Ok(false));
+        assert_eq!(cache.is_member("nixos/nixpkgs", &nixos, "bob"), Ok(false));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn expired_answers() {
+        let (inner, calls) = fake(true);
+        let cache = CachedMembership::new(inner, Duration::from_secs(0));
+        let nixos = Group::Org(String::from("nixos"));
+
+        assert_eq!(cache.is_member("nixos/nixpkgs", &nixos, "alice"), Ok(true));
+        assert_eq!(cache.is_member("nixos/nixpkgs", &nixos, "alice"), Ok(true));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn failures_are_not_cached() {
+        let (inner, calls) = fake(false);
+        let cache = CachedMembership::new(inner, Duration::from_secs(600));
+        let nixos = Group::Org(String::from("nixos"));
+
+        assert!(cache.is_member("nixos/nixpkgs", &nixos, "alice").is_err());
+        assert!(cache.is_member("nixos/nixpkgs", &nixos, "alice").is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
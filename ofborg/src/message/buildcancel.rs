@@ -0,0 +1,31 @@
+use ofborg::message::{Pr, Repo};
+use serde_json;
+
+/// Published on the `build-cancels` fanout exchange to stop builds
+/// which are running, and to skip queued builds requested before it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BuildCancel {
+    pub repo: Repo,
+    pub pr: Pr,
+    /// Only cancel the build with this request ID, or None to cancel
+    /// every build for the PR.
+    pub request_id: Option<String>,
+    /// When the cancel was requested, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub requested_at: Option<u64>,
+}
+
+impl BuildCancel {
+    pub fn matches(&self, repo: &Repo, pr: &Pr, request_id: &str) -> bool {
+        self.repo.full_name == repo.full_name
+            && self.pr.number == pr.number
+            && match self.request_id {
+                Some(ref id) => id == request_id,
+                None => true,
+            }
+    }
+}
+
+pub fn from(data: &[u8]) -> Result<BuildCancel, serde_json::error::Error> {
+    serde_json::from_slice(data)
+}
@@ -1,5 +1,5 @@
 use ofborg::commentparser::Subset;
-use ofborg::message::{Pr, Repo};
+use ofborg::message::{unix_time, Pr, Repo};
 use serde_json;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -8,7 +8,14 @@ pub struct BuildJob {
     pub pr: Pr,
     pub subset: Option<Subset>,
     pub attrs: Vec<String>,
+    /// The systems the job was requested for, or None for every
+    /// system the requester may build on.
+    pub systems: Option<Vec<String>>,
     pub request_id: String,
+    /// When the job was requested, in seconds since the Unix epoch,
+    /// so builders can skip jobs for PRs cancelled while it was queued.
+    #[serde(default)]
+    pub requested_at: Option<u64>,
     pub logs: Option<ExchangeQueue>, // (Exchange, Routing Key)
     pub statusreport: Option<ExchangeQueue>, // (Exchange, Routing Key)
 }
@@ -40,9 +47,11 @@ impl BuildJob {
             pr,
             subset: Some(subset),
             attrs,
+            systems: None,
             logs: Some(logs.unwrap_or((Some("logs".to_owned()), Some(logbackrk)))),
             statusreport: Some(statusreport.unwrap_or((Some("build-results".to_owned()), None))),
             request_id,
+            requested_at: Some(unix_time()),
         }
     }
 }
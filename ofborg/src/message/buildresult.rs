@@ -1,5 +1,7 @@
 use hubcaps::checks::Conclusion;
 use ofborg::message::{Pr, Repo};
+use ofborg::nix::{BuildOutcome, NixError, PathInfo};
+use std::collections::BTreeMap;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum BuildStatus {
@@ -7,6 +9,12 @@ pub enum BuildStatus {
     Success,
     Failure,
     TimedOut,
+    MaxSilentTimedOut,
+    OutOfDisk,
+    KilledBySignal { signal: i32 },
+    DependencyFailed,
+    Cancelled,
+    Superseded,
     UnexpectedError { err: String },
 }
 
@@ -17,6 +25,14 @@ impl From<BuildStatus> for String {
             BuildStatus::Success => "Success".into(),
             BuildStatus::Failure => "Failure".into(),
             BuildStatus::TimedOut => "Timed out, unknown build status".into(),
+            BuildStatus::MaxSilentTimedOut => {
+                "Timed out without output, unknown build status".into()
+            }
+            BuildStatus::OutOfDisk => "Out of disk space, unknown build status".into(),
+            BuildStatus::KilledBySignal { signal } => format!("Killed by signal {}", signal),
+            BuildStatus::DependencyFailed => "Dependency failure".into(),
+            BuildStatus::Cancelled => "Cancelled".into(),
+            BuildStatus::Superseded => "Superseded by a newer commit".into(),
             BuildStatus::UnexpectedError { ref err } => format!("Unexpected error: {}", err),
         }
     }
@@ -28,12 +44,95 @@ impl From<BuildStatus> for Conclusion {
             BuildStatus::Skipped => Conclusion::Neutral,
             BuildStatus::Success => Conclusion::Success,
             BuildStatus::Failure => Conclusion::Neutral,
-            BuildStatus::TimedOut => Conclusion::Neutral,
+            BuildStatus::TimedOut => Conclusion::TimedOut,
+            BuildStatus::MaxSilentTimedOut => Conclusion::TimedOut,
+            BuildStatus::OutOfDisk => Conclusion::Neutral,
+            BuildStatus::KilledBySignal { .. } => Conclusion::Neutral,
+            BuildStatus::DependencyFailed => Conclusion::Neutral,
+            BuildStatus::Cancelled => Conclusion::Cancelled,
+            BuildStatus::Superseded => Conclusion::Cancelled,
             BuildStatus::UnexpectedError { .. } => Conclusion::Neutral,
         }
     }
 }
 
+impl From<BuildOutcome> for BuildStatus {
+    fn from(outcome: BuildOutcome) -> BuildStatus {
+        match outcome {
+            BuildOutcome::Success => BuildStatus::Success,
+            BuildOutcome::PermanentFailure => BuildStatus::Failure,
+            BuildOutcome::TimedOut => BuildStatus::TimedOut,
+            BuildOutcome::MaxSilentTimedOut => BuildStatus::MaxSilentTimedOut,
+            BuildOutcome::OutOfDisk => BuildStatus::OutOfDisk,
+            BuildOutcome::KilledBySignal { signal } => BuildStatus::KilledBySignal { signal },
+            BuildOutcome::DependencyFailed => BuildStatus::DependencyFailed,
+            BuildOutcome::UnexpectedExit { code: Some(code) } => BuildStatus::UnexpectedError {
+                err: format!("command failed with exit code {}", code),
+            },
+            BuildOutcome::UnexpectedExit { code: None } => BuildStatus::UnexpectedError {
+                err: "unexpected build failure".into(),
+            },
+        }
+    }
+}
+
+/// The result of building one attribute.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AttrResult {
+    pub status: BuildStatus,
+    /// The out paths of the attribute, if it built.
+    pub out_paths: Vec<String>,
+    /// The sizes of the out paths, if it built.
+    #[serde(default)]
+    pub sizes: Option<PathSizes>,
+    /// The sizes of the attribute's out paths on the target branch, if
+    /// they were in the builder's store.
+    #[serde(default)]
+    pub target_sizes: Option<PathSizes>,
+    /// The binary cache the out paths were copied to, if any.
+    #[serde(default)]
+    pub binary_cache: Option<String>,
+}
+
+impl AttrResult {
+    pub fn new(status: BuildStatus, out_paths: Vec<String>) -> AttrResult {
+        AttrResult {
+            status,
+            out_paths,
+            sizes: None,
+            target_sizes: None,
+            binary_cache: None,
+        }
+    }
+}
+
+/// The sizes of an attribute's out paths.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct PathSizes {
+    /// The NAR size of each out path.
+    pub nar_sizes: BTreeMap<String, u64>,
+    /// The NAR size of everything in the closure of the out paths.
+    pub closure_size: u64,
+}
+
+impl PathSizes {
+    pub fn new(out_paths: &[String], closure: &[PathInfo]) -> PathSizes {
+        PathSizes {
+            nar_sizes: closure
+                .iter()
+                .filter(|info| out_paths.contains(&info.path))
+                .map(|info| (info.path.clone(), info.nar_size))
+                .collect(),
+            closure_size: closure.iter().map(|info| info.nar_size).sum(),
+        }
+    }
+
+    /// The NAR size of all the out paths.
+    pub fn nar_size(&self) -> u64 {
+        self.nar_sizes.values().sum()
+    }
+}
+
 pub struct LegacyBuildResult {
     pub repo: Repo,
     pub pr: Pr,
@@ -44,6 +143,11 @@ pub struct LegacyBuildResult {
     pub status: BuildStatus,
     pub skipped_attrs: Option<Vec<String>>,
     pub attempted_attrs: Option<Vec<String>>,
+    /// The result of each attempted attribute, if the builder reported
+    /// them separately.
+    pub attr_results: BTreeMap<String, AttrResult>,
+    /// The failures Nix reported, if the builder recognised them.
+    pub errors: Vec<NixError>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -51,9 +155,32 @@ pub enum V1Tag {
     V1,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub enum V2Tag {
+    V2,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
 pub enum BuildResult {
+    V2 {
+        tag: V2Tag,
+        repo: Repo,
+        pr: Pr,
+        system: String,
+        output: Vec<String>,
+        attempt_id: String,
+        request_id: String,
+        /// The first status of `attr_results` which isn't a success.
+        status: BuildStatus,
+        skipped_attrs: Option<Vec<String>>,
+        attempted_attrs: Option<Vec<String>>,
+        attr_results: BTreeMap<String, AttrResult>,
+        /// The failures Nix reported while instantiating and building
+        /// the attributes.
+        #[serde(default)]
+        errors: Vec<NixError>,
+    },
     V1 {
         tag: V1Tag, // use serde once all enum variants have a tag
         repo: Repo,
@@ -107,6 +234,8 @@ impl BuildResult {
                 status: self.status(),
                 attempted_attrs: attempted_attrs.to_owned(),
                 skipped_attrs: skipped_attrs.to_owned(),
+                attr_results: BTreeMap::new(),
+                errors: vec![],
             },
             BuildResult::V1 {
                 ref repo,
@@ -128,6 +257,33 @@ impl BuildResult {
                 status: self.status(),
                 attempted_attrs: attempted_attrs.to_owned(),
                 skipped_attrs: skipped_attrs.to_owned(),
+                attr_results: BTreeMap::new(),
+                errors: vec![],
+            },
+            BuildResult::V2 {
+                ref repo,
+                ref pr,
+                ref system,
+                ref output,
+                ref attempt_id,
+                ref request_id,
+                ref attempted_attrs,
+                ref skipped_attrs,
+                ref attr_results,
+                ref errors,
+                ..
+            } => LegacyBuildResult {
+                repo: repo.to_owned(),
+                pr: pr.to_owned(),
+                system: system.to_owned(),
+                output: output.to_owned(),
+                attempt_id: attempt_id.to_owned(),
+                request_id: request_id.to_owned(),
+                status: self.status(),
+                attempted_attrs: attempted_attrs.to_owned(),
+                skipped_attrs: skipped_attrs.to_owned(),
+                attr_results: attr_results.to_owned(),
+                errors: errors.to_owned(),
             },
         }
     }
@@ -147,6 +303,7 @@ impl BuildResult {
                 }
             }),
             BuildResult::V1 { ref status, .. } => status.to_owned(),
+            BuildResult::V2 { ref status, .. } => status.to_owned(),
         }
     }
 }
@@ -156,6 +313,44 @@ mod tests {
     use super::*;
     use serde_json;
 
+    #[test]
+    fn v2_serialization() {
+        let input = r#"{"tag":"V2","repo":{"owner":"NixOS","name":"nixpkgs","full_name":"NixOS/nixpkgs","clone_url":"https://github.com/nixos/nixpkgs.git"},"pr":{"target_branch":"master","number":42,"head_sha":"0000000000000000000000000000000000000000"},"system":"x86_64-linux","output":["unpacking sources"],"attempt_id":"attempt-id-foo","request_id":"bogus-request-id","status":"Failure","skipped_attrs":[],"attempted_attrs":["bar","hello"],"attr_results":{"bar":{"status":"Failure","out_paths":[],"sizes":null,"target_sizes":null,"binary_cache":null},"hello":{"status":"Success","out_paths":["/nix/store/abc-hello"],"sizes":{"nar_sizes":{"/nix/store/abc-hello":226560},"closure_size":29226216},"target_sizes":null,"binary_cache":null}},"errors":[{"BuilderFailed":{"drv":"/nix/store/abc-bar.drv","exit_code":2}}]}"#;
+        let result: BuildResult = serde_json::from_str(input).expect("result required");
+        assert_eq!(result.status(), BuildStatus::Failure);
+        assert_eq!(
+            result.legacy().errors,
+            vec![NixError::BuilderFailed {
+                drv: String::from("/nix/store/abc-bar.drv"),
+                exit_code: Some(2),
+            }]
+        );
+        assert_eq!(
+            result.legacy().attr_results["hello"],
+            AttrResult {
+                status: BuildStatus::Success,
+                out_paths: vec![String::from("/nix/store/abc-hello")],
+                sizes: Some(PathSizes::new(
+                    &[String::from("/nix/store/abc-hello")],
+                    &[
+                        PathInfo {
+                            path: String::from("/nix/store/abc-hello"),
+                            nar_size: 226560,
+                        },
+                        PathInfo {
+                            path: String::from("/nix/store/def-glibc"),
+                            nar_size: 28_999_656,
+                        },
+                    ],
+                )),
+                target_sizes: None,
+                binary_cache: None,
+            }
+        );
+        let output = serde_json::to_string(&result).expect("json required");
+        assert_eq!(output, input, "json of: {:?}", result);
+    }
+
     #[test]
     fn v1_serialization() {
         let input = r#"{"tag":"V1","repo":{"owner":"NixOS","name":"nixpkgs","full_name":"NixOS/nixpkgs","clone_url":"https://github.com/nixos/nixpkgs.git"},"pr":{"target_branch":"master","number":42,"head_sha":"0000000000000000000000000000000000000000"},"system":"x86_64-linux","output":["unpacking sources"],"attempt_id":"attempt-id-foo","request_id":"bogus-request-id","status":"Success","skipped_attrs":["AAAAAASomeThingsFailToEvaluate"],"attempted_attrs":["hello"]}"#;
@@ -1,3 +1,5 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Repo {
     pub owner: String,
@@ -12,3 +14,11 @@ pub struct Pr {
     pub number: u64,
     pub head_sha: String,
 }
+
+/// Seconds since the Unix epoch, to order build requests and cancels.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0)
+}
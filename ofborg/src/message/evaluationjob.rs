@@ -18,6 +18,24 @@ impl EvaluationJob {
     }
 }
 
+/// An evaluation of NixOS/nixpkgs#42, for tests.
+#[cfg(test)]
+pub fn test_job() -> EvaluationJob {
+    EvaluationJob {
+        repo: Repo {
+            clone_url: "https://github.com/nixos/nixpkgs.git".to_owned(),
+            full_name: "NixOS/nixpkgs".to_owned(),
+            owner: "NixOS".to_owned(),
+            name: "nixpkgs".to_owned(),
+        },
+        pr: Pr {
+            head_sha: "abc123".to_owned(),
+            number: 42,
+            target_branch: Some("master".to_owned()),
+        },
+    }
+}
+
 pub struct Actions {}
 
 impl Actions {
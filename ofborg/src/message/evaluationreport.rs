@@ -0,0 +1,161 @@
+use crate::nixstats::EvaluationStats;
+use ofborg::message::evaluationjob::EvaluationJob;
+use ofborg::message::{Pr, Repo};
+use ofborg::outpathdiff::PackageArch;
+use serde_json;
+use std::collections::BTreeMap;
+
+/// The version of the report format. It is increased whenever a field
+/// is removed or changes meaning, so consumers can skip reports they
+/// don't understand. New fields may be added without increasing it.
+pub const VERSION: u32 = 1;
+
+pub fn from(data: &[u8]) -> Result<EvaluationReport, serde_json::error::Error> {
+    serde_json::from_slice(data)
+}
+
+/// Everything found out while evaluating a PR, published to the
+/// `evaluation-reports` exchange and written to the log storage.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EvaluationReport {
+    pub version: u32,
+    pub repo: Repo,
+    pub pr: Pr,
+    /// The identity of the evaluator which wrote the report.
+    pub evaluator: String,
+    pub status: EvaluationStatus,
+
+    /// The results of the evaluation checks which were run, in order.
+    #[serde(default)]
+    pub checks: Vec<CheckOutcome>,
+
+    /// The attributes whose out paths are different after the PR.
+    #[serde(default)]
+    pub rebuilds: Option<Vec<PackageArch>>,
+    #[serde(default)]
+    pub added: Option<Vec<PackageArch>>,
+    #[serde(default)]
+    pub removed: Option<Vec<PackageArch>>,
+
+    /// The systems whose stdenv is different after the PR.
+    #[serde(default)]
+    pub stdenv_changes: Option<Vec<String>>,
+
+    /// The changed packages of each maintainer.
+    #[serde(default)]
+    pub maintainers: Option<BTreeMap<String, Vec<String>>>,
+
+    /// The statistics of evaluating the target branch and the PR.
+    #[serde(default)]
+    pub stats: Option<EvaluationStatsReport>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum EvaluationStatus {
+    Success,
+    Failure { reason: String },
+    MergeConflict,
+    Superseded,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CheckOutcome {
+    pub name: String,
+    pub success: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EvaluationStatsReport {
+    pub before: EvaluationStats,
+    pub after: EvaluationStats,
+}
+
+impl EvaluationReport {
+    pub fn new(job: &EvaluationJob, evaluator: &str) -> EvaluationReport {
+        EvaluationReport {
+            version: VERSION,
+            repo: job.repo.clone(),
+            pr: job.pr.clone(),
+            evaluator: evaluator.to_owned(),
+            status: EvaluationStatus::Success,
+            checks: vec![],
+            rebuilds: None,
+            added: None,
+            removed: None,
+            stdenv_changes: None,
+            maintainers: None,
+            stats: None,
+        }
+    }
+
+    /// Where the report is written, relative to the log storage.
+    pub fn storage_path(&self) -> String {
+        format!(
+            "evaluation-reports/{}/{}/{}.json",
+            self.repo.full_name, self.pr.number, self.pr.head_sha
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ofborg::message::evaluationjob::test_job;
+
+    #[test]
+    fn round_trip() {
+        let mut report = EvaluationReport::new(&test_job(), "evaluator-x86_64-linux");
+        report.status = EvaluationStatus::Failure {
+            reason: String::from("Failed to merge"),
+        };
+        report.checks.push(CheckOutcome {
+            name: String::from("package-list"),
+            success: true,
+        });
+        report.rebuilds = Some(vec![PackageArch {
+            package: String::from("hello"),
+            architecture: String::from("x86_64-linux"),
+        }]);
+
+        let json = serde_json::to_string(&report).unwrap();
+        let parsed = from(json.as_bytes()).unwrap();
+
+        assert_eq!(parsed.version, VERSION);
+        assert_eq!(parsed.status, report.status);
+        assert_eq!(parsed.checks, report.checks);
+        assert_eq!(parsed.rebuilds, report.rebuilds);
+        assert_eq!(parsed.added, None);
+    }
+
+    #[test]
+    fn minimal_report() {
+        let report = from(
+            br#"{
+                "version": 1,
+                "repo": {
+                    "clone_url": "https://github.com/nixos/nixpkgs.git",
+                    "full_name": "NixOS/nixpkgs",
+                    "owner": "NixOS",
+                    "name": "nixpkgs"
+                },
+                "pr": {"head_sha": "abc123", "number": 42, "target_branch": "master"},
+                "evaluator": "evaluator-x86_64-linux",
+                "status": "MergeConflict"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(report.status, EvaluationStatus::MergeConflict);
+        assert!(report.checks.is_empty());
+        assert!(report.stats.is_none());
+    }
+
+    #[test]
+    fn storage_path() {
+        let report = EvaluationReport::new(&test_job(), "evaluator");
+        assert_eq!(
+            report.storage_path(),
+            "evaluation-reports/NixOS/nixpkgs/42/abc123.json"
+        );
+    }
+}
@@ -0,0 +1,14 @@
+use ofborg::message::{Pr, Repo};
+use serde_json;
+
+/// Published on the `pr-head-updates` fanout exchange when a PR is
+/// opened or pushed to, so workers can drop jobs for older commits.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HeadUpdate {
+    pub repo: Repo,
+    pub pr: Pr,
+}
+
+pub fn from(data: &[u8]) -> Result<HeadUpdate, serde_json::error::Error> {
+    serde_json::from_slice(data)
+}
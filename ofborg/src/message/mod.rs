@@ -1,7 +1,10 @@
+pub mod buildcancel;
 pub mod buildjob;
 pub mod buildlogmsg;
 pub mod buildresult;
 mod common;
 pub mod evaluationjob;
+pub mod evaluationreport;
+pub mod headupdate;
 
-pub use self::common::{Pr, Repo};
+pub use self::common::{unix_time, Pr, Repo};
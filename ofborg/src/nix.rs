@@ -1,16 +1,19 @@
-use ofborg::asynccmd::{AsyncCmd, SpawnedAsyncCmd};
+use ofborg::asynccmd::{AsyncCmd, CancelHandle, SpawnedAsyncCmd};
 use ofborg::partition_result;
-use std::collections::HashMap;
+use serde_json;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::ffi::OsStr;
 use std::fmt;
 use std::fs;
+use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::io::Seek;
 use std::io::SeekFrom;
+use std::os::unix::process::ExitStatusExt;
 use std::path::Path;
-use std::process::{Command, Stdio};
+use std::process::{Command, ExitStatus, Stdio};
 use tempfile::tempfile;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -137,17 +140,23 @@ impl Nix {
         n
     }
 
+    /// Instantiates each attribute on its own, splitting them into the
+    /// ones which instantiate and the ones which don't, with the output
+    /// explaining why.
     pub fn safely_partition_instantiable_attrs(
         &self,
         nixpkgs: &Path,
         file: File,
         attrs: Vec<String>,
-    ) -> (Vec<String>, Vec<(String, Vec<String>)>) {
-        let attr_instantiations: Vec<Result<String, (String, Vec<String>)>> = attrs
+    ) -> (Vec<InstantiatedAttr>, Vec<(String, Vec<String>)>) {
+        let attr_instantiations: Vec<Result<InstantiatedAttr, (String, Vec<String>)>> = attrs
             .into_iter()
             .map(
                 |attr| match self.safely_instantiate_attrs(nixpkgs, file, vec![attr.clone()]) {
-                    Ok(_) => Ok(attr.clone()),
+                    Ok(output) => Ok(InstantiatedAttr {
+                        attr,
+                        drvs: store_drvs(lines_from_file(output)),
+                    }),
                     Err(f) => Err((attr.clone(), lines_from_file(f))),
                 },
             )
@@ -186,15 +195,30 @@ impl Nix {
         self.safe_command(&Operation::Evaluate, nixpkgs, &attrargs, &extra_paths)
     }
 
+    /// Builds the attributes, returning how the build ended and its
+    /// output.
     pub fn safely_build_attrs(
         &self,
         nixpkgs: &Path,
         file: File,
         attrs: Vec<String>,
-    ) -> Result<fs::File, fs::File> {
+    ) -> (BuildOutcome, fs::File) {
         let mut command = self.safe_command::<&OsStr>(&Operation::Build, nixpkgs, &[], &[]);
         self.set_attrs_command(&mut command, file, attrs);
-        self.run(command, true)
+        let (status, mut reader) = self.run_status(command, true);
+
+        let mut output = BuildOutput::new();
+        for line in BufReader::new(&reader).lines() {
+            match line {
+                Ok(line) => output.line(&line),
+                Err(_) => break,
+            }
+        }
+        reader
+            .seek(SeekFrom::Start(0))
+            .expect("Seeking to Start(0)");
+
+        (output.outcome(&status), reader)
     }
 
     pub fn safely_build_attrs_async(
@@ -202,10 +226,109 @@ impl Nix {
         nixpkgs: &Path,
         file: File,
         attrs: Vec<String>,
-    ) -> SpawnedAsyncCmd {
+        cancel: CancelHandle,
+    ) -> SpawnedBuild {
         let mut command = self.safe_command::<&OsStr>(&Operation::Build, nixpkgs, &[], &[]);
         self.set_attrs_command(&mut command, file, attrs);
-        AsyncCmd::new(command).spawn()
+        SpawnedBuild {
+            cmd: AsyncCmd::new(command).spawn_with_cancel(cancel),
+            output: BuildOutput::new(),
+        }
+    }
+
+    /// The out paths the attribute would build, without building it, or
+    /// none if it doesn't instantiate.
+    pub fn safely_query_out_paths(
+        &self,
+        nixpkgs: &Path,
+        file: File,
+        attr: String,
+    ) -> Option<Vec<String>> {
+        let drvs = match self.safely_instantiate_attrs(nixpkgs, file, vec![attr]) {
+            Ok(output) => store_drvs(lines_from_file(output)),
+            Err(_) => return None,
+        };
+        if drvs.is_empty() {
+            return None;
+        }
+
+        self.outputs(&drvs).ok()
+    }
+
+    /// The out paths of the derivations.
+    pub fn outputs(&self, drvs: &[String]) -> Result<Vec<String>, String> {
+        let mut command = self.store_command("nix-store");
+        command.args(&["--query", "--outputs"]);
+        command.args(drvs);
+
+        let (success, stdout, stderr) = self.run_stderr_stdout(command);
+        if !success {
+            return Err(lines_from_file(stderr).join("\n"));
+        }
+        Ok(store_paths(lines_from_file(stdout)))
+    }
+
+    /// The paths which are in the store.
+    pub fn valid_paths(&self, paths: &[String]) -> Result<HashSet<String>, String> {
+        let mut valid: HashSet<String> = paths.iter().cloned().collect();
+
+        // In batches, so the arguments fit on a command line.
+        for batch in paths.chunks(1000) {
+            let mut command = self.store_command("nix-store");
+            command.args(&["--check-validity", "--print-invalid"]);
+            command.args(batch);
+
+            let (success, stdout, stderr) = self.run_stderr_stdout(command);
+            if !success {
+                return Err(lines_from_file(stderr).join("\n"));
+            }
+            for invalid in lines_from_file(stdout) {
+                valid.remove(&invalid);
+            }
+        }
+
+        Ok(valid)
+    }
+
+    /// Every path in the closure of the paths, with its size. Fails if
+    /// any of the paths isn't in the store.
+    pub fn closure(&self, paths: &[String]) -> Result<Vec<PathInfo>, String> {
+        let mut command = self.store_command("nix");
+        command.args(&["path-info", "--json", "--recursive"]);
+        command.args(&["--option", "extra-experimental-features", "nix-command"]);
+        command.args(paths);
+
+        let (success, stdout, stderr) = self.run_stderr_stdout(command);
+        if !success {
+            return Err(lines_from_file(stderr).join("\n"));
+        }
+
+        let json: serde_json::Value =
+            serde_json::from_reader(stdout).map_err(|e| format!("{:?}", e))?;
+        parse_path_info(&json)
+    }
+
+    /// Copies the paths and their closures to the store at the URL.
+    pub fn copy_to(&self, paths: &[String], store_url: &str) -> Result<(), String> {
+        let mut command = self.store_command("nix");
+        command.args(&["copy", "--to", store_url]);
+        command.args(&["--option", "extra-experimental-features", "nix-command"]);
+        command.args(paths);
+
+        match self.run(command, true) {
+            Ok(_) => Ok(()),
+            Err(output) => Err(lines_from_file(output).join("\n")),
+        }
+    }
+
+    /// A command which only uses the store, not nixpkgs.
+    fn store_command(&self, program: &str) -> Command {
+        let mut command = Command::new(program);
+        command.env_clear();
+        command.env("HOME", "/homeless-shelter");
+        command.env("NIX_REMOTE", &self.remote);
+        command.env("PATH", env::var("PATH").unwrap());
+        command
     }
 
     fn set_attrs_command(&self, command: &mut Command, file: File, attrs: Vec<String>) {
@@ -235,7 +358,17 @@ impl Nix {
         self.run(self.safe_command(&op, nixpkgs, &args, &[]), keep_stdout)
     }
 
-    pub fn run(&self, mut cmd: Command, keep_stdout: bool) -> Result<fs::File, fs::File> {
+    pub fn run(&self, cmd: Command, keep_stdout: bool) -> Result<fs::File, fs::File> {
+        let (status, reader) = self.run_status(cmd, keep_stdout);
+
+        if status.success() {
+            Ok(reader)
+        } else {
+            Err(reader)
+        }
+    }
+
+    fn run_status(&self, mut cmd: Command, keep_stdout: bool) -> (ExitStatus, fs::File) {
         let stderr = tempfile().expect("Fetching a stderr tempfile");
         let mut reader = stderr.try_clone().expect("Cloning stderr to the reader");
 
@@ -255,11 +388,7 @@ impl Nix {
             .seek(SeekFrom::Start(0))
             .expect("Seeking to Start(0)");
 
-        if status.success() {
-            Ok(reader)
-        } else {
-            Err(reader)
-        }
+        (status, reader)
     }
 
     pub fn run_stderr_stdout(&self, mut cmd: Command) -> (bool, fs::File, fs::File) {
@@ -352,6 +481,456 @@ fn lines_from_file(file: fs::File) -> Vec<String> {
         .collect()
 }
 
+/// A store path and the size of its NAR.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathInfo {
+    pub path: String,
+    pub nar_size: u64,
+}
+
+/// Parses `nix path-info --json`, which is a list of paths up to Nix
+/// 2.18 and an object keyed by path since.
+fn parse_path_info(json: &serde_json::Value) -> Result<Vec<PathInfo>, String> {
+    let entries: Vec<(String, &serde_json::Value)> = match *json {
+        serde_json::Value::Array(ref entries) => entries
+            .iter()
+            .map(|entry| {
+                let path = entry["path"].as_str().unwrap_or_default().to_owned();
+                (path, entry)
+            })
+            .collect(),
+        serde_json::Value::Object(ref entries) => entries
+            .iter()
+            .map(|(path, entry)| (path.clone(), entry))
+            .collect(),
+        _ => return Err(String::from("Unexpected output of nix path-info")),
+    };
+
+    entries
+        .into_iter()
+        .map(|(path, entry)| match entry["narSize"].as_u64() {
+            Some(nar_size) => Ok(PathInfo { path, nar_size }),
+            None => Err(format!("{} is not in the store", path)),
+        })
+        .collect()
+}
+
+/// An attribute which instantiated, with the derivations it
+/// instantiated to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstantiatedAttr {
+    pub attr: String,
+    pub drvs: Vec<String>,
+}
+
+/// The lines of the output which are derivations.
+fn store_drvs(lines: Vec<String>) -> Vec<String> {
+    store_paths(lines)
+        .into_iter()
+        .filter(|path| path.ends_with(".drv"))
+        .collect()
+}
+
+/// The lines of the output which are store paths.
+fn store_paths(lines: Vec<String>) -> Vec<String> {
+    lines
+        .into_iter()
+        .filter(|line| line.starts_with("/nix/store/") && !line.contains(char::is_whitespace))
+        .collect()
+}
+
+/// How a build ended, from Nix's exit status and output.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuildOutcome {
+    Success,
+    /// A builder failed, or an output didn't match its expected hash.
+    PermanentFailure,
+    /// A build ran for longer than the `build-timeout`.
+    TimedOut,
+    /// A build printed nothing for longer than the `max-silent-time`.
+    MaxSilentTimedOut,
+    /// The builder ran out of disk space.
+    OutOfDisk,
+    /// Nix or a builder was killed by the signal.
+    KilledBySignal {
+        signal: i32,
+    },
+    /// The attributes couldn't be built because a dependency failed.
+    DependencyFailed,
+    /// Nix failed for any other reason, like an attribute which doesn't
+    /// evaluate.
+    UnexpectedExit {
+        code: Option<i32>,
+    },
+}
+
+/// Recognises how a build ended, one line of its output at a time, so
+/// builds streaming their output don't need to keep all of it.
+#[derive(Debug, Default)]
+pub struct BuildOutput {
+    timed_out: bool,
+    silent: bool,
+    out_of_disk: bool,
+    builder_signal: Option<i32>,
+    dependency_failed: bool,
+    errors: ErrorParser,
+}
+
+impl BuildOutput {
+    pub fn new() -> BuildOutput {
+        Default::default()
+    }
+
+    pub fn line(&mut self, line: &str) {
+        self.errors.line(line);
+        let line = normalize_line(line);
+
+        if line.contains(" timed out after ") {
+            self.timed_out = true;
+            self.silent = self.silent || line.contains("of silence");
+        }
+
+        if line.contains("No space left on device") {
+            self.out_of_disk = true;
+        }
+
+        if let Some(signal) = after_any(&line, &["failed due to signal "]) {
+            let digits: String = signal.chars().take_while(|c| c.is_ascii_digit()).collect();
+            self.builder_signal = self.builder_signal.or_else(|| digits.parse().ok());
+        }
+
+        // Nix 2.3: cannot build derivation '<drv>': 1 dependencies couldn't be built
+        // Nix 2.4: 1 dependencies of derivation '<drv>' failed to build
+        if line.contains("dependencies couldn't be built")
+            || (line.contains("dependencies of derivation") && line.contains("failed to build"))
+        {
+            self.dependency_failed = true;
+        }
+    }
+
+    /// The failures Nix reported so far.
+    pub fn errors(&self) -> &[NixError] {
+        self.errors.errors()
+    }
+
+    /// The outcome of the build which exited with the status. Nix
+    /// exits with 100, plus 1 for timeouts, 2 for hash mismatches and
+    /// 4 for non-deterministic builds, when builds fail.
+    pub fn outcome(&self, status: &ExitStatus) -> BuildOutcome {
+        if status.success() {
+            return BuildOutcome::Success;
+        }
+
+        let code = match status.code() {
+            Some(code) => code,
+            None => {
+                return BuildOutcome::KilledBySignal {
+                    signal: status.signal().unwrap_or(0),
+                };
+            }
+        };
+
+        // The flags Nix added to 100, if it exited as a failed build.
+        let flags = if (100..=107).contains(&code) {
+            Some(code - 100)
+        } else {
+            None
+        };
+
+        if self.out_of_disk {
+            BuildOutcome::OutOfDisk
+        } else if self.silent {
+            BuildOutcome::MaxSilentTimedOut
+        } else if self.timed_out || flags.unwrap_or(0) & 1 != 0 {
+            BuildOutcome::TimedOut
+        } else if let Some(signal) = self.builder_signal {
+            BuildOutcome::KilledBySignal { signal }
+        } else if self.dependency_failed {
+            BuildOutcome::DependencyFailed
+        } else if flags.is_some() {
+            BuildOutcome::PermanentFailure
+        } else {
+            BuildOutcome::UnexpectedExit { code: Some(code) }
+        }
+    }
+}
+
+/// A build running in the background, which recognises how it ended
+/// from its output.
+pub struct SpawnedBuild {
+    cmd: SpawnedAsyncCmd,
+    output: BuildOutput,
+}
+
+impl SpawnedBuild {
+    pub fn lines<'a>(&'a mut self) -> impl Iterator<Item = String> + 'a {
+        let output = &mut self.output;
+        self.cmd.lines().inspect(move |line| output.line(line))
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cmd.cancel_handle()
+    }
+
+    /// How the build ended, and the failures Nix reported. Only
+    /// complete once all of its lines were read.
+    pub fn wait(self) -> Result<(BuildOutcome, Vec<NixError>), io::Error> {
+        let output = self.output;
+        self.cmd
+            .wait()
+            .map(|status| (output.outcome(&status), output.errors.into_errors()))
+    }
+}
+
+/// A failure recognised in the output of Nix.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum NixError {
+    MissingAttribute {
+        attr: String,
+    },
+    HashMismatch {
+        path: String,
+        expected: String,
+        got: String,
+    },
+    /// The builder of a derivation failed, with the exit code unless it
+    /// was killed by a signal.
+    BuilderFailed {
+        drv: String,
+        exit_code: Option<i32>,
+    },
+    AssertionFailed {
+        assertion: Option<String>,
+    },
+    InfiniteRecursion,
+    /// Restricted evaluation forbade access to a path, URI or builtin.
+    RestrictedEval {
+        resource: String,
+    },
+    /// Any other `error:` line.
+    Other {
+        message: String,
+    },
+}
+
+impl fmt::Display for NixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NixError::MissingAttribute { ref attr } => write!(f, "Attribute `{}` is missing", attr),
+            NixError::HashMismatch {
+                ref path,
+                ref expected,
+                ref got,
+            } => write!(
+                f,
+                "Hash mismatch in `{}`: expected `{}`, got `{}`",
+                path, expected, got
+            ),
+            NixError::BuilderFailed {
+                ref drv,
+                exit_code: Some(code),
+            } => write!(f, "Builder for `{}` failed with exit code {}", drv, code),
+            NixError::BuilderFailed {
+                ref drv,
+                exit_code: None,
+            } => write!(f, "Builder for `{}` was killed", drv),
+            NixError::AssertionFailed {
+                assertion: Some(ref assertion),
+            } => write!(f, "Assertion `{}` failed", assertion),
+            NixError::AssertionFailed { assertion: None } => write!(f, "An assertion failed"),
+            NixError::InfiniteRecursion => write!(f, "Infinite recursion encountered"),
+            NixError::RestrictedEval { ref resource } => {
+                write!(f, "Access to {} is forbidden in restricted mode", resource)
+            }
+            NixError::Other { ref message } => write!(f, "{}", message),
+        }
+    }
+}
+
+/// The failures Nix reported in its output, in order and without
+/// duplicates.
+pub fn parse_errors(output: &str) -> Vec<NixError> {
+    let mut parser = ErrorParser::new();
+    for line in output.lines() {
+        parser.line(line);
+    }
+    parser.into_errors()
+}
+
+/// Recognises the failures Nix reports, one line of its output at a
+/// time, so streamed output doesn't need to be kept.
+#[derive(Debug, Default)]
+pub struct ErrorParser {
+    errors: Vec<NixError>,
+    mismatch: Option<(String, Option<String>)>,
+}
+
+impl ErrorParser {
+    pub fn new() -> ErrorParser {
+        Default::default()
+    }
+
+    pub fn line(&mut self, line: &str) {
+        let line = normalize_line(line);
+        let line = line.trim();
+
+        let error = if let Some((path, expected)) = self.mismatch.take() {
+            if let Some(wanted) = after_any(line, &["wanted:", "specified:"]) {
+                self.mismatch = Some((path, Some(wanted.trim().to_owned())));
+                return;
+            } else if let Some(got) = after_any(line, &["got:"]) {
+                Some(NixError::HashMismatch {
+                    path,
+                    expected: expected.unwrap_or_default(),
+                    got: got.trim().to_owned(),
+                })
+            } else {
+                parse_error_line(line)
+            }
+        } else if let Some(path) = quoted_after(line, "hash mismatch in fixed-output derivation ") {
+            self.mismatch = Some((path, None));
+            return;
+        } else {
+            parse_error_line(line)
+        };
+
+        if let Some(error) = error {
+            if !self.errors.contains(&error) {
+                self.errors.push(error);
+            }
+        }
+    }
+
+    pub fn errors(&self) -> &[NixError] {
+        &self.errors
+    }
+
+    pub fn into_errors(self) -> Vec<NixError> {
+        self.errors
+    }
+}
+
+fn parse_error_line(line: &str) -> Option<NixError> {
+    if let Some(attr) = quoted_after(line, "attribute ") {
+        if line.contains("' missing") || line.contains("' not found") {
+            return Some(NixError::MissingAttribute { attr });
+        }
+    }
+
+    if let Some(drv) = quoted_after(line, "builder for ") {
+        if let Some(code) = after_any(line, &["failed with exit code "]) {
+            let digits: String = code.chars().take_while(|c| c.is_ascii_digit()).collect();
+            return Some(NixError::BuilderFailed {
+                drv,
+                exit_code: digits.parse().ok(),
+            });
+        } else if line.contains("failed due to signal") {
+            return Some(NixError::BuilderFailed {
+                drv,
+                exit_code: None,
+            });
+        }
+    }
+
+    if let Some(path) = quoted_after(line, "output path ") {
+        // Nix 2.0: output path '<path>' has <algo> hash '<got>' when '<expected>' was expected
+        let mut quoted = line.split('\'').skip(3).step_by(2);
+        if let (Some(got), Some(expected)) = (quoted.next(), quoted.next()) {
+            if line.contains(" was expected") {
+                return Some(NixError::HashMismatch {
+                    path,
+                    expected: expected.to_owned(),
+                    got: got.to_owned(),
+                });
+            }
+        }
+    }
+
+    if let Some(rest) = after_any(line, &["assertion "]) {
+        if let Some(end) = rest.find("failed") {
+            let assertion = rest[..end]
+                .trim()
+                .trim_matches(|c| c == '\'' || c == '(' || c == ')');
+            return Some(NixError::AssertionFailed {
+                assertion: if assertion.is_empty() {
+                    None
+                } else {
+                    Some(assertion.to_owned())
+                },
+            });
+        }
+    }
+
+    if line.contains("infinite recursion encountered") {
+        return Some(NixError::InfiniteRecursion);
+    }
+
+    for restriction in &[
+        " is forbidden in restricted mode",
+        " is not allowed in restricted mode",
+    ] {
+        if let Some(end) = line.find(restriction) {
+            let resource = line[..end].trim_start_matches("error:").trim();
+            let resource = resource.trim_start_matches("access to ");
+            return Some(NixError::RestrictedEval {
+                resource: resource.to_owned(),
+            });
+        }
+    }
+
+    if line.starts_with("error:") {
+        let message = line.trim_start_matches("error:").trim();
+        if !message.is_empty() {
+            return Some(NixError::Other {
+                message: message.to_owned(),
+            });
+        }
+    }
+
+    None
+}
+
+/// The text after the first of the markers in the line.
+fn after_any<'a>(line: &'a str, markers: &[&str]) -> Option<&'a str> {
+    markers
+        .iter()
+        .filter_map(|marker| line.find(marker).map(|i| &line[i + marker.len()..]))
+        .next()
+}
+
+/// The quoted text right after the marker, like `path` in
+/// `<marker>'path'`.
+fn quoted_after(line: &str, marker: &str) -> Option<String> {
+    let rest = after_any(line, &[marker])?;
+    if !rest.starts_with('\'') {
+        return None;
+    }
+    let rest = &rest[1..];
+    let end = rest.find('\'')?;
+    Some(rest[..end].to_owned())
+}
+
+/// Strip colours and use plain quotes, as Nix versions differ in both.
+fn normalize_line(line: &str) -> String {
+    let mut normalized = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{1b}' => {
+                // Skip the rest of the escape sequence, up to its letter.
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+            '‘' | '’' | '`' => normalized.push('\''),
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
 #[cfg(test)]
 mod tests {
     fn nix() -> Nix {
@@ -668,14 +1247,15 @@ mod tests {
     fn safely_build_attrs_success() {
         let nix = nix();
 
-        let ret: Result<fs::File, fs::File> = nix.safely_build_attrs(
+        let (outcome, output) = nix.safely_build_attrs(
             build_path().as_path(),
             File::DefaultNixpkgs,
             vec![String::from("success")],
         );
 
+        assert_eq!(outcome, BuildOutcome::Success);
         assert_run(
-            ret,
+            Ok(output),
             Expect::Pass,
             vec!["-success.drv", "building ", "hi", "-success"],
         );
@@ -685,14 +1265,15 @@ mod tests {
     fn safely_build_attrs_failure() {
         let nix = nix();
 
-        let ret: Result<fs::File, fs::File> = nix.safely_build_attrs(
+        let (outcome, output) = nix.safely_build_attrs(
             build_path().as_path(),
             File::DefaultNixpkgs,
             vec![String::from("failed")],
         );
 
+        assert_eq!(outcome, BuildOutcome::PermanentFailure);
         assert_run(
-            ret,
+            Err(output),
             Expect::Fail,
             vec![
                 "-failed.drv",
@@ -707,7 +1288,7 @@ mod tests {
     fn partition_instantiable_attributes() {
         let nix = nix();
 
-        let ret: (Vec<String>, Vec<(String, Vec<String>)>) = nix
+        let ret: (Vec<InstantiatedAttr>, Vec<(String, Vec<String>)>) = nix
             .safely_partition_instantiable_attrs(
                 individual_eval_path().as_path(),
                 File::DefaultNixpkgs,
@@ -718,7 +1299,9 @@ mod tests {
                 ],
             );
 
-        assert_eq!(ret.0, vec!["passes-instantiation"]);
+        assert_eq!(ret.0.len(), 1);
+        assert_eq!(ret.0[0].attr, "passes-instantiation");
+        assert!(ret.0[0].drvs[0].ends_with("-passes-instantiation.drv"));
 
         assert_eq!(ret.1[0].0, "fails-instantiation");
         assert_eq!(
@@ -782,14 +1365,15 @@ mod tests {
 
     #[test]
     fn strict_sandboxing() {
-        let ret: Result<fs::File, fs::File> = nix().safely_build_attrs(
+        let (outcome, output) = nix().safely_build_attrs(
             build_path().as_path(),
             File::DefaultNixpkgs,
             vec![String::from("sandbox-violation")],
         );
 
+        assert_eq!(outcome, BuildOutcome::UnexpectedExit { code: Some(1) });
         assert_run(
-            ret,
+            Err(output),
             Expect::Fail,
             vec![
                 "error: while evaluating the attribute",
@@ -834,4 +1418,285 @@ mod tests {
             vec!["access to path '/fake'", "is forbidden in restricted mode"],
         );
     }
+
+    fn outcome(code: i32, output: &str) -> BuildOutcome {
+        let mut scanned = BuildOutput::new();
+        for line in output.lines() {
+            scanned.line(line);
+        }
+        scanned.outcome(&ExitStatus::from_raw(code << 8))
+    }
+
+    #[test]
+    fn build_outcomes() {
+        assert_eq!(outcome(0, "/nix/store/abc-hello"), BuildOutcome::Success);
+        assert_eq!(
+            outcome(
+                100,
+                "builder for '/nix/store/abc-hello.drv' failed with exit code 2"
+            ),
+            BuildOutcome::PermanentFailure
+        );
+        assert_eq!(
+            outcome(
+                101,
+                "building of '/nix/store/abc-hello.drv' timed out after 1800 seconds"
+            ),
+            BuildOutcome::TimedOut
+        );
+        assert_eq!(outcome(101, ""), BuildOutcome::TimedOut);
+        assert_eq!(
+            outcome(
+                101,
+                "error: building of '/nix/store/abc-hello.drv' timed out after 3600 seconds of silence"
+            ),
+            BuildOutcome::MaxSilentTimedOut
+        );
+        assert_eq!(
+            outcome(
+                100,
+                "cp: error writing 'out/lib/libhello.so': No space left on device
+builder for '/nix/store/abc-hello.drv' failed with exit code 1"
+            ),
+            BuildOutcome::OutOfDisk
+        );
+        assert_eq!(
+            outcome(
+                100,
+                "builder for '/nix/store/abc-hello.drv' failed due to signal 9 (Killed)"
+            ),
+            BuildOutcome::KilledBySignal { signal: 9 }
+        );
+        assert_eq!(
+            outcome(
+                100,
+                "builder for '/nix/store/abc-libhello.drv' failed with exit code 2
+cannot build derivation '/nix/store/abc-hello.drv': 1 dependencies couldn't be built
+error: build of '/nix/store/abc-hello.drv' failed"
+            ),
+            BuildOutcome::DependencyFailed
+        );
+        assert_eq!(
+            outcome(
+                1,
+                "error: 1 dependencies of derivation '/nix/store/abc-hello.drv' failed to build"
+            ),
+            BuildOutcome::DependencyFailed
+        );
+        assert_eq!(
+            outcome(
+                1,
+                "error: attribute 'hello' in selection path 'hello' not found"
+            ),
+            BuildOutcome::UnexpectedExit { code: Some(1) }
+        );
+        assert_eq!(
+            outcome(1, ""),
+            BuildOutcome::UnexpectedExit { code: Some(1) }
+        );
+        assert_eq!(
+            outcome(96, ""),
+            BuildOutcome::UnexpectedExit { code: Some(96) }
+        );
+        assert_eq!(
+            outcome(97, ""),
+            BuildOutcome::UnexpectedExit { code: Some(97) }
+        );
+        assert_eq!(outcome(102, ""), BuildOutcome::PermanentFailure);
+        assert_eq!(outcome(105, ""), BuildOutcome::TimedOut);
+        assert_eq!(
+            outcome(108, ""),
+            BuildOutcome::UnexpectedExit { code: Some(108) }
+        );
+    }
+
+    #[test]
+    fn build_errors() {
+        let mut output = BuildOutput::new();
+        for line in &[
+            "these derivations will be built:",
+            "  /nix/store/abc-libhello.drv",
+            "  /nix/store/abc-hello.drv",
+            "building '/nix/store/abc-libhello.drv'...",
+            "builder for '/nix/store/abc-libhello.drv' failed with exit code 2",
+            "cannot build derivation '/nix/store/abc-hello.drv': 1 dependencies couldn't be built",
+            "builder for ‘/nix/store/abc-libhello.drv’ failed with exit code 2",
+        ] {
+            output.line(line);
+        }
+        assert_eq!(
+            output.errors(),
+            &[NixError::BuilderFailed {
+                drv: String::from("/nix/store/abc-libhello.drv"),
+                exit_code: Some(2),
+            }]
+        );
+    }
+
+    #[test]
+    fn path_info_formats() {
+        let expected = vec![
+            PathInfo {
+                path: String::from("/nix/store/abc-hello"),
+                nar_size: 226560,
+            },
+            PathInfo {
+                path: String::from("/nix/store/def-glibc"),
+                nar_size: 28_999_656,
+            },
+        ];
+
+        let nix_2_3 = r#"[
+            {"path": "/nix/store/abc-hello", "narHash": "sha256:1", "narSize": 226560, "references": ["/nix/store/def-glibc"]},
+            {"path": "/nix/store/def-glibc", "narHash": "sha256:2", "narSize": 28999656, "references": []}
+        ]"#;
+        assert_eq!(
+            parse_path_info(&serde_json::from_str(nix_2_3).unwrap()),
+            Ok(expected.clone())
+        );
+
+        let nix_2_19 = r#"{
+            "/nix/store/abc-hello": {"narHash": "sha256:1", "narSize": 226560, "references": ["/nix/store/def-glibc"]},
+            "/nix/store/def-glibc": {"narHash": "sha256:2", "narSize": 28999656, "references": []}
+        }"#;
+        assert_eq!(
+            parse_path_info(&serde_json::from_str(nix_2_19).unwrap()),
+            Ok(expected)
+        );
+
+        let invalid = r#"{"/nix/store/abc-hello": null}"#;
+        assert!(parse_path_info(&serde_json::from_str(invalid).unwrap()).is_err());
+    }
+
+    #[test]
+    fn build_killed_by_signal() {
+        assert_eq!(
+            BuildOutput::new().outcome(&ExitStatus::from_raw(15)),
+            BuildOutcome::KilledBySignal { signal: 15 }
+        );
+    }
+
+    #[test]
+    fn parse_errors_evaluation() {
+        let output = "\
+error: attribute 'hello2' in selection path 'hello2' not found
+\u{1b}[31;1merror:\u{1b}[0m attribute ‘foo’ missing, at /nix/store/x/default.nix:3:5
+error: assertion failed at /home/ofborg/nixpkgs/pkgs/a.nix:4:1
+error: assertion (stdenv.isLinux) failed at /home/ofborg/nixpkgs/pkgs/b.nix:5:1
+error: assertion 'false' failed
+error: infinite recursion encountered, at undefined position
+error: access to path '/fake' is forbidden in restricted mode
+error: 'builtins.exec' is not allowed in restricted mode
+error: undefined variable 'bar' at /home/ofborg/nixpkgs/pkgs/c.nix:1:1
+error: infinite recursion encountered, at undefined position
+(use '--show-trace' to show detailed location information)
+";
+
+        assert_eq!(
+            parse_errors(output),
+            vec![
+                NixError::MissingAttribute {
+                    attr: String::from("hello2"),
+                },
+                NixError::MissingAttribute {
+                    attr: String::from("foo"),
+                },
+                NixError::AssertionFailed { assertion: None },
+                NixError::AssertionFailed {
+                    assertion: Some(String::from("stdenv.isLinux")),
+                },
+                NixError::AssertionFailed {
+                    assertion: Some(String::from("false")),
+                },
+                NixError::InfiniteRecursion,
+                NixError::RestrictedEval {
+                    resource: String::from("path '/fake'"),
+                },
+                NixError::RestrictedEval {
+                    resource: String::from("'builtins.exec'"),
+                },
+                NixError::Other {
+                    message: String::from(
+                        "undefined variable 'bar' at /home/ofborg/nixpkgs/pkgs/c.nix:1:1",
+                    ),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_errors_build() {
+        let output = "\
+hash mismatch in fixed-output derivation '/nix/store/abc-src':
+  wanted: sha256:0000000000000000000000000000000000000000000000000000
+  got:    sha256:1111111111111111111111111111111111111111111111111111
+output path '/nix/store/def-src' has sha256 hash 'ggg' when 'eee' was expected
+builder for '/nix/store/ghi-hello.drv' failed with exit code 1; last 10 log lines:
+builder for '/nix/store/jkl-world.drv' failed due to signal 9 (Killed)
+cannot build derivation '/nix/store/mno-env.drv': 2 dependencies couldn't be built
+error: build of '/nix/store/mno-env.drv' failed
+";
+
+        let errors = parse_errors(output);
+        assert_eq!(
+            errors,
+            vec![
+                NixError::HashMismatch {
+                    path: String::from("/nix/store/abc-src"),
+                    expected: String::from(
+                        "sha256:0000000000000000000000000000000000000000000000000000"
+                    ),
+                    got: String::from(
+                        "sha256:1111111111111111111111111111111111111111111111111111"
+                    ),
+                },
+                NixError::HashMismatch {
+                    path: String::from("/nix/store/def-src"),
+                    expected: String::from("eee"),
+                    got: String::from("ggg"),
+                },
+                NixError::BuilderFailed {
+                    drv: String::from("/nix/store/ghi-hello.drv"),
+                    exit_code: Some(1),
+                },
+                NixError::BuilderFailed {
+                    drv: String::from("/nix/store/jkl-world.drv"),
+                    exit_code: None,
+                },
+                NixError::Other {
+                    message: String::from("build of '/nix/store/mno-env.drv' failed"),
+                },
+            ]
+        );
+        assert_eq!(
+            errors[2].to_string(),
+            "Builder for `/nix/store/ghi-hello.drv` failed with exit code 1"
+        );
+    }
+
+    #[test]
+    fn parse_errors_nix_2_4() {
+        let output = "\
+error: hash mismatch in fixed-output derivation '/nix/store/abc-src.drv':
+         specified: sha256-AAAA
+            got:    sha256-BBBB
+error: builder for '/nix/store/ghi-hello.drv' failed with exit code 2;
+       last 10 log lines:
+";
+
+        assert_eq!(
+            parse_errors(output),
+            vec![
+                NixError::HashMismatch {
+                    path: String::from("/nix/store/abc-src.drv"),
+                    expected: String::from("sha256-AAAA"),
+                    got: String::from("sha256-BBBB"),
+                },
+                NixError::BuilderFailed {
+                    drv: String::from("/nix/store/ghi-hello.drv"),
+                    exit_code: Some(2),
+                },
+            ]
+        );
+    }
 }
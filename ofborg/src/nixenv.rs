@@ -13,6 +13,10 @@ use std::io::SeekFrom;
 use std::io::Write;
 use std::path::PathBuf;
 
+/// The expression listing every attribute's out paths, placed in to the
+/// project root before evaluating.
+pub const OUTPATHS_NIX: &[u8] = include_bytes!("outpaths.nix");
+
 pub struct HydraNixEnv {
     path: PathBuf,
     nix: nix::Nix,
@@ -52,7 +56,7 @@ impl HydraNixEnv {
     /// emulates Hydra's behavior.
     fn place_nix(&self) -> Result<(), std::io::Error> {
         let mut file = File::create(self.outpath_nix_path())?;
-        file.write_all(include_bytes!("outpaths.nix"))?;
+        file.write_all(OUTPATHS_NIX)?;
 
         Ok(())
     }
@@ -2,7 +2,7 @@ use separator::Separatable;
 /// Statistics emitted by Nix when NIX_SHOW_STATS=1
 use std::collections::HashMap;
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EvaluationStats {
     /// Number of CPU seconds spent during evaluation.
     #[serde(rename = "cpuTime")]
@@ -32,14 +32,14 @@ pub struct EvaluationStats {
     pub nr_function_calls: u64,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Environments {
     pub number: u64,
     pub elements: u64,
     pub bytes: u64,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Lists {
     pub elements: u64,
 
@@ -48,7 +48,7 @@ pub struct Lists {
     pub concats: u64,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Values {
     pub number: u64,
 
@@ -56,7 +56,7 @@ pub struct Values {
     pub bytes: u64,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Symbols {
     pub number: u64,
 
@@ -64,7 +64,7 @@ pub struct Symbols {
     pub bytes: u64,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Sets {
     pub number: u64,
     pub elements: u64,
@@ -73,7 +73,7 @@ pub struct Sets {
     pub bytes: u64,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Sizes {
     #[serde(rename = "Env")]
     pub env: u64,
@@ -88,7 +88,7 @@ pub struct Sizes {
     pub attr: u64,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GarbageCollector {
     #[serde(rename = "heapSize")]
     pub heap_size: u64,
@@ -96,6 +96,22 @@ pub struct GarbageCollector {
     pub total_bytes: u64,
 }
 
+/// A statistic before and after, by its unit.
+enum Compared {
+    Count(u64, u64),
+    Seconds(f32, f32),
+}
+
+/// A statistic which increased by more than its threshold, in percent.
+#[derive(Debug, PartialEq)]
+pub struct Regression {
+    pub stat: String,
+    pub before: f64,
+    pub after: f64,
+    pub increase: f64,
+    pub threshold: f64,
+}
+
 pub struct EvaluationStatsDiff<'a> {
     left: &'a EvaluationStats,
     right: &'a EvaluationStats,
@@ -109,6 +125,122 @@ impl<'a> EvaluationStatsDiff<'a> {
         EvaluationStatsDiff { left, right }
     }
 
+    /// The statistics which increased by more than their threshold,
+    /// a percentage keyed by the statistic's name in the `markdown`
+    /// table, like `gc-heapSize` or `cpuTime`.
+    pub fn regressions(&self, thresholds: &HashMap<String, f64>) -> Vec<Regression> {
+        let compared = self.compared();
+
+        let mut regressions: Vec<Regression> = vec![];
+        for (stat, threshold) in thresholds {
+            let (before, after) = match compared.iter().find(|&&(key, _)| key == stat) {
+                Some(&(_, Compared::Count(left, right))) => (left as f64, right as f64),
+                Some(&(_, Compared::Seconds(left, right))) => (f64::from(left), f64::from(right)),
+                None => {
+                    warn!("Unknown evaluation statistic {:?} has a threshold", stat);
+                    continue;
+                }
+            };
+
+            if before <= 0.0 {
+                continue;
+            }
+
+            let increase = (after - before) / before * 100.0;
+            if increase > *threshold {
+                regressions.push(Regression {
+                    stat: stat.clone(),
+                    before,
+                    after,
+                    increase,
+                    threshold: *threshold,
+                });
+            }
+        }
+
+        regressions.sort_by(|a, b| a.stat.cmp(&b.stat));
+        regressions
+    }
+
+    fn compared(&self) -> Vec<(&'static str, Compared)> {
+        let (l, r) = (self.left, self.right);
+
+        vec![
+            ("cpuTime", Compared::Seconds(l.cpu_time, r.cpu_time)),
+            ("envs-number", Compared::Count(l.envs.number, r.envs.number)),
+            (
+                "envs-elements",
+                Compared::Count(l.envs.elements, r.envs.elements),
+            ),
+            ("envs-bytes", Compared::Count(l.envs.bytes, r.envs.bytes)),
+            (
+                "list-elements",
+                Compared::Count(l.list.elements, r.list.elements),
+            ),
+            ("list-bytes", Compared::Count(l.list.bytes, r.list.bytes)),
+            (
+                "list-concats",
+                Compared::Count(l.list.concats, r.list.concats),
+            ),
+            (
+                "values-number",
+                Compared::Count(l.values.number, r.values.number),
+            ),
+            (
+                "values-bytes",
+                Compared::Count(l.values.bytes, r.values.bytes),
+            ),
+            (
+                "symbols-number",
+                Compared::Count(l.symbols.number, r.symbols.number),
+            ),
+            (
+                "symbols-bytes",
+                Compared::Count(l.symbols.bytes, r.symbols.bytes),
+            ),
+            ("sets-number", Compared::Count(l.sets.number, r.sets.number)),
+            ("sets-bytes", Compared::Count(l.sets.bytes, r.sets.bytes)),
+            (
+                "sets-elements",
+                Compared::Count(l.sets.elements, r.sets.elements),
+            ),
+            ("sizes-Env", Compared::Count(l.sizes.env, r.sizes.env)),
+            ("sizes-Value", Compared::Count(l.sizes.value, r.sizes.value)),
+            (
+                "sizes-Bindings",
+                Compared::Count(l.sizes.bindings, r.sizes.bindings),
+            ),
+            ("sizes-Attr", Compared::Count(l.sizes.attr, r.sizes.attr)),
+            (
+                "nrOpUpdates",
+                Compared::Count(l.nr_op_updates, r.nr_op_updates),
+            ),
+            (
+                "nrOpUpdateValuesCopied",
+                Compared::Count(l.nr_op_update_values_copied, r.nr_op_update_values_copied),
+            ),
+            ("nrThunks", Compared::Count(l.nr_thunks, r.nr_thunks)),
+            ("nrAvoided", Compared::Count(l.nr_avoided, r.nr_avoided)),
+            ("nrLookups", Compared::Count(l.nr_lookups, r.nr_lookups)),
+            (
+                "nrPrimOpCalls",
+                Compared::Count(l.nr_prim_op_calls, r.nr_prim_op_calls),
+            ),
+            (
+                "nrFunctionCalls",
+                Compared::Count(l.nr_function_calls, r.nr_function_calls),
+            ),
+            (
+                "gc-heapSize",
+                Compared::Count(l.gc.heap_size, r.gc.heap_size),
+            ),
+            (
+                "gc-totalBytes",
+                Compared::Count(l.gc.total_bytes, r.gc.total_bytes),
+            ),
+        ]
+    }
+
     pub fn markdown(&self) -> String {
         struct Row {
             before: String,
@@ -185,124 +317,13 @@ impl<'a> EvaluationStatsDiff<'a> {
         }
 
         let mut data: HashMap<&str, Row> = HashMap::new();
-        data.insert(
-            "cpuTime",
-            Row::from_f32(self.left.cpu_time, self.right.cpu_time),
-        );
-
-        data.insert(
-            "envs-number",
-            Row::from_u64(self.left.envs.number, self.right.envs.number),
-        );
-        data.insert(
-            "envs-elements",
-            Row::from_u64(self.left.envs.elements, self.right.envs.elements),
-        );
-        data.insert(
-            "envs-bytes",
-            Row::from_u64(self.left.envs.bytes, self.right.envs.bytes),
-        );
-
-        data.insert(
-            "list-elements",
-            Row::from_u64(self.left.list.elements, self.right.list.elements),
-        );
-        data.insert(
-            "list-bytes",
-            Row::from_u64(self.left.list.bytes, self.right.list.bytes),
-        );
-        data.insert(
-            "list-concats",
-            Row::from_u64(self.left.list.concats, self.right.list.concats),
-        );
-
-        data.insert(
-            "values-number",
-            Row::from_u64(self.left.values.number, self.right.values.number),
-        );
-        data.insert(
-            "values-bytes",
-            Row::from_u64(self.left.values.bytes, self.right.values.bytes),
-        );
-
-        data.insert(
-            "symbols-number",
-            Row::from_u64(self.left.symbols.number, self.right.symbols.number),
-        );
-        data.insert(
-            "symbols-bytes",
-            Row::from_u64(self.left.symbols.bytes, self.right.symbols.bytes),
-        );
-
-        data.insert(
-            "sets-number",
-            Row::from_u64(self.left.sets.number, self.right.sets.number),
-        );
-        data.insert(
-            "sets-bytes",
-            Row::from_u64(self.left.sets.bytes, self.right.sets.bytes),
-        );
-        data.insert(
-            "sets-elements",
-            Row::from_u64(self.left.sets.elements, self.right.sets.elements),
-        );
-
-        data.insert(
-            "sizes-Env",
-            Row::from_u64(self.left.sizes.env, self.right.sizes.env),
-        );
-        data.insert(
-            "sizes-Value",
-            Row::from_u64(self.left.sizes.value, self.right.sizes.value),
-        );
-        data.insert(
-            "sizes-Bindings",
-            Row::from_u64(self.left.sizes.bindings, self.right.sizes.bindings),
-        );
-        data.insert(
-            "sizes-Attr",
-            Row::from_u64(self.left.sizes.attr, self.right.sizes.attr),
-        );
-
-        data.insert(
-            "nrOpUpdates",
-            Row::from_u64(self.left.nr_op_updates, self.right.nr_op_updates),
-        );
-        data.insert(
-            "nrOpUpdateValuesCopied",
-            Row::from_u64(
-                self.left.nr_op_update_values_copied,
-                self.right.nr_op_update_values_copied,
-            ),
-        );
-        data.insert(
-            "nrThunks",
-            Row::from_u64(self.left.nr_thunks, self.right.nr_thunks),
-        );
-        data.insert(
-            "nrAvoided",
-            Row::from_u64(self.left.nr_avoided, self.right.nr_avoided),
-        );
-        data.insert(
-            "nrLookups",
-            Row::from_u64(self.left.nr_lookups, self.right.nr_lookups),
-        );
-        data.insert(
-            "nrPrimOpCalls",
-            Row::from_u64(self.left.nr_prim_op_calls, self.right.nr_prim_op_calls),
-        );
-        data.insert(
-            "nrFunctionCalls",
-            Row::from_u64(self.left.nr_function_calls, self.right.nr_function_calls),
-        );
-        data.insert(
-            "gc-heapSize",
-            Row::from_u64(self.left.gc.heap_size, self.right.gc.heap_size),
-        );
-        data.insert(
-            "gc-totalBytes",
-            Row::from_u64(self.left.gc.total_bytes, self.right.gc.total_bytes),
-        );
+        for (key, compared) in self.compared() {
+            let row = match compared {
+                Compared::Count(left, right) => Row::from_u64(left, right),
+                Compared::Seconds(left, right) => Row::from_f32(left, right),
+            };
+            data.insert(key, row);
+        }
 
         let (keylen, beforelen, afterlen, difflen, diff_pctlen): (
             usize,
@@ -361,6 +382,7 @@ mod tests {
     use super::EvaluationStats;
     use super::EvaluationStatsDiff;
     use serde_json;
+    use std::collections::HashMap;
 
     const EXAMPLE: &'static str = r#"
 {
@@ -494,6 +516,30 @@ mod tests {
         assert_eq!(load.gc.total_bytes, 24191819392);
     }
 
+    #[test]
+    fn regressions() {
+        let before: EvaluationStats = serde_json::from_str(EXAMPLE2).unwrap();
+        let after: EvaluationStats = serde_json::from_str(EXAMPLE).unwrap();
+        let diff = EvaluationStatsDiff::compare(&before, &after);
+
+        let mut thresholds = HashMap::new();
+        thresholds.insert(String::from("gc-heapSize"), 5.0);
+        thresholds.insert(String::from("cpuTime"), 10.0);
+        thresholds.insert(String::from("sizes-Value"), 0.0);
+        thresholds.insert(String::from("bogus"), 1.0);
+
+        let regressions = diff.regressions(&thresholds);
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(regressions[0].stat, "gc-heapSize");
+        assert_eq!(regressions[0].before, 11433721856.0);
+        assert_eq!(regressions[0].after, 12104687616.0);
+        assert!((regressions[0].increase - 5.87).abs() < 0.01);
+
+        assert!(EvaluationStatsDiff::compare(&after, &before)
+            .regressions(&thresholds)
+            .is_empty());
+    }
+
     fn diff_text(left: &str, right: &str) {
         println!("left:\n{}", left);
         println!("right:\n{}", right);
@@ -0,0 +1,228 @@
+use crate::nixenv::OUTPATHS_NIX;
+use crate::nixstats::EvaluationStats;
+use md5;
+use ofborg::outpathdiff::{PackageArch, PackageOutPaths};
+use serde_json;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Changes whenever the way out paths are evaluated changes in a way
+/// which isn't visible in `outpaths.nix`, so stale entries are no
+/// longer used.
+const EVALUATOR_VERSION: u32 = 1;
+
+/// Remembers the out paths and evaluation statistics of target branch
+/// commits, so PRs to the same commit only evaluate it once.
+pub struct OutPathCache {
+    root: PathBuf,
+    max_entries: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    outpaths: Vec<(PackageArch, String)>,
+    stats: EvaluationStats,
+}
+
+impl OutPathCache {
+    pub fn new(root: PathBuf, max_entries: usize) -> OutPathCache {
+        OutPathCache { root, max_entries }
+    }
+
+    /// The key of a target branch commit, which also covers the
+    /// evaluator, as a new `outpaths.nix` can evaluate the same commit
+    /// differently.
+    pub fn key(base_sha: &str) -> String {
+        let mut evaluator = format!("{}\n", EVALUATOR_VERSION).into_bytes();
+        evaluator.extend_from_slice(OUTPATHS_NIX);
+
+        format!("{}-{:x}", base_sha, md5::compute(&evaluator))
+    }
+
+    /// The out paths and statistics stored for the key, if any. An
+    /// entry which can't be read counts as missing.
+    pub fn get(&self, key: &str) -> Option<(PackageOutPaths, EvaluationStats)> {
+        let file = match File::open(self.entry_path(key)) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!("Failed to read cached out paths {}: {}", key, e);
+                return None;
+            }
+        };
+
+        match serde_json::from_reader::<_, Entry>(file) {
+            Ok(entry) => Some((entry.outpaths.into_iter().collect(), entry.stats)),
+            Err(e) => {
+                warn!("Failed to parse cached out paths {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    /// Stores the out paths and statistics for the key, and forgets the
+    /// oldest entries beyond `max_entries`. Failures are only logged:
+    /// the next PR to the commit evaluates it again.
+    pub fn put(&self, key: &str, value: &(PackageOutPaths, EvaluationStats)) {
+        if let Err(e) = self.write(key, value) {
+            warn!("Failed to cache out paths {}: {}", key, e);
+            return;
+        }
+
+        if let Err(e) = self.prune() {
+            warn!("Failed to prune the out path cache: {}", e);
+        }
+    }
+
+    fn write(&self, key: &str, value: &(PackageOutPaths, EvaluationStats)) -> io::Result<()> {
+        fs::create_dir_all(&self.root)?;
+
+        let (ref outpaths, ref stats) = *value;
+        let entry = Entry {
+            outpaths: outpaths
+                .iter()
+                .map(|(arch, path)| (arch.clone(), path.clone()))
+                .collect(),
+            stats: stats.clone(),
+        };
+
+        // Written next to the entry and renamed, so evaluators sharing
+        // the cache never read a partial entry. Each write has its own
+        // file, as evaluators may write the same entry at once.
+        let tmp = self.root.join(format!(".{}.{}.tmp", key, Uuid::new_v4()));
+        serde_json::to_writer(File::create(&tmp)?, &entry)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        fs::rename(&tmp, self.entry_path(key))
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        let mut entries = vec![];
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().map(|ext| ext == "json").unwrap_or(false) {
+                entries.push((entry.metadata()?.modified()?, path));
+            }
+        }
+
+        if entries.len() <= self.max_entries {
+            return Ok(());
+        }
+
+        entries.sort();
+        let excess = entries.len() - self.max_entries;
+        for (_, path) in entries.into_iter().take(excess) {
+            info!("Removing cached out paths {:?}", path);
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.root.join(format!("{}.json", key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ofborg::test_scratch::TestScratch;
+    use std::collections::HashMap;
+    use std::process::Command;
+
+    const STATS: &str = r#"{
+        "cpuTime": 135.2,
+        "envs": {"number": 130714125, "elements": 183953876, "bytes": 3563057008},
+        "list": {"elements": 207421516, "bytes": 1659372128, "concats": 7194150},
+        "values": {"number": 260454370, "bytes": 6250904880},
+        "symbols": {"number": 372918, "bytes": 16324262},
+        "sets": {"number": 27310541, "bytes": 7134676648, "elements": 288174680},
+        "sizes": {"Env": 16, "Value": 24, "Bindings": 8, "Attr": 24},
+        "nrOpUpdates": 11883339,
+        "nrOpUpdateValuesCopied": 208834564,
+        "nrThunks": 173325665,
+        "nrAvoided": 177840681,
+        "nrLookups": 75292052,
+        "nrPrimOpCalls": 85571252,
+        "nrFunctionCalls": 115193164,
+        "gc": {"heapSize": 12104687616, "totalBytes": 24191819392}
+    }"#;
+
+    fn outpaths(path: &str) -> (PackageOutPaths, EvaluationStats) {
+        let stats: EvaluationStats = serde_json::from_str(STATS).unwrap();
+
+        let mut outpaths = HashMap::new();
+        outpaths.insert(
+            PackageArch {
+                package: String::from("hello"),
+                architecture: String::from("x86_64-linux"),
+            },
+            String::from(path),
+        );
+
+        (outpaths, stats)
+    }
+
+    #[test]
+    fn key_covers_the_evaluator() {
+        let key = OutPathCache::key("abc123");
+        assert!(key.starts_with("abc123-"));
+        assert_eq!(key, OutPathCache::key("abc123"));
+        assert_ne!(key, OutPathCache::key("def456"));
+    }
+
+    #[test]
+    fn roundtrip() {
+        let scratch = TestScratch::new_dir("outpathcache-roundtrip");
+        let cache = OutPathCache::new(scratch.path(), 10);
+
+        assert!(cache.get("abc123").is_none());
+
+        cache.put("abc123", &outpaths("/nix/store/abc-hello"));
+        let (cached, _) = cache.get("abc123").unwrap();
+        assert_eq!(cached, outpaths("/nix/store/abc-hello").0);
+    }
+
+    #[test]
+    fn corrupt_entry_is_a_miss() {
+        let scratch = TestScratch::new_dir("outpathcache-corrupt");
+        let cache = OutPathCache::new(scratch.path(), 10);
+
+        fs::create_dir_all(scratch.path()).unwrap();
+        fs::write(scratch.path().join("abc123.json"), "{").unwrap();
+        assert!(cache.get("abc123").is_none());
+    }
+
+    #[test]
+    fn prunes_oldest() {
+        let scratch = TestScratch::new_dir("outpathcache-prune");
+        let cache = OutPathCache::new(scratch.path(), 3);
+
+        for (key, mtime) in &[
+            ("one", "201901010000"),
+            ("two", "201902010000"),
+            ("three", "201903010000"),
+        ] {
+            cache.put(key, &outpaths("/nix/store/abc-hello"));
+            let status = Command::new("touch")
+                .arg("-t")
+                .arg(mtime)
+                .arg(cache.entry_path(key))
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+
+        let cache = OutPathCache::new(scratch.path(), 2);
+        cache.put("four", &outpaths("/nix/store/abc-hello"));
+
+        assert!(cache.get("one").is_none());
+        assert!(cache.get("two").is_none());
+        assert!(cache.get("three").is_some());
+        assert!(cache.get("four").is_some());
+    }
+}
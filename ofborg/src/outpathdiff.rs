@@ -2,6 +2,7 @@ use crate::nixenv::Error as NixEnvError;
 use crate::nixenv::HydraNixEnv;
 use crate::nixstats::{EvaluationStats, EvaluationStatsDiff};
 use ofborg::nix;
+use ofborg::outpathcache::OutPathCache;
 use std::collections::{HashMap, HashSet};
 use std::io::BufRead;
 
@@ -27,6 +28,27 @@ impl OutPathDiff {
         Ok(())
     }
 
+    /// Like `find_before`, but reuses the out paths of `base_sha` when
+    /// the cache has them. Returns whether it did.
+    pub fn find_before_cached(
+        &mut self,
+        cache: &OutPathCache,
+        base_sha: &str,
+    ) -> Result<bool, NixEnvError> {
+        let key = OutPathCache::key(base_sha);
+
+        if let Some(original) = cache.get(&key) {
+            info!("Using the cached out paths of {}", base_sha);
+            self.original = Some(original);
+            return Ok(true);
+        }
+
+        let original = self.run()?;
+        cache.put(&key, &original);
+        self.original = Some(original);
+        Ok(false)
+    }
+
     pub fn find_after(&mut self) -> Result<(), NixEnvError> {
         if self.original.is_none() {
             debug!("Before is None, not bothering with After");
@@ -72,6 +94,21 @@ impl OutPathDiff {
         }
     }
 
+    /// The systems the PR was evaluated on.
+    pub fn architectures(&self) -> Vec<String> {
+        let mut architectures: Vec<String> = vec![];
+        if let Some((ref cur, _)) = self.current {
+            for key in cur.keys() {
+                if !architectures.contains(&key.architecture) {
+                    architectures.push(key.architecture.clone());
+                }
+            }
+        }
+
+        architectures.sort();
+        architectures
+    }
+
     pub fn calculate_rebuild(&self) -> Option<Vec<PackageArch>> {
         let mut rebuild: Vec<PackageArch> = vec![];
 
@@ -101,7 +138,7 @@ impl OutPathDiff {
 
 pub type PackageOutPaths = HashMap<PackageArch, OutPath>;
 
-#[derive(Debug, PartialEq, Hash, Eq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Hash, Eq, Clone)]
 pub struct PackageArch {
     pub package: Package,
     pub architecture: Architecture,
@@ -0,0 +1,406 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How many builds a tier of the ACL may request through comments, as
+/// configured in the tier's `quota`. Each `build` or `test` command
+/// counts as one build, however many systems it runs on.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QuotaPolicy {
+    /// The length of the window the limits apply to.
+    pub window_seconds: u64,
+
+    /// The builds one user may request on one repository within the
+    /// window.
+    #[serde(default)]
+    pub builds_per_user: Option<usize>,
+
+    /// The builds which may be requested for one PR within the window,
+    /// by anyone.
+    #[serde(default)]
+    pub builds_per_pr: Option<usize>,
+}
+
+/// Why a build request was refused.
+#[derive(Debug, PartialEq)]
+pub enum Throttled {
+    User { limit: usize, window: Duration },
+    Pr { limit: usize, window: Duration },
+}
+
+impl Throttled {
+    /// The limit which was hit, as used in stats.
+    pub fn limit_name(&self) -> String {
+        match *self {
+            Throttled::User { .. } => String::from("user"),
+            Throttled::Pr { .. } => String::from("pr"),
+        }
+    }
+}
+
+impl fmt::Display for Throttled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Throttled::User { limit, window } => write!(
+                f,
+                "you have already requested {} builds in the last {}",
+                limit,
+                describe_window(window)
+            ),
+            Throttled::Pr { limit, window } => write!(
+                f,
+                "{} builds have already been requested for this PR in the last {}",
+                limit,
+                describe_window(window)
+            ),
+        }
+    }
+}
+
+fn describe_window(window: Duration) -> String {
+    let seconds = window.as_secs();
+    if seconds < 120 {
+        format!("{} seconds", seconds)
+    } else {
+        format!("{} minutes", seconds / 60)
+    }
+}
+
+/// The builds requested recently, by user of each repository and by PR.
+#[derive(Default)]
+pub struct Quotas {
+    by_user: HashMap<(String, String), VecDeque<Instant>>,
+    by_pr: HashMap<(String, u64), VecDeque<Instant>>,
+    /// The longest window of the policies used so far. Requests older
+    /// than it don't count towards any limit.
+    longest_window: Duration,
+}
+
+impl Quotas {
+    pub fn new() -> Quotas {
+        Default::default()
+    }
+
+    /// Record a build requested by the user for the PR at `now`, or
+    /// refuse it if the policy's limits have been reached.
+    pub fn take(
+        &mut self,
+        policy: &QuotaPolicy,
+        user: &str,
+        repo: &str,
+        pr: u64,
+        now: Instant,
+    ) -> Result<(), Throttled> {
+        let window = Duration::from_secs(policy.window_seconds);
+        self.longest_window = self.longest_window.max(window);
+        self.forget_expired(now);
+
+        // Older requests are kept for the policies with longer windows.
+        let by_user = self
+            .by_user
+            .entry((repo.to_lowercase(), user.to_lowercase()))
+            .or_default();
+        if let Some(limit) = policy.builds_per_user {
+            if count_since(by_user, now, window) >= limit {
+                return Err(Throttled::User { limit, window });
+            }
+        }
+
+        let by_pr = self.by_pr.entry((repo.to_lowercase(), pr)).or_default();
+        if let Some(limit) = policy.builds_per_pr {
+            if count_since(by_pr, now, window) >= limit {
+                return Err(Throttled::Pr { limit, window });
+            }
+        }
+
+        by_user.push_back(now);
+        by_pr.push_back(now);
+        Ok(())
+    }
+
+    /// Forget the requests which no longer count, and the users and
+    /// PRs left without any.
+    fn forget_expired(&mut self, now: Instant) {
+        let window = self.longest_window;
+        self.by_user.retain(|_, requests| {
+            forget_before(requests, now, window);
+            !requests.is_empty()
+        });
+        self.by_pr.retain(|_, requests| {
+            forget_before(requests, now, window);
+            !requests.is_empty()
+        });
+    }
+}
+
+/// How many of the requests were made within the window before `now`.
+fn count_since(requests: &VecDeque<Instant>, now: Instant, window: Duration) -> usize {
+    requests
+        .iter()
+        .filter(|&&requested| now.duration_since(requested) < window)
+        .count()
+}
+
+fn forget_before(requests: &mut VecDeque<Instant>, now: Instant, window: Duration) {
+    while let Some(&oldest) = requests.front() {
+        if now.duration_since(oldest) < window {
+            break;
+        }
+        requests.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> QuotaPolicy {
+        QuotaPolicy {
+            window_seconds: 3600,
+            builds_per_user: Some(2),
+            builds_per_pr: Some(3),
+        }
+    }
+
+    #[test]
+    fn user_limit() {
+        let mut quotas = Quotas::new();
+        let now = Instant::now();
+
+        assert_eq!(
+            quotas.take(&policy(), "alice", "nixos/nixpkgs", 1, now),
+            Ok(())
+        );
+        assert_eq!(
+            quotas.take(&policy(), "Alice", "nixos/nixpkgs", 2, now),
+            Ok(())
+        );
+        assert_eq!(
+            quotas.take(&policy(), "alice", "nixos/nixpkgs", 3, now),
+            Err(Throttled::User {
+                limit: 2,
+                window: Duration::from_secs(3600)
+            })
+        );
+        assert_eq!(
+            quotas.take(&policy(), "bob", "nixos/nixpkgs", 3, now),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn pr_limit() {
+        let mut quotas = Quotas::new();
+        let now = Instant::now();
+
+        assert_eq!(
+            quotas.take(&policy(), "alice", "nixos/nixpkgs", 1, now),
+            Ok(())
+        );
+        assert_eq!(
+            quotas.take(&policy(), "bob", "nixos/nixpkgs", 1, now),
+            Ok(())
+        );
+        assert_eq!(
+            quotas.take(&policy(), "carol", "NixOS/nixpkgs", 1, now),
+            Ok(())
+        );
+        assert_eq!(
+            quotas.take(&policy(), "dave", "nixos/nixpkgs", 1, now),
+            Err(Throttled::Pr {
+                limit: 3,
+                window: Duration::from_secs(3600)
+            })
+        );
+        assert_eq!(
+            quotas.take(&policy(), "dave", "nixos/nixpkgs", 2, now),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn throttled_requests_are_not_counted() {
+        let mut quotas = Quotas::new();
+        let now = Instant::now();
+        let policy = QuotaPolicy {
+            window_seconds: 3600,
+            builds_per_user: None,
+            builds_per_pr: Some(1),
+        };
+
+        assert_eq!(
+            quotas.take(&policy, "alice", "nixos/nixpkgs", 1, now),
+            Ok(())
+        );
+        assert!(quotas
+            .take(&policy, "alice", "nixos/nixpkgs", 1, now)
+            .is_err());
+        assert!(quotas
+            .take(&policy, "alice", "nixos/nixpkgs", 1, now)
+            .is_err());
+        assert_eq!(
+            quotas.take(&policy, "alice", "nixos/nixpkgs", 2, now),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn window_expiry() {
+        let mut quotas = Quotas::new();
+        let start = Instant::now();
+
+        assert_eq!(
+            quotas.take(&policy(), "alice", "nixos/nixpkgs", 1, start),
+            Ok(())
+        );
+        assert_eq!(
+            quotas.take(
+                &policy(),
+                "alice",
+                "nixos/nixpkgs",
+                1,
+                start + Duration::from_secs(1800)
+            ),
+            Ok(())
+        );
+        assert!(quotas
+            .take(
+                &policy(),
+                "alice",
+                "nixos/nixpkgs",
+                1,
+                start + Duration::from_secs(3599)
+            )
+            .is_err());
+        assert_eq!(
+            quotas.take(
+                &policy(),
+                "alice",
+                "nixos/nixpkgs",
+                1,
+                start + Duration::from_secs(3600)
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn expired_users_and_prs_are_forgotten() {
+        let mut quotas = Quotas::new();
+        let start = Instant::now();
+
+        assert_eq!(
+            quotas.take(&policy(), "alice", "nixos/nixpkgs", 1, start),
+            Ok(())
+        );
+        assert_eq!(
+            quotas.take(
+                &policy(),
+                "bob",
+                "nixos/nixpkgs",
+                2,
+                start + Duration::from_secs(3600)
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            quotas.by_user.keys().collect::<Vec<_>>(),
+            vec![&(String::from("nixos/nixpkgs"), String::from("bob"))]
+        );
+        assert_eq!(
+            quotas.by_pr.keys().collect::<Vec<_>>(),
+            vec![&(String::from("nixos/nixpkgs"), 2)]
+        );
+    }
+
+    #[test]
+    fn user_limits_per_repo() {
+        let mut quotas = Quotas::new();
+        let now = Instant::now();
+        let strict = QuotaPolicy {
+            window_seconds: 3600,
+            builds_per_user: Some(1),
+            builds_per_pr: None,
+        };
+
+        assert_eq!(
+            quotas.take(&policy(), "alice", "nixos/nixpkgs", 1, now),
+            Ok(())
+        );
+        assert_eq!(
+            quotas.take(&policy(), "alice", "nixos/nixpkgs", 2, now),
+            Ok(())
+        );
+        assert_eq!(
+            quotas.take(&strict, "alice", "nixos/ofborg", 1, now),
+            Ok(())
+        );
+        assert_eq!(
+            quotas.take(&strict, "alice", "nixos/ofborg", 2, now),
+            Err(Throttled::User {
+                limit: 1,
+                window: Duration::from_secs(3600)
+            })
+        );
+    }
+
+    #[test]
+    fn shorter_windows_keep_longer_history() {
+        let mut quotas = Quotas::new();
+        let start = Instant::now();
+        let daily = QuotaPolicy {
+            window_seconds: 24 * 3600,
+            builds_per_user: Some(2),
+            builds_per_pr: None,
+        };
+        let hourly = QuotaPolicy {
+            window_seconds: 3600,
+            builds_per_user: Some(2),
+            builds_per_pr: None,
+        };
+
+        assert_eq!(
+            quotas.take(&daily, "alice", "nixos/nixpkgs", 1, start),
+            Ok(())
+        );
+        assert_eq!(
+            quotas.take(
+                &hourly,
+                "alice",
+                "nixos/nixpkgs",
+                1,
+                start + Duration::from_secs(7200)
+            ),
+            Ok(())
+        );
+        assert!(quotas
+            .take(
+                &daily,
+                "alice",
+                "nixos/nixpkgs",
+                1,
+                start + Duration::from_secs(7200)
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn describe_throttling() {
+        assert_eq!(
+            Throttled::User {
+                limit: 5,
+                window: Duration::from_secs(3600)
+            }
+            .to_string(),
+            "you have already requested 5 builds in the last 60 minutes"
+        );
+        assert_eq!(
+            Throttled::Pr {
+                limit: 2,
+                window: Duration::from_secs(600)
+            }
+            .to_string(),
+            "2 builds have already been requested for this PR in the last 10 minutes"
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use hubcaps::comments::{CommentListOptions, CommentOptions};
+use hubcaps::issues::IssueRef;
+use ofborg::checkrun::{rebuild_counts, truncate};
+use ofborg::message::evaluationreport::EvaluationReport;
+use ofborg::outpathdiff::PackageArch;
+use std::collections::BTreeMap;
+
+/// Marks the rebuild summary among the PR's comments.
+pub const MARKER: &str = "<!-- grahamcofborg-rebuild-summary -->";
+
+/// GitHub refuses comments longer than this.
+const MAX_COMMENT_LENGTH: usize = 65536;
+
+/// How many entries of each collapsed list are shown. The full list of
+/// rebuilds is linked from the comment.
+const MAX_LISTED: usize = 500;
+
+/// The rebuild summary of an evaluation, if it compared the out paths.
+pub fn comment(report: &EvaluationReport, changed_paths_url: Option<&str>) -> Option<String> {
+    let rebuilds = match report.rebuilds {
+        Some(ref rebuilds) => rebuilds,
+        None => return None,
+    };
+
+    let mut lines: Vec<String> = vec![
+        String::from(MARKER),
+        format!("### Rebuild summary of {}", report.pr.head_sha),
+        String::from(""),
+    ];
+
+    if rebuilds.is_empty() {
+        lines.push(String::from("No packages are rebuilt."));
+    } else {
+        lines.extend(rebuild_counts(rebuilds));
+        lines.push(String::from(""));
+
+        let mut by_system: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for rebuild in rebuilds {
+            by_system
+                .entry(&rebuild.architecture)
+                .or_default()
+                .push(format!("`{}`", rebuild.package));
+        }
+        for (system, attrs) in by_system {
+            lines.extend(details(&format!("Rebuilds on {}", system), &attrs));
+        }
+
+        if let Some(url) = changed_paths_url {
+            lines.push(format!("[All changed paths]({})", url));
+        }
+    }
+    lines.push(String::from(""));
+
+    if let Some(ref added) = report.added {
+        if !added.is_empty() {
+            lines.extend(details("Added packages", &packages(added)));
+        }
+    }
+    if let Some(ref removed) = report.removed {
+        if !removed.is_empty() {
+            lines.extend(details("Removed packages", &packages(removed)));
+        }
+    }
+
+    if let Some(ref stdenvs) = report.stdenv_changes {
+        if !stdenvs.is_empty() {
+            lines.push(format!("The stdenv changes on {}.", stdenvs.join(", ")));
+            lines.push(String::from(""));
+        }
+    }
+
+    if let Some(ref maintainers) = report.maintainers {
+        if !maintainers.is_empty() {
+            // Without an @, so listing the maintainers doesn't notify
+            // them again: they are already asked to review.
+            let entries: Vec<String> = maintainers
+                .iter()
+                .map(|(maintainer, packages)| format!("{}: {}", maintainer, packages.join(", ")))
+                .collect();
+            lines.extend(details("Impacted maintainers", &entries));
+        }
+    }
+
+    Some(truncate(lines.join("\n").trim_end(), MAX_COMMENT_LENGTH))
+}
+
+/// Whether the evaluation found nothing worth a summary: no rebuilt,
+/// added or removed packages.
+pub fn is_empty(report: &EvaluationReport) -> bool {
+    let none = |attrs: &Option<Vec<PackageArch>>| {
+        attrs.as_ref().map(|attrs| attrs.is_empty()).unwrap_or(true)
+    };
+
+    none(&report.rebuilds) && none(&report.added) && none(&report.removed)
+}
+
+/// Edits the PR's rebuild summary to the body, so each PR has a single
+/// one. A new summary is only posted if `create` is set, so PRs which
+/// never had anything to summarise don't get a comment.
+pub fn post(issue_ref: &IssueRef, body: String, create: bool) {
+    let comments = issue_ref.comments();
+
+    let existing = match comments.iter(&CommentListOptions::default()) {
+        Ok(mut existing) => existing.find(|c| c.body.starts_with(MARKER)),
+        Err(e) => {
+            // Posting without knowing about an existing summary could
+            // leave two of them.
+            warn!("Failed to list the PR's comments: {:?}", e);
+            return;
+        }
+    };
+
+    let result = match existing {
+        Some(ref comment) if comment.body == body => {
+            info!("The rebuild summary is up to date");
+            return;
+        }
+        Some(comment) => comments.edit(comment.id, &CommentOptions { body }),
+        None if create => comments.create(&CommentOptions { body }),
+        None => return,
+    };
+
+    if let Err(e) = result {
+        warn!("Failed to post the rebuild summary: {:?}", e);
+    }
+}
+
+fn packages(attrs: &[PackageArch]) -> Vec<String> {
+    let mut packages: Vec<String> = attrs
+        .iter()
+        .map(|attr| format!("`{}`", attr.package))
+        .collect();
+    packages.sort();
+    packages.dedup();
+    packages
+}
+
+/// A collapsed list with a count in its title.
+fn details(title: &str, entries: &[String]) -> Vec<String> {
+    let mut lines = vec![
+        format!("<details><summary>{} ({})</summary>", title, entries.len()),
+        String::from(""),
+    ];
+    for entry in entries.iter().take(MAX_LISTED) {
+        lines.push(format!("- {}", entry));
+    }
+    if entries.len() > MAX_LISTED {
+        lines.push(format!("- and {} more", entries.len() - MAX_LISTED));
+    }
+    lines.push(String::from(""));
+    lines.push(String::from("</details>"));
+    lines.push(String::from(""));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ofborg::message::evaluationjob::test_job;
+
+    fn report() -> EvaluationReport {
+        EvaluationReport::new(&test_job(), "evaluator")
+    }
+
+    fn attr(package: &str, architecture: &str) -> PackageArch {
+        PackageArch {
+            package: package.to_owned(),
+            architecture: architecture.to_owned(),
+        }
+    }
+
+    #[test]
+    fn no_comparison() {
+        assert_eq!(comment(&report(), None), None);
+    }
+
+    #[test]
+    fn no_rebuilds() {
+        let mut report = report();
+        report.rebuilds = Some(vec![]);
+        report.added = Some(vec![]);
+        report.removed = Some(vec![]);
+
+        assert_eq!(
+            comment(&report, None).unwrap(),
+            format!(
+                "{}\n### Rebuild summary of abc123\n\nNo packages are rebuilt.",
+                MARKER
+            )
+        );
+    }
+
+    #[test]
+    fn empty() {
+        let mut report = report();
+        assert!(is_empty(&report));
+
+        report.rebuilds = Some(vec![]);
+        report.added = Some(vec![]);
+        report.removed = Some(vec![]);
+        assert!(is_empty(&report));
+
+        report.removed = Some(vec![attr("hello", "x86_64-linux")]);
+        assert!(!is_empty(&report));
+    }
+
+    #[test]
+    fn full_summary() {
+        let mut report = report();
+        report.rebuilds = Some(vec![
+            attr("hello", "x86_64-linux"),
+            attr("hello", "aarch64-linux"),
+            attr("world", "x86_64-linux"),
+        ]);
+        report.added = Some(vec![attr("world", "x86_64-linux")]);
+        report.removed = Some(vec![]);
+        report.stdenv_changes = Some(vec![String::from("x86_64-linux")]);
+        let mut maintainers = BTreeMap::new();
+        maintainers.insert(
+            String::from("alice"),
+            vec![String::from("hello"), String::from("world")],
+        );
+        report.maintainers = Some(maintainers);
+
+        let comment = comment(&report, Some("https://logs.example.com/changed")).unwrap();
+        assert_eq!(
+            comment,
+            format!(
+                "{}
+### Rebuild summary of abc123
+
+| System | Rebuilds |
+|-|-|
+| aarch64-linux | 1 |
+| x86_64-linux | 2 |
+
+<details><summary>Rebuilds on aarch64-linux (1)</summary>
+
+- `hello`
+
+</details>
+
+<details><summary>Rebuilds on x86_64-linux (2)</summary>
+
+- `hello`
+- `world`
+
+</details>
+
+[All changed paths](https://logs.example.com/changed)
+
+<details><summary>Added packages (1)</summary>
+
+- `world`
+
+</details>
+
+The stdenv changes on x86_64-linux.
+
+<details><summary>Impacted maintainers (1)</summary>
+
+- alice: hello, world
+
+</details>",
+                MARKER
+            )
+        );
+    }
+
+    #[test]
+    fn long_lists_are_cut() {
+        let entries: Vec<String> = (0..MAX_LISTED + 2).map(|i| i.to_string()).collect();
+        let lines = details("Rebuilds", &entries);
+        assert_eq!(lines[0], "<details><summary>Rebuilds (502)</summary>");
+        assert!(lines.contains(&String::from("- and 2 more")));
+    }
+}
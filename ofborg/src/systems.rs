@@ -1,28 +1,140 @@
-#[derive(Clone, Debug)]
-pub enum System {
-    X8664Linux,
-    Aarch64Linux,
-    X8664Darwin,
+use std::fmt;
+
+/// A platform builds can be scheduled on, as listed in the `systems`
+/// section of the configuration.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct System {
+    /// The Nix system name, like `x86_64-linux`.
+    pub name: String,
+
+    /// If the builders for this system can run NixOS tests.
+    #[serde(default)]
+    pub nixos_tests: bool,
+
+    /// The least trusted users who may build on this system.
+    pub trust: Trust,
+
+    /// The queue build jobs are published to, defaulting to
+    /// `build-inputs-<name>`.
+    #[serde(default)]
+    pub queue: Option<String>,
+
+    /// The platform named in the `10.rebuild-<platform>` labels, like
+    /// `linux`, defaulting to the system's name. Stdenv changes are
+    /// only checked for systems which have one.
+    #[serde(default)]
+    pub rebuild_label: Option<String>,
+}
+
+/// The tiers of users from the ACL, from least to most trusted.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum Trust {
+    Known,
+    Trusted,
 }
 
 impl System {
-    pub fn to_string(&self) -> String {
-        match self {
-            System::X8664Linux => String::from("x86_64-linux"),
-            System::Aarch64Linux => String::from("aarch64-linux"),
-            System::X8664Darwin => String::from("x86_64-darwin"),
+    pub fn as_build_destination(&self) -> (Option<String>, Option<String>) {
+        match self.queue {
+            Some(ref queue) => (None, Some(queue.clone())),
+            None => (None, Some(format!("build-inputs-{}", self.name))),
         }
     }
 
-    pub fn as_build_destination(&self) -> (Option<String>, Option<String>) {
-        (None, Some(format!("build-inputs-{}", self.to_string())))
+    pub fn can_run_nixos_tests(&self) -> bool {
+        self.nixos_tests
     }
 
-    pub fn can_run_nixos_tests(&self) -> bool {
-        match self {
-            System::X8664Linux => true,
-            System::Aarch64Linux => true,
-            System::X8664Darwin => false,
+    /// If users with the given trust may build on this system.
+    pub fn allows(&self, trust: Trust) -> bool {
+        trust >= self.trust
+    }
+}
+
+impl fmt::Display for System {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// The systems used when the configuration doesn't list any.
+pub fn default_systems() -> Vec<System> {
+    vec![
+        System {
+            name: String::from("x86_64-darwin"),
+            nixos_tests: false,
+            trust: Trust::Trusted,
+            queue: None,
+            rebuild_label: Some(String::from("darwin")),
+        },
+        System {
+            name: String::from("x86_64-linux"),
+            nixos_tests: true,
+            trust: Trust::Known,
+            queue: None,
+            rebuild_label: Some(String::from("linux")),
+        },
+        System {
+            name: String::from("aarch64-linux"),
+            nixos_tests: true,
+            trust: Trust::Known,
+            queue: None,
+            rebuild_label: None,
+        },
+    ]
+}
+
+/// The distinct rebuild labels of the systems, in order.
+pub fn rebuild_labels(systems: &[System]) -> Vec<String> {
+    let mut labels: Vec<String> = vec![];
+    for label in systems.iter().filter_map(|s| s.rebuild_label.clone()) {
+        if !labels.contains(&label) {
+            labels.push(label);
         }
     }
+
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn deserialize_minimal() {
+        let system: System =
+            serde_json::from_str(r#"{"name": "aarch64-darwin", "trust": "trusted"}"#).unwrap();
+        assert_eq!(system.to_string(), "aarch64-darwin");
+        assert_eq!(
+            system.as_build_destination(),
+            (None, Some(String::from("build-inputs-aarch64-darwin")))
+        );
+        assert!(!system.can_run_nixos_tests());
+        assert!(system.allows(Trust::Trusted));
+        assert!(!system.allows(Trust::Known));
+    }
+
+    #[test]
+    fn custom_queue() {
+        let system: System = serde_json::from_str(
+            r#"{"name": "i686-linux", "trust": "known", "queue": "build-inputs-x86_64-linux"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            system.as_build_destination(),
+            (None, Some(String::from("build-inputs-x86_64-linux")))
+        );
+        assert!(system.allows(Trust::Trusted));
+        assert!(system.allows(Trust::Known));
+    }
+
+    #[test]
+    fn default_rebuild_labels() {
+        assert_eq!(
+            rebuild_labels(&default_systems()),
+            vec![String::from("darwin"), String::from("linux")]
+        );
+    }
 }
@@ -1,25 +1,17 @@
 use crate::maintainers::{Maintainer, MaintainersByPackage};
 use ofborg::outpathdiff::PackageArch;
-use ofborg::tasks;
+use ofborg::systems::{self, System};
 use std::collections::HashMap;
 
 pub struct StdenvTagger {
     possible: Vec<String>,
     selected: Vec<String>,
+    systems: Vec<System>,
 }
 
 impl Default for StdenvTagger {
     fn default() -> StdenvTagger {
-        let mut t = StdenvTagger {
-            possible: vec![
-                String::from("10.rebuild-linux-stdenv"),
-                String::from("10.rebuild-darwin-stdenv"),
-            ],
-            selected: vec![],
-        };
-        t.possible.sort();
-
-        t
+        StdenvTagger::for_systems(&systems::default_systems())
     }
 }
 
@@ -28,14 +20,37 @@ impl StdenvTagger {
         Default::default()
     }
 
-    pub fn changed(&mut self, systems: Vec<tasks::eval::stdenvs::System>) {
+    pub fn for_systems(systems: &[System]) -> StdenvTagger {
+        let mut t = StdenvTagger {
+            possible: systems::rebuild_labels(systems)
+                .iter()
+                .map(|label| format!("10.rebuild-{}-stdenv", label))
+                .collect(),
+            selected: vec![],
+            systems: systems.to_vec(),
+        };
+        t.possible.sort();
+
+        t
+    }
+
+    pub fn changed(&mut self, systems: Vec<String>) {
         for system in systems {
-            match system {
-                tasks::eval::stdenvs::System::X8664Darwin => {
-                    self.selected.push(String::from("10.rebuild-darwin-stdenv"));
+            let label = self
+                .systems
+                .iter()
+                .find(|s| s.name == system)
+                .and_then(|s| s.rebuild_label.clone());
+
+            match label {
+                Some(label) => {
+                    let tag = format!("10.rebuild-{}-stdenv", label);
+                    if !self.selected.contains(&tag) {
+                        self.selected.push(tag);
+                    }
                 }
-                tasks::eval::stdenvs::System::X8664Linux => {
-                    self.selected.push(String::from("10.rebuild-linux-stdenv"));
+                None => {
+                    info!("No rebuild label for the stdenv of {}", system);
                 }
             }
         }
@@ -110,36 +125,60 @@ impl PkgsAddedRemovedTagger {
     }
 }
 
+/// One of the `10.rebuild-<platform>: <name>` labels, applied when
+/// the number of rebuilds on the platform is at least `min` and, if
+/// set, at most `max`. Buckets may overlap.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RebuildBucket {
+    pub name: String,
+    pub min: u64,
+    #[serde(default)]
+    pub max: Option<u64>,
+}
+
+impl RebuildBucket {
+    pub fn new(name: &str, min: u64, max: Option<u64>) -> RebuildBucket {
+        RebuildBucket {
+            name: name.to_owned(),
+            min,
+            max,
+        }
+    }
+
+    fn contains(&self, count: u64) -> bool {
+        count >= self.min && self.max.map(|max| count <= max).unwrap_or(true)
+    }
+}
+
+/// The buckets used when the configuration doesn't list any.
+pub fn default_rebuild_buckets() -> Vec<RebuildBucket> {
+    vec![
+        RebuildBucket::new("0", 0, Some(0)),
+        RebuildBucket::new("1-10", 1, Some(10)),
+        RebuildBucket::new("11-100", 11, Some(100)),
+        RebuildBucket::new("101-500", 101, Some(500)),
+        RebuildBucket::new("501+", 501, None),
+        RebuildBucket::new("501-1000", 501, Some(1000)),
+        RebuildBucket::new("1001-2500", 1001, Some(2500)),
+        RebuildBucket::new("2501-5000", 2501, Some(5000)),
+        RebuildBucket::new("5001+", 5001, None),
+    ]
+}
+
+/// Labels how many attributes are rebuilt on each platform. Systems
+/// without a `rebuild_label` are labelled with their own name, but
+/// only once they appear in the evaluation.
 pub struct RebuildTagger {
     possible: Vec<String>,
     selected: Vec<String>,
+    systems: Vec<System>,
+    buckets: Vec<RebuildBucket>,
+    labels: Vec<String>,
 }
 
 impl Default for RebuildTagger {
     fn default() -> RebuildTagger {
-        RebuildTagger {
-            possible: vec![
-                String::from("10.rebuild-darwin: 0"),
-                String::from("10.rebuild-darwin: 1-10"),
-                String::from("10.rebuild-darwin: 11-100"),
-                String::from("10.rebuild-darwin: 101-500"),
-                String::from("10.rebuild-darwin: 501+"),
-                String::from("10.rebuild-darwin: 501-1000"),
-                String::from("10.rebuild-darwin: 1001-2500"),
-                String::from("10.rebuild-darwin: 2501-5000"),
-                String::from("10.rebuild-darwin: 5001+"),
-                String::from("10.rebuild-linux: 0"),
-                String::from("10.rebuild-linux: 1-10"),
-                String::from("10.rebuild-linux: 11-100"),
-                String::from("10.rebuild-linux: 101-500"),
-                String::from("10.rebuild-linux: 501+"),
-                String::from("10.rebuild-linux: 501-1000"),
-                String::from("10.rebuild-linux: 1001-2500"),
-                String::from("10.rebuild-linux: 2501-5000"),
-                String::from("10.rebuild-linux: 5001+"),
-            ],
-            selected: vec![],
-        }
+        RebuildTagger::for_systems(&systems::default_systems())
     }
 }
 
@@ -148,40 +187,59 @@ impl RebuildTagger {
         Default::default()
     }
 
+    pub fn for_systems(systems: &[System]) -> RebuildTagger {
+        RebuildTagger::with_buckets(systems, &default_rebuild_buckets())
+    }
+
+    pub fn with_buckets(systems: &[System], buckets: &[RebuildBucket]) -> RebuildTagger {
+        let mut tagger = RebuildTagger {
+            possible: vec![],
+            selected: vec![],
+            systems: systems.to_vec(),
+            buckets: buckets.to_vec(),
+            labels: vec![],
+        };
+
+        for label in systems::rebuild_labels(systems) {
+            tagger.add_label(label);
+        }
+
+        tagger
+    }
+
+    /// Count the rebuilds of these systems too, even if none of their
+    /// attributes are rebuilt.
+    pub fn evaluated_on(&mut self, architectures: &[String]) {
+        for architecture in architectures {
+            let label = self.label_for(architecture);
+            self.add_label(label);
+        }
+    }
+
     pub fn parse_attrs(&mut self, attrs: Vec<PackageArch>) {
-        let mut counter_darwin = 0;
-        let mut counter_linux = 0;
+        let architectures: Vec<String> = attrs.iter().map(|a| a.architecture.clone()).collect();
+        self.evaluated_on(&architectures);
+
+        let mut counters: Vec<(String, u64)> =
+            self.labels.iter().map(|label| (label.clone(), 0)).collect();
 
         for attr in attrs {
-            match attr.architecture.as_ref() {
-                "x86_64-darwin" => {
-                    counter_darwin += 1;
-                }
-                "x86_64-linux" => {
-                    counter_linux += 1;
-                }
-                "aarch64-linux" => {}
-                "i686-linux" => {}
-                arch => {
-                    info!("Unknown arch: {:?}", arch);
-                }
+            let label = self.label_for(&attr.architecture);
+            if let Some(counter) = counters.iter_mut().find(|c| c.0 == label) {
+                counter.1 += 1;
             }
         }
 
         self.selected = vec![];
-        self.selected.extend(
-            RebuildTagger::bucket(counter_darwin)
-                .iter()
-                .map(|bucket| format!("10.rebuild-darwin: {}", bucket))
-                .collect::<Vec<String>>(),
-        );
-
-        self.selected.extend(
-            RebuildTagger::bucket(counter_linux)
-                .iter()
-                .map(|bucket| format!("10.rebuild-linux: {}", bucket))
-                .collect::<Vec<String>>(),
-        );
+        for (label, count) in counters {
+            self.selected.extend(
+                self.buckets
+                    .iter()
+                    .filter(|bucket| bucket.contains(count))
+                    .map(|bucket| format!("10.rebuild-{}: {}", label, bucket.name))
+                    .collect::<Vec<String>>(),
+            );
+        }
 
         for tag in &self.selected {
             if !self.possible.contains(&tag) {
@@ -209,24 +267,25 @@ impl RebuildTagger {
         remove
     }
 
-    fn bucket(count: u64) -> &'static [&'static str] {
-        if count > 5000 {
-            &["501+", "5001+"]
-        } else if count > 2500 {
-            &["501+", "2501-5000"]
-        } else if count > 1000 {
-            &["501+", "1001-2500"]
-        } else if count > 500 {
-            &["501+", "501-1000"]
-        } else if count > 100 {
-            &["101-500"]
-        } else if count > 10 {
-            &["11-100"]
-        } else if count > 0 {
-            &["1-10"]
-        } else {
-            &["0"]
+    fn label_for(&self, architecture: &str) -> String {
+        self.systems
+            .iter()
+            .find(|s| s.name == architecture)
+            .and_then(|s| s.rebuild_label.clone())
+            .unwrap_or_else(|| architecture.to_owned())
+    }
+
+    fn add_label(&mut self, label: String) {
+        if self.labels.contains(&label) {
+            return;
         }
+
+        self.possible.extend(
+            self.buckets
+                .iter()
+                .map(|bucket| format!("10.rebuild-{}: {}", label, bucket.name)),
+        );
+        self.labels.push(label);
     }
 }
 
@@ -329,6 +388,7 @@ impl MaintainerPRTagger {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json;
 
     struct PackageArchSrc {
         linux: usize,
@@ -378,6 +438,112 @@ mod tests {
         }
     }
 
+    #[test]
+    pub fn test_unlabelled_systems() {
+        let mut tagger = RebuildTagger::new();
+        tagger.evaluated_on(&[
+            String::from("x86_64-linux"),
+            String::from("aarch64-linux"),
+            String::from("i686-linux"),
+        ]);
+        tagger.parse_attrs(vec![PackageArch {
+            package: String::from("hello"),
+            architecture: String::from("aarch64-linux"),
+        }]);
+
+        assert_eq!(
+            tagger.tags_to_add(),
+            vec![
+                "10.rebuild-darwin: 0",
+                "10.rebuild-linux: 0",
+                "10.rebuild-aarch64-linux: 1-10",
+                "10.rebuild-i686-linux: 0",
+            ]
+        );
+        assert!(tagger
+            .tags_to_remove()
+            .contains(&String::from("10.rebuild-i686-linux: 5001+")));
+    }
+
+    #[test]
+    pub fn test_configured_buckets() {
+        let buckets: Vec<RebuildBucket> = serde_json::from_str(
+            r#"[
+                {"name": "none", "min": 0, "max": 0},
+                {"name": "some", "min": 1, "max": 99},
+                {"name": "many", "min": 100}
+            ]"#,
+        )
+        .unwrap();
+
+        let mut tagger = RebuildTagger::with_buckets(&systems::default_systems(), &buckets);
+        tagger.parse_attrs(PackageArchSrc::linux(150).and_darwin(3).into());
+
+        assert_eq!(
+            tagger.tags_to_add(),
+            vec!["10.rebuild-darwin: some", "10.rebuild-linux: many"]
+        );
+        assert_eq!(
+            tagger.tags_to_remove(),
+            vec![
+                "10.rebuild-darwin: none",
+                "10.rebuild-darwin: many",
+                "10.rebuild-linux: none",
+                "10.rebuild-linux: some",
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_configured_systems() {
+        let systems: Vec<System> = serde_json::from_str(
+            r#"[
+                {"name": "x86_64-linux", "trust": "known", "rebuild_label": "linux"},
+                {"name": "i686-linux", "trust": "known", "rebuild_label": "linux"},
+                {"name": "aarch64-linux", "trust": "known", "rebuild_label": "aarch64-linux"}
+            ]"#,
+        )
+        .unwrap();
+
+        let mut tagger = RebuildTagger::for_systems(&systems);
+        tagger.parse_attrs(vec![
+            PackageArch {
+                package: String::from("hello"),
+                architecture: String::from("x86_64-linux"),
+            },
+            PackageArch {
+                package: String::from("hello"),
+                architecture: String::from("i686-linux"),
+            },
+            PackageArch {
+                package: String::from("hello"),
+                architecture: String::from("x86_64-darwin"),
+            },
+        ]);
+        assert_eq!(
+            tagger.tags_to_add(),
+            vec![
+                "10.rebuild-linux: 1-10",
+                "10.rebuild-aarch64-linux: 0",
+                "10.rebuild-x86_64-darwin: 1-10",
+            ]
+        );
+        assert!(tagger
+            .tags_to_remove()
+            .contains(&String::from("10.rebuild-aarch64-linux: 1-10")));
+
+        let mut tagger = StdenvTagger::for_systems(&systems);
+        tagger.changed(vec![
+            String::from("x86_64-linux"),
+            String::from("i686-linux"),
+        ]);
+        assert_eq!(tagger.tags_to_add(), vec!["10.rebuild-linux-stdenv"]);
+        assert_eq!(
+            tagger.tags_to_remove(),
+            vec!["10.rebuild-aarch64-linux-stdenv"]
+        );
+    }
+
     #[test]
     pub fn test_packages_changed() {
         let mut tagger = RebuildTagger::new();
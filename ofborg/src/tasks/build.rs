@@ -4,13 +4,18 @@ extern crate uuid;
 
 use uuid::Uuid;
 
+use ofborg::asynccmd::CancelHandle;
 use ofborg::checkout;
 use ofborg::commentparser;
+use ofborg::config::BinaryCacheConfig;
+use ofborg::latestheads::LatestHeads;
 use ofborg::message::buildjob;
 use ofborg::message::buildlogmsg;
-use ofborg::message::buildresult::{BuildResult, BuildStatus, V1Tag};
+use ofborg::message::buildresult::{AttrResult, BuildResult, BuildStatus, PathSizes, V2Tag};
 use ofborg::nix;
-use std::collections::VecDeque;
+use ofborg::tasks::buildcancel::RunningBuilds;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
 
 use amqp::protocol::basic::{BasicProperties, Deliver};
 use ofborg::notifyworker;
@@ -21,6 +26,31 @@ pub struct BuildWorker {
     nix: nix::Nix,
     system: String,
     identity: String,
+    running: RunningBuilds,
+    heads: LatestHeads,
+    options: BuildOptions,
+}
+
+/// What builders do for the attributes which built, besides reporting
+/// their out paths and sizes.
+#[derive(Clone, Debug, Default)]
+pub struct BuildOptions {
+    /// Where the outputs are copied to, if anywhere.
+    pub binary_cache: Option<BinaryCacheConfig>,
+    /// Whether their sizes on the target branch are reported too.
+    pub target_sizes: bool,
+}
+
+/// What the steps of building one job share.
+struct JobContext<'a> {
+    job: &'a buildjob::BuildJob,
+    co: &'a checkout::CachedProjectCo,
+    refpath: String,
+    buildfile: nix::File,
+    /// The target branch commit the PR is merged into, if the sizes on
+    /// the target branch are reported.
+    target_commit: Option<String>,
+    cancel: &'a CancelHandle,
 }
 
 impl BuildWorker {
@@ -29,12 +59,18 @@ impl BuildWorker {
         nix: nix::Nix,
         system: String,
         identity: String,
+        running: RunningBuilds,
+        heads: LatestHeads,
+        options: BuildOptions,
     ) -> BuildWorker {
         BuildWorker {
             cloner,
             nix,
             system,
             identity,
+            running,
+            heads,
+            options,
         }
     }
 
@@ -45,6 +81,331 @@ impl BuildWorker {
     ) -> JobActions<'a, 'b> {
         JobActions::new(&self.system, &self.identity, job, receiver)
     }
+
+    fn build_job(&self, actions: &mut JobActions, job: &buildjob::BuildJob, cancel: &CancelHandle) {
+        info!("Working on {}", job.pr.number);
+        let project = self
+            .cloner
+            .project(&job.repo.full_name, job.repo.clone_url.clone());
+        let co = project
+            .clone_for("builder".to_string(), self.identity.clone())
+            .unwrap();
+
+        let target_branch = match job.pr.target_branch.clone() {
+            Some(x) => x,
+            None => String::from("origin/master"),
+        };
+
+        let buildfile = match job.subset {
+            Some(commentparser::Subset::NixOS) => nix::File::ReleaseNixOS,
+            _ => nix::File::DefaultNixpkgs,
+        };
+
+        let refpath = co.checkout_origin_ref(target_branch.as_ref()).unwrap();
+        let target_commit = if self.options.target_sizes {
+            co.head_commit().ok()
+        } else {
+            None
+        };
+        let ctx = JobContext {
+            job,
+            co: &co,
+            refpath,
+            buildfile,
+            target_commit,
+            cancel,
+        };
+        if cancel.is_cancelled() {
+            self.stop_cancelled(actions, job);
+            return;
+        }
+
+        co.fetch_pr(job.pr.number).unwrap();
+        if cancel.is_cancelled() {
+            self.stop_cancelled(actions, job);
+            return;
+        }
+
+        if !co.commit_exists(job.pr.head_sha.as_ref()) {
+            info!("Commit {} doesn't exist", job.pr.head_sha);
+            actions.commit_missing();
+            return;
+        }
+
+        if co.merge_commit(job.pr.head_sha.as_ref()).is_err() {
+            info!("Failed to merge {}", job.pr.head_sha);
+            actions.merge_failed();
+            return;
+        }
+
+        if cancel.is_cancelled() {
+            self.stop_cancelled(actions, job);
+            return;
+        }
+
+        println!(
+            "Got path: {:?}, determining which ones we can build ",
+            ctx.refpath
+        );
+        let (can_build, cannot_build) = self.nix.safely_partition_instantiable_attrs(
+            ctx.refpath.as_ref(),
+            buildfile,
+            job.attrs.clone(),
+        );
+
+        let can_build_attrs: Vec<String> = can_build
+            .iter()
+            .map(|instantiated| instantiated.attr.clone())
+            .collect();
+        let cannot_build_attrs: Vec<String> = cannot_build
+            .clone()
+            .into_iter()
+            .map(|(attr, _)| attr)
+            .collect();
+
+        println!(
+            "Can build: '{}', Cannot build: '{}'",
+            can_build_attrs.join(", "),
+            cannot_build_attrs.join(", ")
+        );
+
+        if cancel.is_cancelled() {
+            self.stop_cancelled(actions, job);
+            return;
+        }
+
+        actions.log_started(can_build_attrs.clone(), cannot_build_attrs.clone());
+        actions.log_instantiation_errors(cannot_build);
+
+        if can_build.is_empty() {
+            actions.build_not_attempted(cannot_build_attrs);
+            return;
+        }
+
+        let mut attr_results = self.build_attrs(actions, &ctx, &can_build);
+        self.add_target_sizes(&ctx, &mut attr_results);
+
+        println!("ok built ({:?}), building", attr_results);
+        println!("Lines:\n-----8<-----");
+        actions
+            .log_snippet()
+            .iter()
+            .inspect(|x| println!("{}", x))
+            .last();
+        println!("----->8-----");
+
+        actions.build_finished(attr_results, can_build_attrs, cannot_build_attrs);
+        println!("Done!");
+    }
+
+    /// Builds the attributes with one nix-build, which keeps going past
+    /// failures, and tells how each of them went from whether its out
+    /// paths are in the store afterwards.
+    fn build_attrs(
+        &self,
+        actions: &mut JobActions,
+        ctx: &JobContext,
+        attrs: &[nix::InstantiatedAttr],
+    ) -> BTreeMap<String, AttrResult> {
+        let mut spawned = self.nix.safely_build_attrs_async(
+            Path::new(&ctx.refpath),
+            ctx.buildfile,
+            attrs.iter().map(|attr| attr.attr.clone()).collect(),
+            ctx.cancel.clone(),
+        );
+
+        for line in spawned.lines() {
+            actions.log_line(&line);
+        }
+
+        let result = spawned.wait();
+        if ctx.cancel.is_cancelled() {
+            let status = self.cancelled_status(ctx.job);
+            if status == BuildStatus::Superseded {
+                actions.log_line("Build superseded by a newer commit.");
+            } else {
+                actions.log_line("Build cancelled.");
+            }
+            return attrs
+                .iter()
+                .map(|attr| (attr.attr.clone(), AttrResult::new(status.clone(), vec![])))
+                .collect();
+        }
+
+        let (outcome, errors) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                let status = BuildStatus::UnexpectedError {
+                    err: format!("failed on interior command {:?}", e),
+                };
+                return attrs
+                    .iter()
+                    .map(|attr| (attr.attr.clone(), AttrResult::new(status.clone(), vec![])))
+                    .collect();
+            }
+        };
+
+        let failed_builders: Vec<String> = errors
+            .iter()
+            .filter_map(|error| match *error {
+                nix::NixError::BuilderFailed { ref drv, .. } => Some(drv.clone()),
+                _ => None,
+            })
+            .collect();
+        actions.record_errors(errors);
+
+        attrs
+            .iter()
+            .map(|attr| {
+                let result = self.attr_result(actions, attr, &outcome, &failed_builders);
+                (attr.attr.clone(), result)
+            })
+            .collect()
+    }
+
+    /// How the attribute went in a build which ended with the outcome.
+    fn attr_result(
+        &self,
+        actions: &mut JobActions,
+        attr: &nix::InstantiatedAttr,
+        outcome: &nix::BuildOutcome,
+        failed_builders: &[String],
+    ) -> AttrResult {
+        let built = self.nix.outputs(&attr.drvs).and_then(|out_paths| {
+            let valid = self.nix.valid_paths(&out_paths)?;
+            Ok((out_paths, valid))
+        });
+
+        match built {
+            Ok((ref out_paths, ref valid)) if out_paths.iter().all(|path| valid.contains(path)) => {
+                let mut result = AttrResult::new(BuildStatus::Success, out_paths.clone());
+                result.sizes = self.sizes(&result.out_paths);
+                result.binary_cache = self.copy_to_binary_cache(actions, &result.out_paths);
+                result
+            }
+            Ok(_) => {
+                let builder_failed = attr.drvs.iter().any(|drv| failed_builders.contains(drv));
+                AttrResult::new(attr_status(outcome, builder_failed), vec![])
+            }
+            Err(e) => AttrResult::new(
+                BuildStatus::UnexpectedError {
+                    err: format!("failed to query the out paths: {}", e),
+                },
+                vec![],
+            ),
+        }
+    }
+
+    /// Adds the sizes on the target branch of the attributes which
+    /// built, when their out paths there are in the store. Only the
+    /// attributes which built are instantiated on the target branch.
+    fn add_target_sizes(&self, ctx: &JobContext, attr_results: &mut BTreeMap<String, AttrResult>) {
+        let target_commit = match ctx.target_commit {
+            Some(ref commit) => commit,
+            None => return,
+        };
+        if ctx.cancel.is_cancelled()
+            || !attr_results
+                .values()
+                .any(|result| result.status == BuildStatus::Success)
+        {
+            return;
+        }
+
+        if let Err(e) = ctx.co.checkout_ref(target_commit.as_ref()) {
+            info!("Not reporting the sizes on the target branch: {:?}", e);
+            return;
+        }
+
+        for (attr, result) in attr_results.iter_mut() {
+            if ctx.cancel.is_cancelled() {
+                return;
+            }
+            if result.status != BuildStatus::Success {
+                continue;
+            }
+
+            result.target_sizes = self
+                .nix
+                .safely_query_out_paths(Path::new(&ctx.refpath), ctx.buildfile, attr.clone())
+                .and_then(|paths| self.sizes(&paths));
+        }
+    }
+
+    /// Copies the out paths to the binary cache, if one is configured,
+    /// returning where reviewers can fetch them from.
+    fn copy_to_binary_cache(
+        &self,
+        actions: &mut JobActions,
+        out_paths: &[String],
+    ) -> Option<String> {
+        let cache = match self.options.binary_cache {
+            Some(ref cache) if !out_paths.is_empty() => cache,
+            _ => return None,
+        };
+
+        actions.log_line(&format!("Copying the outputs to {}", cache.public_url()));
+        match self.nix.copy_to(out_paths, &cache.store_url()) {
+            Ok(()) => Some(cache.public_url()),
+            Err(e) => {
+                actions.log_line("Failed to copy the outputs to the binary cache:");
+                for line in e.lines() {
+                    actions.log_line(line);
+                }
+                None
+            }
+        }
+    }
+
+    /// The sizes of the out paths, if they are all in the store.
+    fn sizes(&self, out_paths: &[String]) -> Option<PathSizes> {
+        if out_paths.is_empty() {
+            return None;
+        }
+
+        match self.nix.closure(out_paths) {
+            Ok(closure) => Some(PathSizes::new(out_paths, &closure)),
+            Err(e) => {
+                info!("Not reporting the sizes of {:?}: {}", out_paths, e);
+                None
+            }
+        }
+    }
+
+    /// Reports a job which was cancelled before it started building.
+    fn stop_cancelled(&self, actions: &mut JobActions, job: &buildjob::BuildJob) {
+        info!(
+            "Build of {}#{} was cancelled",
+            job.repo.full_name, job.pr.number
+        );
+        if self.cancelled_status(job) == BuildStatus::Superseded {
+            actions.build_superseded();
+        } else {
+            actions.build_cancelled();
+        }
+    }
+
+    fn cancelled_status(&self, job: &buildjob::BuildJob) -> BuildStatus {
+        if self.heads.is_superseded(&job.repo, &job.pr) {
+            BuildStatus::Superseded
+        } else {
+            BuildStatus::Cancelled
+        }
+    }
+}
+
+/// The status of an attribute whose out paths weren't built, in a build
+/// which ended with the outcome, depending on whether the builder of
+/// the attribute itself failed.
+fn attr_status(outcome: &nix::BuildOutcome, builder_failed: bool) -> BuildStatus {
+    match *outcome {
+        nix::BuildOutcome::Success => BuildStatus::UnexpectedError {
+            err: "the build succeeded without its out paths".into(),
+        },
+        nix::BuildOutcome::DependencyFailed if builder_failed => BuildStatus::Failure,
+        nix::BuildOutcome::PermanentFailure if !builder_failed => BuildStatus::DependencyFailed,
+        ref outcome => outcome.clone().into(),
+    }
 }
 
 pub struct JobActions<'a, 'b> {
@@ -54,6 +415,7 @@ pub struct JobActions<'a, 'b> {
     job: &'b buildjob::BuildJob,
     line_counter: u64,
     snippet_log: VecDeque<String>,
+    errors: Vec<nix::NixError>,
     attempt_id: String,
     log_exchange: Option<String>,
     log_routing_key: Option<String>,
@@ -85,6 +447,7 @@ impl<'a, 'b> JobActions<'a, 'b> {
             job,
             line_counter: 0,
             snippet_log: VecDeque::with_capacity(10),
+            errors: vec![],
             attempt_id: format!("{}", Uuid::new_v4()),
             log_exchange,
             log_routing_key,
@@ -106,8 +469,8 @@ impl<'a, 'b> JobActions<'a, 'b> {
     }
 
     pub fn merge_failed(&mut self) {
-        let msg = BuildResult::V1 {
-            tag: V1Tag::V1,
+        let msg = BuildResult::V2 {
+            tag: V2Tag::V2,
             repo: self.job.repo.clone(),
             pr: self.job.pr.clone(),
             system: self.system.clone(),
@@ -117,6 +480,43 @@ impl<'a, 'b> JobActions<'a, 'b> {
             attempted_attrs: None,
             skipped_attrs: None,
             status: BuildStatus::Failure,
+            attr_results: BTreeMap::new(),
+            errors: vec![],
+        };
+
+        let result_exchange = self.result_exchange.clone();
+        let result_routing_key = self.result_routing_key.clone();
+
+        self.tell(worker::publish_serde_action(
+            result_exchange,
+            result_routing_key,
+            &msg,
+        ));
+        self.tell(worker::Action::Ack);
+    }
+
+    pub fn build_superseded(&mut self) {
+        self.build_stopped(BuildStatus::Superseded, "Superseded by a newer commit");
+    }
+
+    pub fn build_cancelled(&mut self) {
+        self.build_stopped(BuildStatus::Cancelled, "Cancelled");
+    }
+
+    fn build_stopped(&mut self, status: BuildStatus, reason: &str) {
+        let msg = BuildResult::V2 {
+            tag: V2Tag::V2,
+            repo: self.job.repo.clone(),
+            pr: self.job.pr.clone(),
+            system: self.system.clone(),
+            output: vec![String::from(reason)],
+            attempt_id: self.attempt_id.clone(),
+            request_id: self.job.request_id.clone(),
+            attempted_attrs: Some(self.job.attrs.clone()),
+            skipped_attrs: None,
+            status,
+            attr_results: BTreeMap::new(),
+            errors: vec![],
         };
 
         let result_exchange = self.result_exchange.clone();
@@ -153,10 +553,27 @@ impl<'a, 'b> JobActions<'a, 'b> {
         for (attr, log) in cannot_build {
             self.log_line(&format!("Cannot nix-instantiate `{}' because:", &attr));
 
-            for line in log {
-                self.log_line(&line);
+            let errors = nix::parse_errors(&log.join("\n"));
+            if errors.is_empty() {
+                for line in log {
+                    self.log_line(&line);
+                }
+            } else {
+                for error in &errors {
+                    self.log_line(&format!("{}", error));
+                }
             }
             self.log_line("");
+            self.record_errors(errors);
+        }
+    }
+
+    /// Keeps the failures Nix reported, to send them with the result.
+    pub fn record_errors(&mut self, errors: Vec<nix::NixError>) {
+        for error in errors {
+            if !self.errors.contains(&error) {
+                self.errors.push(error);
+            }
         }
     }
 
@@ -187,8 +604,8 @@ impl<'a, 'b> JobActions<'a, 'b> {
     }
 
     pub fn build_not_attempted(&mut self, not_attempted_attrs: Vec<String>) {
-        let msg = BuildResult::V1 {
-            tag: V1Tag::V1,
+        let msg = BuildResult::V2 {
+            tag: V2Tag::V2,
             repo: self.job.repo.clone(),
             pr: self.job.pr.clone(),
             system: self.system.clone(),
@@ -198,6 +615,8 @@ impl<'a, 'b> JobActions<'a, 'b> {
             skipped_attrs: Some(not_attempted_attrs),
             attempted_attrs: None,
             status: BuildStatus::Skipped,
+            attr_results: BTreeMap::new(),
+            errors: self.errors.clone(),
         };
 
         let result_exchange = self.result_exchange.clone();
@@ -221,12 +640,19 @@ impl<'a, 'b> JobActions<'a, 'b> {
 
     pub fn build_finished(
         &mut self,
-        status: BuildStatus,
+        attr_results: BTreeMap<String, AttrResult>,
         attempted_attrs: Vec<String>,
         not_attempted_attrs: Vec<String>,
     ) {
-        let msg = BuildResult::V1 {
-            tag: V1Tag::V1,
+        let status = attempted_attrs
+            .iter()
+            .filter_map(|attr| attr_results.get(attr))
+            .map(|result| result.status.clone())
+            .find(|status| *status != BuildStatus::Success)
+            .unwrap_or(BuildStatus::Success);
+
+        let msg = BuildResult::V2 {
+            tag: V2Tag::V2,
             repo: self.job.repo.clone(),
             pr: self.job.pr.clone(),
             system: self.system.clone(),
@@ -236,6 +662,8 @@ impl<'a, 'b> JobActions<'a, 'b> {
             status,
             attempted_attrs: Some(attempted_attrs),
             skipped_attrs: Some(not_attempted_attrs),
+            attr_results,
+            errors: self.errors.clone(),
         };
 
         let result_exchange = self.result_exchange.clone();
@@ -288,106 +716,33 @@ impl notifyworker::SimpleNotifyWorker for BuildWorker {
             return;
         }
 
-        info!("Working on {}", job.pr.number);
-        let project = self
-            .cloner
-            .project(&job.repo.full_name, job.repo.clone_url.clone());
-        let co = project
-            .clone_for("builder".to_string(), self.identity.clone())
-            .unwrap();
-
-        let target_branch = match job.pr.target_branch.clone() {
-            Some(x) => x,
-            None => String::from("origin/master"),
-        };
-
-        let buildfile = match job.subset {
-            Some(commentparser::Subset::NixOS) => nix::File::ReleaseNixOS,
-            _ => nix::File::DefaultNixpkgs,
-        };
-
-        let refpath = co.checkout_origin_ref(target_branch.as_ref()).unwrap();
-        co.fetch_pr(job.pr.number).unwrap();
-
-        if !co.commit_exists(job.pr.head_sha.as_ref()) {
-            info!("Commit {} doesn't exist", job.pr.head_sha);
-            actions.commit_missing();
-            return;
-        }
-
-        if co.merge_commit(job.pr.head_sha.as_ref()).is_err() {
-            info!("Failed to merge {}", job.pr.head_sha);
-            actions.merge_failed();
-            return;
+        if let Some(ref systems) = job.systems {
+            if !systems.contains(&self.system) {
+                info!("Job was not requested for {}", self.system);
+                actions.nothing_to_do();
+                return;
+            }
         }
 
-        println!(
-            "Got path: {:?}, determining which ones we can build ",
-            refpath
-        );
-        let (can_build, cannot_build) = self.nix.safely_partition_instantiable_attrs(
-            refpath.as_ref(),
-            buildfile,
-            job.attrs.clone(),
-        );
-
-        let cannot_build_attrs: Vec<String> = cannot_build
-            .clone()
-            .into_iter()
-            .map(|(attr, _)| attr)
-            .collect();
-
-        println!(
-            "Can build: '{}', Cannot build: '{}'",
-            can_build.join(", "),
-            cannot_build_attrs.join(", ")
-        );
-
-        actions.log_started(can_build.clone(), cannot_build_attrs.clone());
-        actions.log_instantiation_errors(cannot_build);
-
-        if can_build.is_empty() {
-            actions.build_not_attempted(cannot_build_attrs);
+        if self.heads.is_superseded(&job.repo, &job.pr) {
+            info!(
+                "{} is no longer the head of {}",
+                job.pr.head_sha, job.pr.number
+            );
+            actions.build_superseded();
             return;
         }
 
-        let mut spawned =
-            self.nix
-                .safely_build_attrs_async(refpath.as_ref(), buildfile, can_build.clone());
-
-        for line in spawned.lines() {
-            actions.log_line(&line);
+        // Registered before the checkout, so a cancel stops the job at
+        // the next step, even if it hasn't started building yet.
+        let cancel = CancelHandle::new();
+        self.running.register(job, cancel.clone());
+        if self.running.was_cancelled(job) {
+            self.stop_cancelled(&mut actions, job);
+        } else {
+            self.build_job(&mut actions, job, &cancel);
         }
-
-        // TODO: this belongs in the nix module.
-        let status = match spawned.wait() {
-            Ok(s) => match s.code() {
-                Some(0) => BuildStatus::Success,
-                Some(100) => BuildStatus::Failure, // nix permanent failure
-                Some(101) => BuildStatus::TimedOut, // nix build timedout
-                Some(i) => BuildStatus::UnexpectedError {
-                    err: format!("command failed with exit code {}", i),
-                },
-                None => BuildStatus::UnexpectedError {
-                    err: "unexpected build failure".into(),
-                },
-            },
-            e => BuildStatus::UnexpectedError {
-                err: format!("failed on interior command {:?}", e),
-            },
-        };
-
-        println!("ok built ({:?}), building", status);
-        println!("Lines:\n-----8<-----");
-        actions
-            .log_snippet()
-            .iter()
-            .inspect(|x| println!("{}", x))
-            .last();
-        println!("----->8-----");
-
-        actions.build_finished(status, can_build, cannot_build_attrs);
-        println!("Done!");
+        self.running.deregister(job);
     }
 }
 
@@ -419,6 +774,9 @@ mod tests {
             nix,
             "x86_64-linux".to_owned(),
             "cargo-test-build".to_owned(),
+            RunningBuilds::new(),
+            LatestHeads::new(),
+            Default::default(),
         );
 
         worker
@@ -495,9 +853,11 @@ mod tests {
                 owner: "ofborg-test".to_owned(),
             },
             subset: None,
+            systems: None,
             logs: Some((Some(String::from("logs")), Some(String::from("build.log")))),
             statusreport: Some((Some(String::from("build-results")), None)),
             request_id: "bogus-request-id".to_owned(),
+            requested_at: None,
         };
 
         let mut dummyreceiver = notifyworker::DummyNotificationReceiver::new();
@@ -540,9 +900,11 @@ mod tests {
                 owner: "ofborg-test".to_owned(),
             },
             subset: None,
+            systems: None,
             logs: Some((Some(String::from("logs")), Some(String::from("build.log")))),
             statusreport: Some((Some(String::from("build-results")), None)),
             request_id: "bogus-request-id".to_owned(),
+            requested_at: None,
         };
 
         let mut dummyreceiver = notifyworker::DummyNotificationReceiver::new();
@@ -555,9 +917,36 @@ mod tests {
             &mut actions,
             "\"line_number\":1,\"output\":\"Cannot nix-instantiate `not-real\' because:\"",
         );
-        assert_contains_job(&mut actions, "\"line_number\":2,\"output\":\"error: attribute 'not-real' in selection path 'not-real' not found\"}");
+        assert_contains_job(
+            &mut actions,
+            "\"line_number\":2,\"output\":\"Attribute `not-real` is missing\"}",
+        );
         assert_contains_job(&mut actions, "skipped_attrs\":[\"not-real"); // First one to the github poster
         assert_contains_job(&mut actions, "skipped_attrs\":[\"not-real"); // This one to the logs
         assert_eq!(actions.next(), Some(worker::Action::Ack));
     }
+
+    #[test]
+    fn attr_statuses() {
+        assert_eq!(
+            attr_status(&nix::BuildOutcome::PermanentFailure, true),
+            BuildStatus::Failure
+        );
+        assert_eq!(
+            attr_status(&nix::BuildOutcome::PermanentFailure, false),
+            BuildStatus::DependencyFailed
+        );
+        assert_eq!(
+            attr_status(&nix::BuildOutcome::DependencyFailed, true),
+            BuildStatus::Failure
+        );
+        assert_eq!(
+            attr_status(&nix::BuildOutcome::DependencyFailed, false),
+            BuildStatus::DependencyFailed
+        );
+        assert_eq!(
+            attr_status(&nix::BuildOutcome::TimedOut, false),
+            BuildStatus::TimedOut
+        );
+    }
 }
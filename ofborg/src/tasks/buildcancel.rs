@@ -0,0 +1,266 @@
+extern crate amqp;
+extern crate env_logger;
+
+use amqp::protocol::basic::{BasicProperties, Deliver};
+use ofborg::asynccmd::CancelHandle;
+use ofborg::message::buildcancel::{self, BuildCancel};
+use ofborg::message::buildjob::BuildJob;
+use ofborg::message::headupdate::HeadUpdate;
+use ofborg::message::{unix_time, Pr, Repo};
+use ofborg::worker;
+use std::sync::{Arc, Mutex};
+
+/// How long a cancel is remembered, to skip the builds of the PR which
+/// were queued before it.
+const CANCEL_MEMORY_SECS: u64 = 24 * 60 * 60;
+
+/// The builds running on this builder which can be cancelled, and the
+/// recent cancels, shared between the `BuildWorker` and the
+/// `BuildCancelWorker`.
+#[derive(Clone, Default)]
+pub struct RunningBuilds {
+    builds: Arc<Mutex<Vec<RunningBuild>>>,
+    cancels: Arc<Mutex<Vec<RecordedCancel>>>,
+}
+
+struct RunningBuild {
+    repo: Repo,
+    pr: Pr,
+    request_id: String,
+    cancel: CancelHandle,
+}
+
+struct RecordedCancel {
+    request: BuildCancel,
+    at: u64,
+}
+
+impl RunningBuilds {
+    pub fn new() -> RunningBuilds {
+        Default::default()
+    }
+
+    pub fn register(&self, job: &BuildJob, cancel: CancelHandle) {
+        self.builds.lock().unwrap().push(RunningBuild {
+            repo: job.repo.clone(),
+            pr: job.pr.clone(),
+            request_id: job.request_id.clone(),
+            cancel,
+        });
+    }
+
+    pub fn deregister(&self, job: &BuildJob) {
+        self.builds
+            .lock()
+            .unwrap()
+            .retain(|build| build.request_id != job.request_id);
+    }
+
+    /// Cancel every running build matching the request, returning
+    /// how many builds were cancelled. The request is remembered so
+    /// matching builds which are still queued are skipped.
+    pub fn cancel(&self, request: &BuildCancel) -> usize {
+        let now = unix_time();
+        {
+            let mut cancels = self.cancels.lock().unwrap();
+            cancels.retain(|cancel| cancel.at + CANCEL_MEMORY_SECS > now);
+            cancels.push(RecordedCancel {
+                request: request.clone(),
+                at: request.requested_at.unwrap_or(now),
+            });
+        }
+
+        self.cancel_where(|build| request.matches(&build.repo, &build.pr, &build.request_id))
+    }
+
+    /// Whether the job was requested before a cancel matching it, so it
+    /// should be skipped instead of built.
+    pub fn was_cancelled(&self, job: &BuildJob) -> bool {
+        let requested_at = match job.requested_at {
+            Some(at) => at,
+            None => return false,
+        };
+
+        self.cancels.lock().unwrap().iter().any(|cancel| {
+            requested_at < cancel.at && cancel.request.matches(&job.repo, &job.pr, &job.request_id)
+        })
+    }
+
+    /// Cancel every running build of an older commit of the updated
+    /// PR, returning how many builds were cancelled.
+    pub fn supersede(&self, update: &HeadUpdate) -> usize {
+        self.cancel_where(|build| {
+            build.repo.full_name == update.repo.full_name
+                && build.pr.number == update.pr.number
+                && build.pr.head_sha != update.pr.head_sha
+        })
+    }
+
+    fn cancel_where<F>(&self, predicate: F) -> usize
+    where
+        F: Fn(&RunningBuild) -> bool,
+    {
+        let builds = self.builds.lock().unwrap();
+        let matching: Vec<&RunningBuild> = builds.iter().filter(|build| predicate(build)).collect();
+
+        for build in matching.iter() {
+            build.cancel.cancel();
+        }
+
+        matching.len()
+    }
+}
+
+pub struct BuildCancelWorker {
+    running: RunningBuilds,
+}
+
+impl BuildCancelWorker {
+    pub fn new(running: RunningBuilds) -> BuildCancelWorker {
+        BuildCancelWorker { running }
+    }
+}
+
+impl worker::SimpleWorker for BuildCancelWorker {
+    type J = BuildCancel;
+
+    fn msg_to_job(
+        &mut self,
+        _: &Deliver,
+        _: &BasicProperties,
+        body: &[u8],
+    ) -> Result<Self::J, String> {
+        buildcancel::from(body).map_err(|e| {
+            format!(
+                "Failed to deserialize BuildCancel: {:?}, err: {:}",
+                String::from_utf8_lossy(body),
+                e
+            )
+        })
+    }
+
+    fn consumer(&mut self, job: &BuildCancel) -> worker::Actions {
+        let cancelled = self.running.cancel(job);
+        info!(
+            "Cancelled {} build(s) for {}#{}",
+            cancelled, job.repo.full_name, job.pr.number
+        );
+
+        vec![worker::Action::Ack]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ofborg::commentparser::Subset;
+
+    fn make_job(number: u64, request_id: &str) -> BuildJob {
+        BuildJob::new(
+            Repo {
+                clone_url: "https://github.com/nixos/nixpkgs.git".to_owned(),
+                full_name: "NixOS/nixpkgs".to_owned(),
+                owner: "NixOS".to_owned(),
+                name: "nixpkgs".to_owned(),
+            },
+            Pr {
+                head_sha: "abc123".to_owned(),
+                number,
+                target_branch: Some("master".to_owned()),
+            },
+            Subset::Nixpkgs,
+            vec!["hello".to_owned()],
+            None,
+            None,
+            request_id.to_owned(),
+        )
+    }
+
+    fn make_cancel(number: u64, request_id: Option<&str>) -> BuildCancel {
+        let job = make_job(number, "");
+        BuildCancel {
+            repo: job.repo,
+            pr: job.pr,
+            request_id: request_id.map(|id| id.to_owned()),
+            requested_at: None,
+        }
+    }
+
+    #[test]
+    fn cancel_matching_pr() {
+        let running = RunningBuilds::new();
+        let mine = CancelHandle::new();
+        let other = CancelHandle::new();
+        running.register(&make_job(1, "a"), mine.clone());
+        running.register(&make_job(2, "b"), other.clone());
+
+        assert_eq!(running.cancel(&make_cancel(1, None)), 1);
+        assert!(mine.is_cancelled());
+        assert!(!other.is_cancelled());
+    }
+
+    #[test]
+    fn cancel_matching_request_id() {
+        let running = RunningBuilds::new();
+        let mine = CancelHandle::new();
+        let other = CancelHandle::new();
+        running.register(&make_job(1, "a"), mine.clone());
+        running.register(&make_job(1, "b"), other.clone());
+
+        assert_eq!(running.cancel(&make_cancel(1, Some("b"))), 1);
+        assert!(!mine.is_cancelled());
+        assert!(other.is_cancelled());
+    }
+
+    #[test]
+    fn supersede_older_heads() {
+        let running = RunningBuilds::new();
+        let old = CancelHandle::new();
+        let other = CancelHandle::new();
+        running.register(&make_job(1, "a"), old.clone());
+        running.register(&make_job(2, "b"), other.clone());
+
+        let mut update = make_cancel(1, None);
+        update.pr.head_sha = "def456".to_owned();
+        let update = HeadUpdate {
+            repo: update.repo,
+            pr: update.pr,
+        };
+
+        assert_eq!(running.supersede(&update), 1);
+        assert!(old.is_cancelled());
+        assert!(!other.is_cancelled());
+    }
+
+    #[test]
+    fn cancel_after_deregister() {
+        let running = RunningBuilds::new();
+        let handle = CancelHandle::new();
+        let job = make_job(1, "a");
+        running.register(&job, handle.clone());
+        running.deregister(&job);
+
+        assert_eq!(running.cancel(&make_cancel(1, None)), 0);
+        assert!(!handle.is_cancelled());
+    }
+
+    #[test]
+    fn queued_jobs_of_cancelled_prs() {
+        let running = RunningBuilds::new();
+        let mut queued = make_job(1, "a");
+        queued.requested_at = Some(100);
+        let mut other = make_job(2, "b");
+        other.requested_at = Some(100);
+
+        let mut cancel = make_cancel(1, None);
+        cancel.requested_at = Some(200);
+        assert_eq!(running.cancel(&cancel), 0);
+
+        assert!(running.was_cancelled(&queued));
+        assert!(!running.was_cancelled(&other));
+
+        let mut later = make_job(1, "c");
+        later.requested_at = Some(300);
+        assert!(!running.was_cancelled(&later));
+    }
+}
@@ -1,45 +1,133 @@
+use hubcaps::issues::IssueRef;
 use ofborg::checkout::CachedProjectCo;
-use ofborg::commitstatus::CommitStatus;
-use ofborg::evalchecker::EvalChecker;
-use std::path::Path;
-use tasks::eval::{EvaluationComplete, EvaluationStrategy, StepResult};
+use ofborg::checkrun::EvalCheckRun;
+use ofborg::evalchecker::{checkers_for_branch, load_repo_checks, EvalCheckSpec, EvalChecker};
+use ofborg::manifest::Manifest;
+use ofborg::message::evaluationjob::EvaluationJob;
+use ofborg::message::evaluationreport::EvaluationReport;
+use ofborg::nix::Nix;
+use ofborg::tagger::PathsTagger;
+use ofborg::tasks::evaluate::update_labels;
+use std::path::{Path, PathBuf};
+use tasks::eval::{Error, EvaluationComplete, EvaluationStrategy, StepResult};
 
-#[derive(Default)]
-pub struct GenericStrategy {}
-impl GenericStrategy {
-    pub fn new() -> GenericStrategy {
-        Self {}
+/// Evaluates PRs to repositories other than nixpkgs, as described by
+/// the `Manifest` in the PR.
+pub struct GenericStrategy<'a> {
+    job: &'a EvaluationJob,
+    issue_ref: &'a IssueRef<'a>,
+    nix: Nix,
+    eval_checks: Vec<EvalCheckSpec>,
+    checkout: Option<PathBuf>,
+    changed_paths: Option<Vec<String>>,
+    manifest: Manifest,
+}
+
+impl<'a> GenericStrategy<'a> {
+    pub fn new(
+        job: &'a EvaluationJob,
+        issue_ref: &'a IssueRef,
+        nix: Nix,
+        eval_checks: Vec<EvalCheckSpec>,
+    ) -> GenericStrategy<'a> {
+        Self {
+            job,
+            issue_ref,
+            nix,
+            eval_checks,
+            checkout: None,
+            changed_paths: None,
+            manifest: Manifest::default(),
+        }
+    }
+
+    fn target_branch(&self) -> &str {
+        match self.job.pr.target_branch {
+            Some(ref branch) => branch,
+            None => "master",
+        }
+    }
+
+    fn tag_from_paths(&self) {
+        if self.manifest.labels.is_empty() {
+            return;
+        }
+
+        if let Some(ref changed_paths) = self.changed_paths {
+            let mut tagger = PathsTagger::new(self.manifest.labels.clone());
+
+            for path in changed_paths {
+                tagger.path_changed(path);
+            }
+
+            update_labels(
+                self.issue_ref,
+                &tagger.tags_to_add(),
+                &tagger.tags_to_remove(),
+            );
+        }
     }
 }
 
-impl EvaluationStrategy for GenericStrategy {
+impl<'a> EvaluationStrategy for GenericStrategy<'a> {
     fn pre_clone(&mut self) -> StepResult<()> {
         Ok(())
     }
 
-    fn on_target_branch(&mut self, _co: &Path, _status: &mut CommitStatus) -> StepResult<()> {
+    fn on_target_branch(
+        &mut self,
+        co: &Path,
+        _base_sha: &str,
+        _status: &mut EvalCheckRun,
+    ) -> StepResult<()> {
+        if let Some(checks) = load_repo_checks(co).map_err(Error::Fail)? {
+            self.eval_checks = checks;
+        }
+        self.checkout = Some(co.to_path_buf());
+
         Ok(())
     }
 
-    fn after_fetch(&mut self, _co: &CachedProjectCo) -> StepResult<()> {
+    fn after_fetch(&mut self, co: &CachedProjectCo) -> StepResult<()> {
+        self.changed_paths = Some(
+            co.files_changed_from_head(&self.job.pr.head_sha)
+                .unwrap_or_else(|_| vec![]),
+        );
+
         Ok(())
     }
 
     fn merge_conflict(&mut self) {}
 
-    fn after_merge(&mut self, _status: &mut CommitStatus) -> StepResult<()> {
+    fn after_merge(&mut self, status: &mut EvalCheckRun) -> StepResult<()> {
+        if let Some(ref checkout) = self.checkout {
+            status.set_progress("Reading the manifest");
+            if let Some(manifest) = Manifest::load(checkout).map_err(Error::Fail)? {
+                self.manifest = manifest;
+            }
+        }
+
+        self.tag_from_paths();
+
         Ok(())
     }
 
     fn evaluation_checks(&self) -> Vec<EvalChecker> {
-        vec![]
+        let mut checkers = checkers_for_branch(&self.eval_checks, self.target_branch(), &self.nix);
+        checkers.extend(self.manifest.checkers(&self.nix));
+        checkers
     }
 
     fn all_evaluations_passed(
         &mut self,
         _co: &Path,
-        _status: &mut CommitStatus,
+        _status: &mut EvalCheckRun,
     ) -> StepResult<EvaluationComplete> {
-        Ok(Default::default())
+        Ok(EvaluationComplete {
+            builds: self.manifest.build_jobs(self.job),
+            checks: vec![],
+        })
     }
+
+    fn add_to_report(&self, _report: &mut EvaluationReport) {}
 }
@@ -1,29 +1,36 @@
 pub mod stdenvs;
 pub use self::stdenvs::Stdenvs;
 mod nixpkgs;
-pub use self::nixpkgs::NixpkgsStrategy;
+pub use self::nixpkgs::{default_checks as default_nixpkgs_checks, NixpkgsStrategy};
 mod generic;
 pub use self::generic::GenericStrategy;
 use hubcaps::checks::CheckRunOptions;
 use ofborg::checkout::CachedProjectCo;
-use ofborg::commitstatus::CommitStatus;
+use ofborg::checkrun::EvalCheckRun;
 use ofborg::evalchecker::EvalChecker;
 use ofborg::message::buildjob::BuildJob;
+use ofborg::message::evaluationreport::EvaluationReport;
 use std::path::Path;
 
 pub trait EvaluationStrategy {
     fn pre_clone(&mut self) -> StepResult<()>;
 
-    fn on_target_branch(&mut self, co: &Path, status: &mut CommitStatus) -> StepResult<()>;
+    fn on_target_branch(
+        &mut self,
+        co: &Path,
+        base_sha: &str,
+        status: &mut EvalCheckRun,
+    ) -> StepResult<()>;
     fn after_fetch(&mut self, co: &CachedProjectCo) -> StepResult<()>;
     fn merge_conflict(&mut self);
-    fn after_merge(&mut self, status: &mut CommitStatus) -> StepResult<()>;
+    fn after_merge(&mut self, status: &mut EvalCheckRun) -> StepResult<()>;
     fn evaluation_checks(&self) -> Vec<EvalChecker>;
     fn all_evaluations_passed(
         &mut self,
         co: &Path,
-        status: &mut CommitStatus,
+        status: &mut EvalCheckRun,
     ) -> StepResult<EvaluationComplete>;
+    fn add_to_report(&self, report: &mut EvaluationReport);
 }
 
 pub type StepResult<T> = Result<T, Error>;
@@ -1,29 +1,37 @@
 use crate::maintainers;
 use crate::maintainers::ImpactedMaintainers;
 use crate::nixenv::HydraNixEnv;
+use crate::nixstats::Regression;
 use chrono::Utc;
 use hubcaps::checks::{CheckRunOptions, CheckRunState, Conclusion, Output};
-use hubcaps::gists::Gists;
 use hubcaps::issues::{Issue, IssueRef};
 use hubcaps::repositories::Repository;
+use ofborg::artifacts::ArtifactStore;
 use ofborg::checkout::CachedProjectCo;
+use ofborg::checkrun::{annotations, failure_text, output_excerpt, rebuild_counts, EvalCheckRun};
+use ofborg::closuresize;
 use ofborg::commentparser::Subset;
-use ofborg::commitstatus::CommitStatus;
-use ofborg::evalchecker::EvalChecker;
+use ofborg::config::{AutoBuildConfig, AutoBuildMode, ClosureSizeConfig, PerformanceConfig};
+use ofborg::evalchecker::{
+    checkers_for_branch, load_repo_checks, CheckOperation, EvalCheckSpec, EvalChecker,
+};
 use ofborg::message::buildjob::BuildJob;
 use ofborg::message::evaluationjob::EvaluationJob;
-use ofborg::nix;
+use ofborg::message::evaluationreport::{EvaluationReport, EvaluationStatsReport};
 use ofborg::nix::Nix;
+use ofborg::outpathcache::OutPathCache;
 use ofborg::outpathdiff::{OutPathDiff, PackageArch};
-use ofborg::tagger::{MaintainerPRTagger, PathsTagger, RebuildTagger};
+use ofborg::rebuildsummary;
+use ofborg::systems::System;
+use ofborg::tagger::{MaintainerPRTagger, PathsTagger, RebuildBucket, RebuildTagger};
 use ofborg::tagger::{PkgsAddedRemovedTagger, StdenvTagger};
 use ofborg::tasks::eval::{
     stdenvs::Stdenvs, Error, EvaluationComplete, EvaluationStrategy, StepResult,
 };
 use ofborg::tasks::evaluate::update_labels;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
-use tasks::evaluate::make_gist;
+use tasks::evaluate::store_artifact;
 use uuid::Uuid;
 
 pub struct NixpkgsStrategy<'a> {
@@ -32,13 +40,22 @@ pub struct NixpkgsStrategy<'a> {
     issue: &'a Issue,
     issue_ref: &'a IssueRef<'a>,
     repo: &'a Repository<'a>,
-    gists: &'a Gists<'a>,
+    artifacts: &'a dyn ArtifactStore,
     nix: Nix,
     tag_paths: &'a HashMap<String, Vec<String>>,
+    systems: &'a [System],
+    auto_build: &'a AutoBuildConfig,
+    rebuild_buckets: &'a [RebuildBucket],
+    performance: &'a PerformanceConfig,
+    closure_size: &'a ClosureSizeConfig,
+    eval_checks: Vec<EvalCheckSpec>,
+    outpath_cache: Option<&'a OutPathCache>,
     stdenv_diff: Option<Stdenvs>,
     outpath_diff: Option<OutPathDiff>,
     changed_paths: Option<Vec<String>>,
+    changed_paths_url: Option<String>,
     touched_packages: Option<Vec<String>>,
+    impacted_maintainers: Option<BTreeMap<String, Vec<String>>>,
 }
 
 impl<'a> NixpkgsStrategy<'a> {
@@ -49,9 +66,16 @@ impl<'a> NixpkgsStrategy<'a> {
         issue: &'a Issue,
         issue_ref: &'a IssueRef,
         repo: &'a Repository,
-        gists: &'a Gists,
+        artifacts: &'a dyn ArtifactStore,
         nix: Nix,
         tag_paths: &'a HashMap<String, Vec<String>>,
+        systems: &'a [System],
+        auto_build: &'a AutoBuildConfig,
+        rebuild_buckets: &'a [RebuildBucket],
+        performance: &'a PerformanceConfig,
+        closure_size: &'a ClosureSizeConfig,
+        eval_checks: Vec<EvalCheckSpec>,
+        outpath_cache: Option<&'a OutPathCache>,
     ) -> NixpkgsStrategy<'a> {
         Self {
             job,
@@ -59,13 +83,29 @@ impl<'a> NixpkgsStrategy<'a> {
             issue,
             issue_ref,
             repo,
-            gists,
+            artifacts,
             nix,
             tag_paths,
+            systems,
+            auto_build,
+            rebuild_buckets,
+            performance,
+            closure_size,
+            eval_checks,
+            outpath_cache,
             stdenv_diff: None,
             outpath_diff: None,
             changed_paths: None,
+            changed_paths_url: None,
             touched_packages: None,
+            impacted_maintainers: None,
+        }
+    }
+
+    fn target_branch(&self) -> &str {
+        match self.job.pr.target_branch {
+            Some(ref branch) => branch,
+            None => "master",
         }
     }
 
@@ -101,7 +141,13 @@ impl<'a> NixpkgsStrategy<'a> {
     }
 
     fn check_stdenvs_before(&mut self, dir: &Path) {
-        let mut stdenvs = Stdenvs::new(self.nix.clone(), dir.to_path_buf());
+        let stdenv_systems: Vec<String> = self
+            .systems
+            .iter()
+            .filter(|system| system.rebuild_label.is_some())
+            .map(|system| system.name.clone())
+            .collect();
+        let mut stdenvs = Stdenvs::new(self.nix.clone(), dir.to_path_buf(), stdenv_systems);
         stdenvs.identify_before();
         self.stdenv_diff = Some(stdenvs);
     }
@@ -114,7 +160,7 @@ impl<'a> NixpkgsStrategy<'a> {
 
     fn update_stdenv_labels(&self) {
         if let Some(ref stdenvs) = self.stdenv_diff {
-            let mut stdenvtagger = StdenvTagger::new();
+            let mut stdenvtagger = StdenvTagger::for_systems(self.systems);
             if !stdenvs.are_same() {
                 stdenvtagger.changed(stdenvs.changed());
             }
@@ -126,10 +172,15 @@ impl<'a> NixpkgsStrategy<'a> {
         }
     }
 
-    fn check_outpaths_before(&mut self, dir: &Path) -> StepResult<()> {
+    fn check_outpaths_before(&mut self, dir: &Path, base_sha: &str) -> StepResult<()> {
         let mut rebuildsniff = OutPathDiff::new(self.nix.clone(), dir.to_path_buf());
 
-        if let Err(err) = rebuildsniff.find_before() {
+        let result = match self.outpath_cache {
+            Some(cache) => rebuildsniff.find_before_cached(cache, base_sha).map(|_| ()),
+            None => rebuildsniff.find_before(),
+        };
+
+        if let Err(err) = result {
             /*
             self.events
                 .notify(Event::TargetBranchFailsEvaluation(target_branch.clone()));
@@ -167,6 +218,30 @@ impl<'a> NixpkgsStrategy<'a> {
     fn performance_stats(&self) -> Vec<CheckRunOptions> {
         if let Some(ref rebuildsniff) = self.outpath_diff {
             if let Some(report) = rebuildsniff.performance_diff() {
+                let regressions = report.regressions(&self.performance.thresholds);
+                self.update_performance_label(&regressions);
+
+                let (conclusion, title) = if regressions.is_empty() {
+                    (Conclusion::Success, "Evaluator Performance Report")
+                } else if self.performance.action_required {
+                    (
+                        Conclusion::ActionRequired,
+                        "Evaluator Performance Regression",
+                    )
+                } else {
+                    (Conclusion::Failure, "Evaluator Performance Regression")
+                };
+                let summary = regressions
+                    .iter()
+                    .map(|r| {
+                        format!(
+                            "- **{}** increased by {:.2}%, more than {}%",
+                            r.stat, r.increase, r.threshold
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n");
+
                 return vec![CheckRunOptions {
                     name: "Evaluation Performance Report".to_owned(),
                     actions: None,
@@ -174,14 +249,14 @@ impl<'a> NixpkgsStrategy<'a> {
                         Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
                     ),
                     started_at: None,
-                    conclusion: Some(Conclusion::Success),
+                    conclusion: Some(conclusion),
                     status: Some(CheckRunState::Completed),
                     details_url: None,
                     external_id: None,
                     head_sha: self.job.pr.head_sha.clone(),
                     output: Some(Output {
-                        title: "Evaluator Performance Report".to_string(),
-                        summary: "".to_string(),
+                        title: title.to_string(),
+                        summary,
                         text: Some(report.markdown()),
                         annotations: None,
                         images: None,
@@ -192,6 +267,19 @@ impl<'a> NixpkgsStrategy<'a> {
         vec![]
     }
 
+    fn update_performance_label(&self, regressions: &[Regression]) {
+        if self.performance.thresholds.is_empty() {
+            return;
+        }
+
+        let label = vec![self.performance.label()];
+        if regressions.is_empty() {
+            update_labels(self.issue_ref, &[], &label);
+        } else {
+            update_labels(self.issue_ref, &label, &[]);
+        }
+    }
+
     fn update_new_package_labels(&self) {
         if let Some(ref rebuildsniff) = self.outpath_diff {
             if let Some((removed, added)) = rebuildsniff.package_diff() {
@@ -206,14 +294,21 @@ impl<'a> NixpkgsStrategy<'a> {
         }
     }
 
-    fn update_rebuild_labels(&self, dir: &Path, overall_status: &mut CommitStatus) {
+    fn update_rebuild_labels(&mut self, dir: &Path, status: &mut EvalCheckRun) {
+        let mut impacted_maintainers = None;
+
         if let Some(ref rebuildsniff) = self.outpath_diff {
-            let mut rebuild_tags = RebuildTagger::new();
+            let mut rebuild_tags = RebuildTagger::with_buckets(self.systems, self.rebuild_buckets);
+            rebuild_tags.evaluated_on(&rebuildsniff.architectures());
 
             if let Some(attrs) = rebuildsniff.calculate_rebuild() {
-                if !attrs.is_empty() {
-                    overall_status.set_url(self.gist_changed_paths(&attrs));
-                    self.record_impacted_maintainers(&dir, &attrs);
+                if attrs.is_empty() {
+                    status.set_summary(String::from("No packages are rebuilt."));
+                } else {
+                    status.set_summary(rebuild_counts(&attrs).join("\n"));
+                    self.changed_paths_url = self.store_changed_paths(&attrs);
+                    status.set_url(self.changed_paths_url.clone());
+                    impacted_maintainers = self.record_impacted_maintainers(&dir, &attrs);
                 }
 
                 rebuild_tags.parse_attrs(attrs.clone());
@@ -225,13 +320,74 @@ impl<'a> NixpkgsStrategy<'a> {
                 &rebuild_tags.tags_to_remove(),
             );
         }
+
+        self.impacted_maintainers = impacted_maintainers;
     }
 
-    fn gist_changed_paths(&self, attrs: &[PackageArch]) -> Option<String> {
-        make_gist(
-            &self.gists,
+    fn post_rebuild_summary(&self) {
+        let mut report = EvaluationReport::new(self.job, "");
+        self.add_to_report(&mut report);
+
+        let url = self.changed_paths_url.as_ref().map(|url| &url[..]);
+        if let Some(body) = rebuildsummary::comment(&report, url) {
+            rebuildsummary::post(self.issue_ref, body, !rebuildsummary::is_empty(&report));
+        }
+    }
+
+    /// Flag the rebuilt packages whose closure grew too much, out of
+    /// those whose out paths before and after are in the store.
+    fn check_closure_sizes(&self) {
+        let max_attrs = self.closure_size.max_attrs();
+        let changes = match self.outpath_diff {
+            Some(ref diff) if max_attrs > 0 => closuresize::compare(&self.nix, diff, max_attrs),
+            _ => return,
+        };
+        if changes.is_empty() {
+            return;
+        }
+
+        let regressions: Vec<String> = changes
+            .iter()
+            .filter(|change| change.is_regression(self.closure_size))
+            .flat_map(|change| change.markdown())
+            .collect();
+
+        let mut status = EvalCheckRun::new(
+            self.repo.checkruns(),
+            self.job.pr.head_sha.clone(),
+            String::from("grahamcofborg-eval-closure-size"),
+            String::from("Comparing the closures of the rebuilt packages."),
+        );
+        status.set_text(Some(
+            changes
+                .iter()
+                .flat_map(|change| change.markdown())
+                .collect::<Vec<String>>()
+                .join("\n"),
+        ));
+
+        if regressions.is_empty() {
+            status.set_summary(format!(
+                "Compared the closures of {} rebuilt packages.",
+                changes.len()
+            ));
+            status.complete(Conclusion::Success, "No closure grew too much");
+        } else {
+            status.set_summary(format!(
+                "These closures grew by more than {} MiB and {}%:\n\n{}",
+                self.closure_size.max_increase_mb(),
+                self.closure_size.max_increase_percent(),
+                regressions.join("\n")
+            ));
+            status.complete(Conclusion::Failure, "Closure size regression");
+        }
+    }
+
+    fn store_changed_paths(&self, attrs: &[PackageArch]) -> Option<String> {
+        store_artifact(
+            self.artifacts,
+            self.job,
             "Changed Paths",
-            Some("".to_owned()),
             attrs
                 .iter()
                 .map(|attr| format!("{}\t{}", &attr.architecture, &attr.package))
@@ -240,7 +396,13 @@ impl<'a> NixpkgsStrategy<'a> {
         )
     }
 
-    fn record_impacted_maintainers(&self, dir: &Path, attrs: &[PackageArch]) {
+    /// Request reviews from the maintainers of the changed attributes,
+    /// returning the changed packages of each maintainer.
+    fn record_impacted_maintainers(
+        &self,
+        dir: &Path,
+        attrs: &[PackageArch],
+    ) -> Option<BTreeMap<String, Vec<String>>> {
         let changed_attributes = attrs
             .iter()
             .map(|attr| attr.package.split('.').collect::<Vec<&str>>())
@@ -254,24 +416,29 @@ impl<'a> NixpkgsStrategy<'a> {
                 &changed_attributes,
             );
 
-            let gist_url = make_gist(
-                &self.gists,
-                "Potential Maintainers",
-                Some("".to_owned()),
-                match m {
-                    Ok(ref maintainers) => format!("Maintainers:\n{}", maintainers),
-                    Err(ref e) => format!("Ignorable calculation error:\n{:?}", e),
-                },
-            );
+            let contents = match m {
+                Ok(ref maintainers) => format!("Maintainers:\n{}", maintainers),
+                Err(ref e) => format!("Ignorable calculation error:\n{:?}", e),
+            };
 
-            let status = CommitStatus::new(
-                self.repo.statuses(),
+            let mut status = EvalCheckRun::new(
+                self.repo.checkruns(),
                 self.job.pr.head_sha.clone(),
                 String::from("grahamcofborg-eval-check-maintainers"),
-                String::from("matching changed paths to changed attrs..."),
-                gist_url,
+                String::from("Matching the changed paths to the changed attributes."),
             );
-            status.set(hubcaps::statuses::State::Success);
+            status.set_text(Some(output_excerpt(&contents)));
+            status.set_url(store_artifact(
+                self.artifacts,
+                self.job,
+                "Potential Maintainers",
+                contents,
+            ));
+            if m.is_ok() {
+                status.complete(Conclusion::Success, "Found the impacted maintainers");
+            } else {
+                status.complete(Conclusion::Neutral, "Ignorable calculation error");
+            }
 
             if let Ok(ref maint) = m {
                 request_reviews(&maint, &self.pull);
@@ -284,39 +451,68 @@ impl<'a> NixpkgsStrategy<'a> {
                     &maint_tagger.tags_to_remove(),
                 );
             }
+
+            m.ok().map(|maint| maint.packages_by_maintainer())
+        } else {
+            None
         }
     }
 
     fn check_meta_queue_builds(&self, dir: &Path) -> StepResult<Vec<BuildJob>> {
         if let Some(ref possibly_touched_packages) = self.touched_packages {
-            let mut status = CommitStatus::new(
-                self.repo.statuses(),
+            let mut status = EvalCheckRun::new(
+                self.repo.checkruns(),
                 self.job.pr.head_sha.clone(),
                 String::from("grahamcofborg-eval-check-meta"),
-                String::from("config.nix: checkMeta = true"),
-                None,
+                String::from("`config.nix: checkMeta = true`"),
             );
-            status.set(hubcaps::statuses::State::Pending);
+            status.set_progress("Checking the package metadata");
 
             let nixenv = HydraNixEnv::new(self.nix.clone(), dir.to_path_buf(), true);
             match nixenv.execute_with_stats() {
                 Ok((pkgs, _stats)) => {
-                    let mut try_build: Vec<String> = pkgs
-                        .keys()
-                        .map(|pkgarch| pkgarch.package.clone())
-                        .filter(|pkg| possibly_touched_packages.contains(&pkg))
-                        .collect();
-                    try_build.sort();
-                    try_build.dedup();
-
-                    status.set_url(None);
-                    status.set(hubcaps::statuses::State::Success);
-
-                    if !try_build.is_empty() && try_build.len() <= 10 {
-                        // In the case of trying to merge master in to
-                        // a stable branch, we don't want to do this.
-                        // Therefore, only schedule builds if there
-                        // less than or exactly 10
+                    let evaluated: HashSet<&String> =
+                        pkgs.keys().map(|pkgarch| &pkgarch.package).collect();
+                    let max_attrs = self.auto_build.max_attrs();
+
+                    let try_build: Vec<String> = match self.auto_build.mode {
+                        AutoBuildMode::CommitMessages => {
+                            let mut try_build: Vec<String> = evaluated
+                                .iter()
+                                .filter(|pkg| possibly_touched_packages.contains(pkg))
+                                .map(|pkg| (*pkg).clone())
+                                .collect();
+                            try_build.sort();
+                            try_build.dedup();
+
+                            // In the case of trying to merge master in to
+                            // a stable branch, we don't want to do this.
+                            // Therefore, only schedule builds if there
+                            // less than or exactly max_attrs
+                            if try_build.len() <= max_attrs {
+                                try_build
+                            } else {
+                                vec![]
+                            }
+                        }
+                        AutoBuildMode::ChangedAttrs => {
+                            let rebuilds = self
+                                .outpath_diff
+                                .as_ref()
+                                .and_then(|diff| diff.calculate_rebuild())
+                                .unwrap_or_default();
+
+                            prioritise_rebuilds(&rebuilds)
+                                .into_iter()
+                                .filter(|pkg| evaluated.contains(pkg))
+                                .take(max_attrs)
+                                .collect()
+                        }
+                    };
+
+                    status.complete(Conclusion::Success, "The package metadata is valid");
+
+                    if !try_build.is_empty() {
                         Ok(vec![BuildJob::new(
                             self.job.repo.clone(),
                             self.job.pr.clone(),
@@ -331,8 +527,16 @@ impl<'a> NixpkgsStrategy<'a> {
                     }
                 }
                 Err(out) => {
-                    status.set_url(make_gist(&self.gists, "Meta Check", None, out.display()));
-                    status.set(hubcaps::statuses::State::Failure);
+                    let output = out.display();
+                    status.set_text(Some(failure_text(&output)));
+                    status.set_annotations(annotations(&output, dir, "Meta check"));
+                    status.set_url(store_artifact(
+                        self.artifacts,
+                        self.job,
+                        "Meta Check",
+                        output,
+                    ));
+                    status.complete(Conclusion::Failure, "Failed to validate package metadata");
                     Err(Error::Fail(String::from(
                         "Failed to validate package metadata.",
                     )))
@@ -350,18 +554,21 @@ impl<'a> EvaluationStrategy for NixpkgsStrategy<'a> {
         Ok(())
     }
 
-    fn on_target_branch(&mut self, dir: &Path, status: &mut CommitStatus) -> StepResult<()> {
-        status.set_with_description(
-            "Checking original stdenvs",
-            hubcaps::statuses::State::Pending,
-        );
+    fn on_target_branch(
+        &mut self,
+        dir: &Path,
+        base_sha: &str,
+        status: &mut EvalCheckRun,
+    ) -> StepResult<()> {
+        if let Some(checks) = load_repo_checks(dir).map_err(Error::Fail)? {
+            self.eval_checks = checks;
+        }
+
+        status.set_progress("Checking original stdenvs");
         self.check_stdenvs_before(dir);
 
-        status.set_with_description(
-            "Checking original out paths",
-            hubcaps::statuses::State::Pending,
-        );
-        self.check_outpaths_before(dir)?;
+        status.set_progress("Checking original out paths");
+        self.check_outpaths_before(dir, base_sha)?;
 
         Ok(())
     }
@@ -389,155 +596,130 @@ impl<'a> EvaluationStrategy for NixpkgsStrategy<'a> {
         );
     }
 
-    fn after_merge(&mut self, status: &mut CommitStatus) -> StepResult<()> {
+    fn after_merge(&mut self, status: &mut EvalCheckRun) -> StepResult<()> {
         update_labels(
             &self.issue_ref,
             &[],
             &["2.status: merge conflict".to_owned()],
         );
 
-        status.set_with_description("Checking new stdenvs", hubcaps::statuses::State::Pending);
+        status.set_progress("Checking new stdenvs");
         self.check_stdenvs_after();
 
-        status.set_with_description("Checking new out paths", hubcaps::statuses::State::Pending);
+        status.set_progress("Checking new out paths");
         self.check_outpaths_after()?;
 
         Ok(())
     }
 
     fn evaluation_checks(&self) -> Vec<EvalChecker> {
-        vec![
-            EvalChecker::new(
-                "package-list",
-                nix::Operation::QueryPackagesJSON,
-                vec![String::from("--file"), String::from(".")],
-                self.nix.clone(),
-            ),
-            EvalChecker::new(
-                "package-list-no-aliases",
-                nix::Operation::QueryPackagesJSON,
-                vec![
-                    String::from("--file"),
-                    String::from("."),
-                    String::from("--arg"),
-                    String::from("config"),
-                    String::from("{ allowAliases = false; }"),
-                ],
-                self.nix.clone(),
-            ),
-            EvalChecker::new(
-                "nixos",
-                nix::Operation::Instantiate,
-                vec![
-                    String::from("--arg"),
-                    String::from("nixpkgs"),
-                    String::from("{ outPath=./.; revCount=999999; shortRev=\"ofborg\"; }"),
-                    String::from("./nixos/release-combined.nix"),
-                    String::from("-A"),
-                    String::from("tested"),
-                ],
-                self.nix.clone(),
-            ),
-            EvalChecker::new(
-                "nixos-options",
-                nix::Operation::Instantiate,
-                vec![
-                    String::from("--arg"),
-                    String::from("nixpkgs"),
-                    String::from("{ outPath=./.; revCount=999999; shortRev=\"ofborg\"; }"),
-                    String::from("./nixos/release.nix"),
-                    String::from("-A"),
-                    String::from("options"),
-                ],
-                self.nix.clone(),
-            ),
-            EvalChecker::new(
-                "nixos-manual",
-                nix::Operation::Instantiate,
-                vec![
-                    String::from("--arg"),
-                    String::from("nixpkgs"),
-                    String::from("{ outPath=./.; revCount=999999; shortRev=\"ofborg\"; }"),
-                    String::from("./nixos/release.nix"),
-                    String::from("-A"),
-                    String::from("manual"),
-                ],
-                self.nix.clone(),
-            ),
-            EvalChecker::new(
-                "nixpkgs-manual",
-                nix::Operation::Instantiate,
-                vec![
-                    String::from("--arg"),
-                    String::from("nixpkgs"),
-                    String::from("{ outPath=./.; revCount=999999; shortRev=\"ofborg\"; }"),
-                    String::from("./pkgs/top-level/release.nix"),
-                    String::from("-A"),
-                    String::from("manual"),
-                ],
-                self.nix.clone(),
-            ),
-            EvalChecker::new(
-                "nixpkgs-tarball",
-                nix::Operation::Instantiate,
-                vec![
-                    String::from("--arg"),
-                    String::from("nixpkgs"),
-                    String::from("{ outPath=./.; revCount=999999; shortRev=\"ofborg\"; }"),
-                    String::from("./pkgs/top-level/release.nix"),
-                    String::from("-A"),
-                    String::from("tarball"),
-                ],
-                self.nix.clone(),
-            ),
-            EvalChecker::new(
-                "nixpkgs-unstable-jobset",
-                nix::Operation::Instantiate,
-                vec![
-                    String::from("--arg"),
-                    String::from("nixpkgs"),
-                    String::from("{ outPath=./.; revCount=999999; shortRev=\"ofborg\"; }"),
-                    String::from("./pkgs/top-level/release.nix"),
-                    String::from("-A"),
-                    String::from("unstable"),
-                ],
-                self.nix.clone(),
-            ),
-            EvalChecker::new(
-                "darwin",
-                nix::Operation::Instantiate,
-                vec![
-                    String::from("--arg"),
-                    String::from("nixpkgs"),
-                    String::from("{ outPath=./.; revCount=999999; shortRev=\"ofborg\"; }"),
-                    String::from("./pkgs/top-level/release.nix"),
-                    String::from("-A"),
-                    String::from("darwin-tested"),
-                ],
-                self.nix.clone(),
-            ),
-        ]
+        checkers_for_branch(&self.eval_checks, self.target_branch(), &self.nix)
     }
 
     fn all_evaluations_passed(
         &mut self,
         dir: &Path,
-        status: &mut CommitStatus,
+        status: &mut EvalCheckRun,
     ) -> StepResult<EvaluationComplete> {
         self.update_stdenv_labels();
 
-        status.set_with_description(
-            "Calculating Changed Outputs",
-            hubcaps::statuses::State::Pending,
-        );
+        status.set_progress("Calculating Changed Outputs");
 
         self.update_new_package_labels();
         self.update_rebuild_labels(&dir, status);
+        self.post_rebuild_summary();
+        self.check_closure_sizes();
         let checks = self.performance_stats();
 
         let builds = self.check_meta_queue_builds(&dir)?;
         Ok(EvaluationComplete { builds, checks })
     }
+
+    fn add_to_report(&self, report: &mut EvaluationReport) {
+        if let Some(ref rebuildsniff) = self.outpath_diff {
+            if let Some((_, ref before)) = rebuildsniff.original {
+                if let Some((_, ref after)) = rebuildsniff.current {
+                    report.stats = Some(EvaluationStatsReport {
+                        before: before.clone(),
+                        after: after.clone(),
+                    });
+
+                    report.rebuilds = rebuildsniff.calculate_rebuild().map(sorted_attrs);
+                    if let Some((removed, added)) = rebuildsniff.package_diff() {
+                        report.removed = Some(sorted_attrs(removed));
+                        report.added = Some(sorted_attrs(added));
+                    }
+
+                    // The stdenvs are checked right before the out
+                    // paths, so they are only complete if these are.
+                    if let Some(ref stdenvs) = self.stdenv_diff {
+                        report.stdenv_changes = Some(stdenvs.changed());
+                    }
+                }
+            }
+        }
+
+        report.maintainers = self.impacted_maintainers.clone();
+    }
+}
+
+fn sorted_attrs(mut attrs: Vec<PackageArch>) -> Vec<PackageArch> {
+    attrs.sort_by(|a, b| {
+        a.package
+            .cmp(&b.package)
+            .then(a.architecture.cmp(&b.architecture))
+    });
+    attrs
+}
+
+/// The evaluation checks of nixpkgs, unless the config or the target
+/// branch declares others.
+pub fn default_checks() -> Vec<EvalCheckSpec> {
+    vec![
+        EvalCheckSpec::new(
+            "package-list",
+            CheckOperation::QueryPackagesJson,
+            &["--file", "."],
+        ),
+        EvalCheckSpec::new(
+            "package-list-no-aliases",
+            CheckOperation::QueryPackagesJson,
+            &[
+                "--file",
+                ".",
+                "--arg",
+                "config",
+                "{ allowAliases = false; }",
+            ],
+        ),
+        release_check("nixos", "./nixos/release-combined.nix", "tested"),
+        release_check("nixos-options", "./nixos/release.nix", "options"),
+        release_check("nixos-manual", "./nixos/release.nix", "manual"),
+        release_check("nixpkgs-manual", "./pkgs/top-level/release.nix", "manual"),
+        release_check("nixpkgs-tarball", "./pkgs/top-level/release.nix", "tarball"),
+        release_check(
+            "nixpkgs-unstable-jobset",
+            "./pkgs/top-level/release.nix",
+            "unstable",
+        ),
+        release_check("darwin", "./pkgs/top-level/release.nix", "darwin-tested"),
+    ]
+}
+
+/// Instantiate an attribute of one of the release files.
+fn release_check(name: &str, file: &str, attr: &str) -> EvalCheckSpec {
+    EvalCheckSpec::new(
+        name,
+        CheckOperation::Instantiate,
+        &[
+            "--arg",
+            "nixpkgs",
+            "{ outPath=./.; revCount=999999; shortRev=\"ofborg\"; }",
+            file,
+            "-A",
+            attr,
+        ],
+    )
 }
 
 fn request_reviews(maint: &maintainers::ImpactedMaintainers, pull: &hubcaps::pulls::PullRequest) {
@@ -576,6 +758,18 @@ fn parse_commit_messages(messages: &[String]) -> Vec<String> {
         .collect()
 }
 
+/// The rebuilt attributes, the ones rebuilt on the most systems first.
+fn prioritise_rebuilds(rebuilds: &[PackageArch]) -> Vec<String> {
+    let mut systems_by_attr: HashMap<&String, usize> = HashMap::new();
+    for rebuild in rebuilds {
+        *systems_by_attr.entry(&rebuild.package).or_insert(0) += 1;
+    }
+
+    let mut attrs: Vec<(&String, usize)> = systems_by_attr.into_iter().collect();
+    attrs.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    attrs.into_iter().map(|(attr, _)| attr.clone()).collect()
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -617,4 +811,37 @@ mod tests {
             expect
         );
     }
+
+    #[test]
+    fn test_default_checks() {
+        let nix = Nix::new("x86_64-linux".to_owned(), "daemon".to_owned(), 1800, None);
+        let checkers = checkers_for_branch(&default_checks(), "master", &nix);
+
+        assert_eq!(checkers.len(), 9);
+        assert_eq!(checkers[0].name(), "grahamcofborg-eval-package-list");
+        assert_eq!(
+            checkers[2].cli_cmd(),
+            "nix-instantiate --arg nixpkgs { outPath=./.; revCount=999999; shortRev=\"ofborg\"; } ./nixos/release-combined.nix -A tested"
+        );
+    }
+
+    #[test]
+    fn test_prioritise_rebuilds() {
+        let rebuild = |package: &str, architecture: &str| PackageArch {
+            package: package.to_owned(),
+            architecture: architecture.to_owned(),
+        };
+
+        assert_eq!(
+            prioritise_rebuilds(&[
+                rebuild("hello", "x86_64-linux"),
+                rebuild("vim", "x86_64-linux"),
+                rebuild("firefox", "x86_64-darwin"),
+                rebuild("vim", "x86_64-darwin"),
+                rebuild("firefox", "x86_64-linux"),
+                rebuild("vim", "aarch64-linux"),
+            ]),
+            vec!["vim", "firefox", "hello"]
+        );
+    }
 }
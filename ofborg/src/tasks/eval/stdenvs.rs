@@ -1,5 +1,6 @@
 use ofborg::files::file_to_str;
 use ofborg::nix;
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 enum StdenvFrom {
@@ -7,82 +8,59 @@ enum StdenvFrom {
     After,
 }
 
-#[derive(Debug)]
-pub enum System {
-    X8664Darwin,
-    X8664Linux,
-}
-
 #[derive(Debug, PartialEq)]
 pub struct Stdenvs {
     nix: nix::Nix,
     co: PathBuf,
+    systems: Vec<String>,
 
-    linux_stdenv_before: Option<String>,
-    linux_stdenv_after: Option<String>,
-
-    darwin_stdenv_before: Option<String>,
-    darwin_stdenv_after: Option<String>,
+    before: HashMap<String, Option<String>>,
+    after: HashMap<String, Option<String>>,
 }
 
 impl Stdenvs {
-    pub fn new(nix: nix::Nix, co: PathBuf) -> Stdenvs {
+    pub fn new(nix: nix::Nix, co: PathBuf, systems: Vec<String>) -> Stdenvs {
         Stdenvs {
             nix,
             co,
+            systems,
 
-            linux_stdenv_before: None,
-            linux_stdenv_after: None,
-
-            darwin_stdenv_before: None,
-            darwin_stdenv_after: None,
+            before: HashMap::new(),
+            after: HashMap::new(),
         }
     }
 
     pub fn identify_before(&mut self) {
-        self.identify(System::X8664Linux, StdenvFrom::Before);
-        self.identify(System::X8664Darwin, StdenvFrom::Before);
+        for system in self.systems.clone() {
+            self.identify(&system, StdenvFrom::Before);
+        }
     }
 
     pub fn identify_after(&mut self) {
-        self.identify(System::X8664Linux, StdenvFrom::After);
-        self.identify(System::X8664Darwin, StdenvFrom::After);
+        for system in self.systems.clone() {
+            self.identify(&system, StdenvFrom::After);
+        }
     }
 
     pub fn are_same(&self) -> bool {
         self.changed().is_empty()
     }
 
-    pub fn changed(&self) -> Vec<System> {
-        let mut changed: Vec<System> = vec![];
-
-        if self.linux_stdenv_before != self.linux_stdenv_after {
-            changed.push(System::X8664Linux);
-        }
-
-        if self.darwin_stdenv_before != self.darwin_stdenv_after {
-            changed.push(System::X8664Darwin);
-        }
-
-        changed
+    /// The systems whose stdenv is different after the PR.
+    pub fn changed(&self) -> Vec<String> {
+        self.systems
+            .iter()
+            .filter(|system| self.before.get(*system) != self.after.get(*system))
+            .cloned()
+            .collect()
     }
 
-    fn identify(&mut self, system: System, from: StdenvFrom) {
-        match (system, from) {
-            (System::X8664Linux, StdenvFrom::Before) => {
-                self.linux_stdenv_before = self.evalstdenv("x86_64-linux");
-            }
-            (System::X8664Linux, StdenvFrom::After) => {
-                self.linux_stdenv_after = self.evalstdenv("x86_64-linux");
-            }
-
-            (System::X8664Darwin, StdenvFrom::Before) => {
-                self.darwin_stdenv_before = self.evalstdenv("x86_64-darwin");
-            }
-            (System::X8664Darwin, StdenvFrom::After) => {
-                self.darwin_stdenv_after = self.evalstdenv("x86_64-darwin");
-            }
-        }
+    fn identify(&mut self, system: &str, from: StdenvFrom) {
+        let stdenv = self.evalstdenv(system);
+        match from {
+            StdenvFrom::Before => self.before.insert(system.to_owned(), stdenv),
+            StdenvFrom::After => self.after.insert(system.to_owned(), stdenv),
+        };
     }
 
     /// This is used to find out what the output path of the stdenv for the
@@ -130,12 +108,13 @@ mod tests {
 
         let remote = env::var("NIX_REMOTE").unwrap_or("".to_owned());
         let nix = nix::Nix::new(String::from("x86_64-linux"), remote, 1200, None);
-        let mut stdenv = Stdenvs::new(nix.clone(), PathBuf::from(nixpkgs.trim_end()));
-        stdenv.identify(System::X8664Linux, StdenvFrom::Before);
-        stdenv.identify(System::X8664Darwin, StdenvFrom::Before);
-
-        stdenv.identify(System::X8664Linux, StdenvFrom::After);
-        stdenv.identify(System::X8664Darwin, StdenvFrom::After);
+        let mut stdenv = Stdenvs::new(
+            nix.clone(),
+            PathBuf::from(nixpkgs.trim_end()),
+            vec![String::from("x86_64-linux"), String::from("x86_64-darwin")],
+        );
+        stdenv.identify_before();
+        stdenv.identify_after();
 
         assert!(stdenv.are_same());
     }
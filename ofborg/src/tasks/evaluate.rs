@@ -4,22 +4,34 @@ extern crate env_logger;
 extern crate uuid;
 use amqp::protocol::basic::{BasicProperties, Deliver};
 use hubcaps;
-use hubcaps::checks::CheckRunOptions;
-use hubcaps::gists::Gists;
+use hubcaps::checks::{CheckRunOptions, Conclusion};
 use hubcaps::issues::Issue;
 use ofborg::acl::ACL;
+use ofborg::artifacts::{evaluation_artifact_path, ArtifactStore};
 use ofborg::checkout;
-use ofborg::commitstatus::CommitStatus;
-use ofborg::config::GithubAppVendingMachine;
+use ofborg::checkrun::{
+    annotations, evaluation_conclusion, evaluation_summary, failure_text, EvalCheckRun,
+};
+use ofborg::config::{
+    AutoBuildConfig, ClosureSizeConfig, GithubAppVendingMachine, PerformanceConfig,
+};
+use ofborg::evalchecker::{CheckerPool, EvalCheckSpec, Progress};
 use ofborg::files::file_to_str;
+use ofborg::latestheads::LatestHeads;
+use ofborg::message::evaluationreport::{CheckOutcome, EvaluationReport, EvaluationStatus};
 use ofborg::message::{buildjob, evaluationjob};
 use ofborg::nix;
+use ofborg::outpathcache::OutPathCache;
 use ofborg::stats;
 use ofborg::stats::Event;
 use ofborg::systems;
+use ofborg::tagger::RebuildBucket;
 use ofborg::worker;
+use serde_json;
 use std::collections::HashMap;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::RwLock;
 use std::time::Instant;
 use tasks::eval;
@@ -28,12 +40,22 @@ use tasks::eval::StepResult;
 pub struct EvaluationWorker<E> {
     cloner: checkout::CachedCloner,
     nix: nix::Nix,
-    github: hubcaps::Github,
+    artifacts: Box<dyn ArtifactStore>,
     github_vend: RwLock<GithubAppVendingMachine>,
     acl: ACL,
     identity: String,
     events: E,
     tag_paths: HashMap<String, Vec<String>>,
+    systems: Vec<systems::System>,
+    auto_build: AutoBuildConfig,
+    rebuild_buckets: Vec<RebuildBucket>,
+    performance: PerformanceConfig,
+    closure_size: ClosureSizeConfig,
+    heads: LatestHeads,
+    report_storage: Option<PathBuf>,
+    checker_pool: CheckerPool,
+    eval_checks: HashMap<String, Vec<EvalCheckSpec>>,
+    outpath_cache: Option<OutPathCache>,
 }
 
 impl<E: stats::SysEvents> EvaluationWorker<E> {
@@ -41,22 +63,42 @@ impl<E: stats::SysEvents> EvaluationWorker<E> {
     pub fn new(
         cloner: checkout::CachedCloner,
         nix: &nix::Nix,
-        github: hubcaps::Github,
+        artifacts: Box<dyn ArtifactStore>,
         github_vend: GithubAppVendingMachine,
         acl: ACL,
         identity: String,
         events: E,
         tag_paths: HashMap<String, Vec<String>>,
+        systems: Vec<systems::System>,
+        auto_build: AutoBuildConfig,
+        rebuild_buckets: Vec<RebuildBucket>,
+        performance: PerformanceConfig,
+        closure_size: ClosureSizeConfig,
+        heads: LatestHeads,
+        report_storage: Option<PathBuf>,
+        checker_pool: CheckerPool,
+        eval_checks: HashMap<String, Vec<EvalCheckSpec>>,
+        outpath_cache: Option<OutPathCache>,
     ) -> EvaluationWorker<E> {
         EvaluationWorker {
             cloner,
             nix: nix.without_limited_supported_systems(),
-            github,
+            artifacts,
             github_vend: RwLock::new(github_vend),
             acl,
             identity,
             events,
             tag_paths,
+            systems,
+            auto_build,
+            rebuild_buckets,
+            performance,
+            closure_size,
+            heads,
+            report_storage,
+            checker_pool,
+            eval_checks,
+            outpath_cache,
         }
     }
 
@@ -64,24 +106,61 @@ impl<E: stats::SysEvents> EvaluationWorker<E> {
         evaluationjob::Actions {}
     }
 
+    /// Record why a step of the strategy failed on the check run,
+    /// returning the reason.
     fn handle_strategy_err(
         &self,
         ret: StepResult<()>,
-        gists: &Gists<'_>,
-        status: &mut CommitStatus,
-    ) -> Result<(), ()> {
+        job: &evaluationjob::EvaluationJob,
+        status: &mut EvalCheckRun,
+        checkout: Option<&Path>,
+    ) -> Result<(), String> {
         match ret {
             Ok(()) => Ok(()),
-            Err(eval::Error::Fail(msg)) => {
-                status.set_with_description(&msg, hubcaps::statuses::State::Failure);
-                Err(())
-            }
+            Err(eval::Error::Fail(msg)) => Err(msg),
             Err(eval::Error::FailWithGist(msg, filename, content)) => {
-                status.set_url(make_gist(&gists, &filename, Some("".to_owned()), content));
-                status.set_with_description(&msg, hubcaps::statuses::State::Failure);
-                Err(())
+                status.set_text(Some(failure_text(&content)));
+                if let Some(checkout) = checkout {
+                    status.set_annotations(annotations(&content, checkout, &filename));
+                }
+                status.set_url(store_artifact(&*self.artifacts, job, &filename, content));
+                Err(msg)
+            }
+        }
+    }
+
+    /// Complete the overall check run with the report of the
+    /// evaluation, then publish the report and write it to the log
+    /// storage, along with the job's actions.
+    fn finish(
+        &self,
+        mut report: EvaluationReport,
+        strategy: &dyn eval::EvaluationStrategy,
+        overall_status: &mut EvalCheckRun,
+        actions: worker::Actions,
+    ) -> worker::Actions {
+        strategy.add_to_report(&mut report);
+
+        let summary = evaluation_summary(&report);
+        if !summary.is_empty() {
+            overall_status.set_summary(summary);
+        }
+        let (conclusion, title) = evaluation_conclusion(&report.status);
+        overall_status.complete(conclusion, &title);
+
+        if let Some(ref root) = self.report_storage {
+            if let Err(e) = write_report(root, &report) {
+                warn!("Failed to write the evaluation report: {:?}", e);
             }
         }
+
+        let mut response = vec![worker::publish_serde_action(
+            Some("evaluation-reports".to_owned()),
+            None,
+            &report,
+        )];
+        response.extend(actions);
+        response
     }
 }
 
@@ -121,7 +200,6 @@ impl<E: stats::SysEvents + 'static> worker::SimpleWorker for EvaluationWorker<E>
             .for_repo(&job.repo.owner, &job.repo.name)
             .expect("Failed to get a github client token");
         let repo = github_client.repo(job.repo.owner.clone(), job.repo.name.clone());
-        let gists = self.github.gists();
         let pulls = repo.pulls();
         let pull = pulls.get(job.pr.number);
         let issue_ref = repo.issue(job.pr.number);
@@ -156,6 +234,11 @@ impl<E: stats::SysEvents + 'static> worker::SimpleWorker for EvaluationWorker<E>
             }
         };
 
+        let eval_checks = self
+            .eval_checks
+            .get(&job.repo.full_name.to_lowercase())
+            .cloned();
+
         let mut evaluation_strategy: Box<eval::EvaluationStrategy> = if job.is_nixpkgs() {
             Box::new(eval::NixpkgsStrategy::new(
                 &job,
@@ -163,36 +246,66 @@ impl<E: stats::SysEvents + 'static> worker::SimpleWorker for EvaluationWorker<E>
                 &issue,
                 &issue_ref,
                 &repo,
-                &gists,
+                &*self.artifacts,
                 self.nix.clone(),
                 &self.tag_paths,
+                &self.systems,
+                &self.auto_build,
+                &self.rebuild_buckets,
+                &self.performance,
+                &self.closure_size,
+                eval_checks.unwrap_or_else(eval::default_nixpkgs_checks),
+                self.outpath_cache.as_ref(),
             ))
         } else {
-            Box::new(eval::GenericStrategy::new())
+            Box::new(eval::GenericStrategy::new(
+                job,
+                &issue_ref,
+                self.nix.clone(),
+                eval_checks.unwrap_or_default(),
+            ))
         };
 
-        let mut overall_status = CommitStatus::new(
-            repo.statuses(),
+        let mut overall_status = EvalCheckRun::new(
+            repo.checkruns(),
             job.pr.head_sha.clone(),
             "grahamcofborg-eval".to_owned(),
-            "Starting".to_owned(),
-            None,
+            "Evaluating the PR.".to_owned(),
         );
+        let mut report = EvaluationReport::new(job, &self.identity);
 
-        overall_status.set_with_description("Starting", hubcaps::statuses::State::Pending);
+        if is_superseded(&self.heads, &mut self.events, job) {
+            report.status = EvaluationStatus::Superseded;
+            return self.finish(
+                report,
+                &*evaluation_strategy,
+                &mut overall_status,
+                self.actions().skip(job),
+            );
+        }
 
-        if self
-            .handle_strategy_err(evaluation_strategy.pre_clone(), &gists, &mut overall_status)
-            .is_err()
-        {
-            return self.actions().skip(&job);
+        overall_status.set_progress("Starting");
+
+        if let Err(reason) = self.handle_strategy_err(
+            evaluation_strategy.pre_clone(),
+            job,
+            &mut overall_status,
+            None,
+        ) {
+            report.status = EvaluationStatus::Failure { reason };
+            return self.finish(
+                report,
+                &*evaluation_strategy,
+                &mut overall_status,
+                self.actions().skip(job),
+            );
         }
 
         let project = self
             .cloner
             .project(&job.repo.full_name, job.repo.clone_url.clone());
 
-        overall_status.set_with_description("Cloning project", hubcaps::statuses::State::Pending);
+        overall_status.set_progress("Cloning project");
 
         info!("Working on {}", job.pr.number);
         let co = project
@@ -204,22 +317,48 @@ impl<E: stats::SysEvents + 'static> worker::SimpleWorker for EvaluationWorker<E>
             None => String::from("master"),
         };
 
-        overall_status.set_with_description(
-            format!("Checking out {}", &target_branch).as_ref(),
-            hubcaps::statuses::State::Pending,
-        );
+        overall_status.set_progress(&format!("Checking out {}", &target_branch));
         info!("Checking out target branch {}", &target_branch);
         let refpath = co.checkout_origin_ref(target_branch.as_ref()).unwrap();
+        let base_sha = co.head_commit().unwrap();
 
-        if self
-            .handle_strategy_err(
-                evaluation_strategy.on_target_branch(&Path::new(&refpath), &mut overall_status),
-                &gists,
+        let mut target_status = phase_check_run(
+            &repo,
+            job,
+            "target-branch",
+            format!("Evaluating `{}` before the PR is merged.", &target_branch),
+        );
+        target_status.set_progress("Evaluating the target branch");
+
+        if let Err(reason) = self.handle_strategy_err(
+            evaluation_strategy.on_target_branch(
+                &Path::new(&refpath),
+                &base_sha,
+                &mut target_status,
+            ),
+            job,
+            &mut target_status,
+            Some(Path::new(&refpath)),
+        ) {
+            target_status.complete(Conclusion::Failure, &reason);
+            report.status = EvaluationStatus::Failure { reason };
+            return self.finish(
+                report,
+                &*evaluation_strategy,
                 &mut overall_status,
-            )
-            .is_err()
-        {
-            return self.actions().skip(&job);
+                self.actions().skip(job),
+            );
+        }
+        target_status.complete(Conclusion::Success, "Evaluated the target branch");
+
+        if is_superseded(&self.heads, &mut self.events, job) {
+            report.status = EvaluationStatus::Superseded;
+            return self.finish(
+                report,
+                &*evaluation_strategy,
+                &mut overall_status,
+                self.actions().skip(job),
+            );
         }
 
         let target_branch_rebuild_sniff_start = Instant::now();
@@ -231,137 +370,269 @@ impl<E: stats::SysEvents + 'static> worker::SimpleWorker for EvaluationWorker<E>
         self.events
             .notify(Event::EvaluationDurationCount(target_branch.clone()));
 
-        overall_status.set_with_description("Fetching PR", hubcaps::statuses::State::Pending);
+        overall_status.set_progress("Fetching PR");
+
+        let mut merge_status = phase_check_run(
+            &repo,
+            job,
+            "merge",
+            format!(
+                "Merging the PR into `{}` and evaluating the result.",
+                &target_branch
+            ),
+        );
+        merge_status.set_progress("Fetching the PR");
 
         co.fetch_pr(job.pr.number).unwrap();
 
         if !co.commit_exists(job.pr.head_sha.as_ref()) {
-            overall_status
-                .set_with_description("Commit not found", hubcaps::statuses::State::Error);
+            merge_status.complete(Conclusion::Failure, "Commit not found");
 
             info!("Commit {} doesn't exist", job.pr.head_sha);
-            return self.actions().skip(&job);
+            report.status = EvaluationStatus::Failure {
+                reason: String::from("Commit not found"),
+            };
+            return self.finish(
+                report,
+                &*evaluation_strategy,
+                &mut overall_status,
+                self.actions().skip(job),
+            );
         }
 
-        if self
-            .handle_strategy_err(
-                evaluation_strategy.after_fetch(&co),
-                &gists,
+        if let Err(reason) = self.handle_strategy_err(
+            evaluation_strategy.after_fetch(&co),
+            job,
+            &mut merge_status,
+            Some(Path::new(&refpath)),
+        ) {
+            merge_status.complete(Conclusion::Failure, &reason);
+            report.status = EvaluationStatus::Failure { reason };
+            return self.finish(
+                report,
+                &*evaluation_strategy,
                 &mut overall_status,
-            )
-            .is_err()
-        {
-            return self.actions().skip(&job);
+                self.actions().skip(job),
+            );
         }
 
-        overall_status.set_with_description("Merging PR", hubcaps::statuses::State::Pending);
+        overall_status.set_progress("Merging PR");
+        merge_status.set_progress("Merging the PR");
 
         if co.merge_commit(job.pr.head_sha.as_ref()).is_err() {
-            overall_status
-                .set_with_description("Failed to merge", hubcaps::statuses::State::Failure);
+            merge_status.complete(Conclusion::Failure, "Failed to merge");
 
             info!("Failed to merge {}", job.pr.head_sha);
 
             evaluation_strategy.merge_conflict();
 
-            return self.actions().skip(&job);
+            report.status = EvaluationStatus::MergeConflict;
+            return self.finish(
+                report,
+                &*evaluation_strategy,
+                &mut overall_status,
+                self.actions().skip(job),
+            );
+        }
+
+        if let Err(reason) = self.handle_strategy_err(
+            evaluation_strategy.after_merge(&mut merge_status),
+            job,
+            &mut merge_status,
+            Some(Path::new(&refpath)),
+        ) {
+            merge_status.complete(Conclusion::Failure, &reason);
+            report.status = EvaluationStatus::Failure { reason };
+            return self.finish(
+                report,
+                &*evaluation_strategy,
+                &mut overall_status,
+                self.actions().skip(job),
+            );
         }
+        merge_status.complete(Conclusion::Success, "Merged and evaluated the PR");
 
-        if self
-            .handle_strategy_err(
-                evaluation_strategy.after_merge(&mut overall_status),
-                &gists,
+        if is_superseded(&self.heads, &mut self.events, job) {
+            report.status = EvaluationStatus::Superseded;
+            return self.finish(
+                report,
+                &*evaluation_strategy,
                 &mut overall_status,
-            )
-            .is_err()
-        {
-            return self.actions().skip(&job);
+                self.actions().skip(job),
+            );
         }
 
         println!("Got path: {:?}, building", refpath);
-        overall_status
-            .set_with_description("Beginning Evaluations", hubcaps::statuses::State::Pending);
+        overall_status.set_progress("Beginning Evaluations");
 
-        let eval_results: bool = evaluation_strategy
-            .evaluation_checks()
-            .into_iter()
+        let checks = evaluation_strategy.evaluation_checks();
+        let mut statuses: Vec<EvalCheckRun> = checks
+            .iter()
             .map(|check| {
-                let mut status = CommitStatus::new(
-                    repo.statuses(),
+                let mut status = EvalCheckRun::new(
+                    repo.checkruns(),
                     job.pr.head_sha.clone(),
                     check.name(),
-                    check.cli_cmd(),
-                    None,
+                    format!("`{}`", check.cli_cmd()),
                 );
+                status.set_queued("Waiting for other evaluations");
+                status
+            })
+            .collect();
+        let mut outcomes: Vec<CheckOutcome> = checks
+            .iter()
+            .map(|check| CheckOutcome {
+                name: check.name(),
+                success: false,
+            })
+            .collect();
+
+        let events = &mut self.events;
+        let artifacts = &*self.artifacts;
+        self.checker_pool
+            .run(checks, Path::new(&refpath), |index, check, progress| {
+                let status = &mut statuses[index];
+                let (result, duration) = match progress {
+                    Progress::Started => {
+                        status.set_progress("Evaluating");
+                        return;
+                    }
+                    Progress::Finished(finished) => finished,
+                };
 
-                status.set(hubcaps::statuses::State::Pending);
+                events.notify(Event::EvalCheckerDuration(check.name(), duration.as_secs()));
+                events.notify(Event::EvalCheckerDurationCount(check.name()));
 
-                let state: hubcaps::statuses::State;
-                let gist_url: Option<String>;
-                match check.execute(Path::new(&refpath)) {
+                match result {
                     Ok(_) => {
-                        state = hubcaps::statuses::State::Success;
-                        gist_url = None;
+                        outcomes[index].success = true;
+                        status.complete(Conclusion::Success, "Evaluation succeeded");
                     }
                     Err(mut out) => {
-                        state = hubcaps::statuses::State::Failure;
-                        gist_url = make_gist(
-                            &gists,
+                        let output = file_to_str(&mut out);
+                        status.set_text(Some(failure_text(&output)));
+                        status.set_annotations(annotations(
+                            &output,
+                            Path::new(&refpath),
                             &check.name(),
-                            Some(format!("{:?}", state)),
-                            file_to_str(&mut out),
-                        );
+                        ));
+                        status.set_url(store_artifact(artifacts, job, &check.name(), output));
+                        status.complete(Conclusion::Failure, "Evaluation failed");
                     }
                 }
+            });
 
-                status.set_url(gist_url);
-                status.set(state.clone());
-
-                if state == hubcaps::statuses::State::Success {
-                    Ok(())
-                } else {
-                    Err(())
-                }
-            })
-            .all(|status| status == Ok(()));
+        let eval_results = outcomes.iter().all(|outcome| outcome.success);
+        report.checks.extend(outcomes);
 
         info!("Finished evaluations");
+        if is_superseded(&self.heads, &mut self.events, job) {
+            report.status = EvaluationStatus::Superseded;
+            return self.finish(
+                report,
+                &*evaluation_strategy,
+                &mut overall_status,
+                self.actions().skip(job),
+            );
+        }
+
         let mut response: worker::Actions = vec![];
 
         if eval_results {
+            let mut changes_status = phase_check_run(
+                &repo,
+                job,
+                "changes",
+                String::from("Calculating what the PR changes."),
+            );
+
             let ret = evaluation_strategy
-                .all_evaluations_passed(&Path::new(&refpath), &mut overall_status);
+                .all_evaluations_passed(&Path::new(&refpath), &mut changes_status);
             match ret {
                 Ok(complete) => {
+                    changes_status.complete(Conclusion::Success, "Calculated the changes");
                     send_check_statuses(complete.checks, &repo);
                     response.extend(schedule_builds(complete.builds, auto_schedule_build_archs));
                 }
                 Err(e) => {
                     info!("Failed after all the evaluations passed");
-                    if self
-                        .handle_strategy_err(Err(e), &gists, &mut overall_status)
-                        .is_err()
-                    {
-                        return self.actions().skip(&job);
+                    if let Err(reason) = self.handle_strategy_err(
+                        Err(e),
+                        job,
+                        &mut changes_status,
+                        Some(Path::new(&refpath)),
+                    ) {
+                        changes_status.complete(Conclusion::Failure, &reason);
+                        report.status = EvaluationStatus::Failure { reason };
+                        return self.finish(
+                            report,
+                            &*evaluation_strategy,
+                            &mut overall_status,
+                            self.actions().skip(job),
+                        );
                     }
                 }
             }
 
             info!("Just about done...");
-
-            overall_status.set_with_description("^.^!", hubcaps::statuses::State::Success);
         } else {
-            overall_status
-                .set_with_description("Complete, with errors", hubcaps::statuses::State::Failure);
+            report.status = EvaluationStatus::Failure {
+                reason: String::from("Complete, with errors"),
+            };
         }
 
         self.events.notify(Event::TaskEvaluationCheckComplete);
 
         info!("done!");
-        self.actions().done(&job, response)
+        let actions = self.actions().done(&job, response);
+        self.finish(report, &*evaluation_strategy, &mut overall_status, actions)
     }
 }
 
+/// Check if a newer commit was pushed to the PR since the job was
+/// queued.
+fn is_superseded<E: stats::SysEvents>(
+    heads: &LatestHeads,
+    events: &mut E,
+    job: &evaluationjob::EvaluationJob,
+) -> bool {
+    if !heads.is_superseded(&job.repo, &job.pr) {
+        return false;
+    }
+
+    events.notify(Event::EvaluationSuperseded);
+    info!(
+        "Abandoning {}#{}: {} is no longer the head",
+        job.repo.full_name, job.pr.number, job.pr.head_sha
+    );
+    true
+}
+
+/// The check run of a phase of the evaluation.
+fn phase_check_run<'a>(
+    repo: &'a hubcaps::repositories::Repository,
+    job: &evaluationjob::EvaluationJob,
+    phase: &str,
+    summary: String,
+) -> EvalCheckRun<'a> {
+    EvalCheckRun::new(
+        repo.checkruns(),
+        job.pr.head_sha.clone(),
+        format!("grahamcofborg-eval-{}", phase),
+        summary,
+    )
+}
+
+fn write_report(root: &Path, report: &EvaluationReport) -> io::Result<()> {
+    let path = root.join(report.storage_path());
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    serde_json::to_writer(File::create(path)?, report)?;
+    Ok(())
+}
+
 fn send_check_statuses(checks: Vec<CheckRunOptions>, repo: &hubcaps::repositories::Repository) {
     for check in checks {
         match repo.checkruns().create(&check) {
@@ -381,7 +652,19 @@ fn schedule_builds(
         builds, auto_schedule_build_archs
     );
     for buildjob in builds {
-        for arch in auto_schedule_build_archs.iter() {
+        // Jobs for some systems only go to those the PR may build on.
+        let archs: Vec<&systems::System> = auto_schedule_build_archs
+            .iter()
+            .filter(|arch| match buildjob.systems {
+                Some(ref systems) => systems.contains(&arch.to_string()),
+                None => true,
+            })
+            .collect();
+        if archs.is_empty() {
+            continue;
+        }
+
+        for arch in archs.iter() {
             let (exchange, routingkey) = arch.as_build_destination();
             response.push(worker::publish_serde_action(
                 exchange, routingkey, &buildjob,
@@ -392,10 +675,7 @@ fn schedule_builds(
             None,
             &buildjob::QueuedBuildJobs {
                 job: buildjob,
-                architectures: auto_schedule_build_archs
-                    .iter()
-                    .map(|arch| arch.to_string())
-                    .collect(),
+                architectures: archs.iter().map(|arch| arch.to_string()).collect(),
             },
         ));
     }
@@ -403,31 +683,21 @@ fn schedule_builds(
     response
 }
 
-pub fn make_gist<'a>(
-    gists: &hubcaps::gists::Gists<'a>,
+/// Store an artifact of the evaluation of the job, returning the URL
+/// it can be viewed at.
+pub fn store_artifact(
+    artifacts: &dyn ArtifactStore,
+    job: &evaluationjob::EvaluationJob,
     name: &str,
-    description: Option<String>,
     contents: String,
 ) -> Option<String> {
-    let mut files: HashMap<String, hubcaps::gists::Content> = HashMap::new();
-    files.insert(
-        name.to_string(),
-        hubcaps::gists::Content {
-            filename: Some(name.to_string()),
-            content: contents,
-        },
-    );
-
-    Some(
-        gists
-            .create(&hubcaps::gists::GistOptions {
-                description,
-                public: Some(true),
-                files,
-            })
-            .expect("Failed to create gist!")
-            .html_url,
-    )
+    match artifacts.store(&evaluation_artifact_path(job, name), &contents) {
+        Ok(url) => Some(url),
+        Err(e) => {
+            warn!("Failed to store the {} artifact: {}", name, e);
+            None
+        }
+    }
 }
 
 pub fn update_labels(issue: &hubcaps::issues::IssueRef, add: &[String], remove: &[String]) {
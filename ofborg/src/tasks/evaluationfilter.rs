@@ -6,7 +6,7 @@ use ofborg::ghevent;
 use serde_json;
 
 use amqp::protocol::basic::{BasicProperties, Deliver};
-use ofborg::message::{evaluationjob, Pr, Repo};
+use ofborg::message::{evaluationjob, headupdate, Pr, Repo};
 use ofborg::worker;
 
 pub struct EvaluationFilterWorker {
@@ -92,12 +92,18 @@ impl worker::SimpleWorker for EvaluationFilterWorker {
             target_branch: Some(job.pull_request.base.git_ref.clone()),
         };
 
+        let head = headupdate::HeadUpdate {
+            repo: repo_msg.clone(),
+            pr: pr_msg.clone(),
+        };
+
         let msg = evaluationjob::EvaluationJob {
             repo: repo_msg.clone(),
             pr: pr_msg.clone(),
         };
 
         return vec![
+            worker::publish_serde_action(Some("pr-head-updates".to_owned()), None, &head),
             worker::publish_serde_action(None, Some("mass-rebuild-check-jobs".to_owned()), &msg),
             worker::Action::Ack,
         ];
@@ -118,13 +124,30 @@ mod tests {
 
         let mut worker = EvaluationFilterWorker::new(acl::ACL::new(
             vec!["nixos/nixpkgs".to_owned()],
-            vec![],
+            acl::Policy::from_user_lists(vec![], vec![]),
             vec![],
         ));
 
+        let repo = Repo {
+            clone_url: String::from("https://github.com/NixOS/nixpkgs.git"),
+            full_name: String::from("NixOS/nixpkgs"),
+            owner: String::from("NixOS"),
+            name: String::from("nixpkgs"),
+        };
+        let pr = Pr {
+            number: 33299,
+            head_sha: String::from("887e8b460a7d45ddb3bbdebe01447b251b3229e8"),
+            target_branch: Some(String::from("staging")),
+        };
+
         assert_eq!(
             worker.consumer(&job),
             vec![
+                worker::publish_serde_action(
+                    Some("pr-head-updates".to_owned()),
+                    None,
+                    &headupdate::HeadUpdate { repo, pr },
+                ),
                 worker::publish_serde_action(
                     None,
                     Some("mass-rebuild-check-jobs".to_owned()),
@@ -10,21 +10,57 @@ use uuid::Uuid;
 use amqp::protocol::basic::{BasicProperties, Deliver};
 use hubcaps;
 use ofborg::commentparser;
-use ofborg::message::{buildjob, evaluationjob, Pr, Repo};
+use ofborg::message::{buildcancel, buildjob, evaluationjob, unix_time, Pr, Repo};
+use ofborg::quota;
+use ofborg::stats::{Event, SysEvents};
+use ofborg::systems;
 use ofborg::worker;
+use std::time::Instant;
 
-pub struct GitHubCommentWorker {
+pub struct GitHubCommentWorker<E> {
     acl: acl::ACL,
     github: hubcaps::Github,
+    events: E,
+    quotas: quota::Quotas,
 }
 
-impl GitHubCommentWorker {
-    pub fn new(acl: acl::ACL, github: hubcaps::Github) -> GitHubCommentWorker {
-        GitHubCommentWorker { acl, github }
+impl<E: SysEvents> GitHubCommentWorker<E> {
+    pub fn new(acl: acl::ACL, github: hubcaps::Github, events: E) -> GitHubCommentWorker<E> {
+        GitHubCommentWorker {
+            acl,
+            github,
+            events,
+            quotas: quota::Quotas::new(),
+        }
+    }
+
+    fn reply_with_errors(&self, job: &ghevent::IssueComment, errors: &[commentparser::ParseError]) {
+        self.reply(job, parse_errors_reply(errors));
+    }
+
+    fn reply(&self, job: &ghevent::IssueComment, body: String) {
+        let reply = hubcaps::comments::CommentOptions { body };
+
+        let post = self
+            .github
+            .repo(
+                job.repository.owner.login.clone(),
+                job.repository.name.clone(),
+            )
+            .issue(job.issue.number)
+            .comments()
+            .create(&reply);
+
+        if let Err(x) = post {
+            info!(
+                "replying to {}#{} on GitHub yielded error {}",
+                job.repository.full_name, job.issue.number, x
+            );
+        }
     }
 }
 
-impl worker::SimpleWorker for GitHubCommentWorker {
+impl<E: SysEvents + 'static> worker::SimpleWorker for GitHubCommentWorker<E> {
     type J = ghevent::IssueComment;
 
     fn msg_to_job(
@@ -50,8 +86,18 @@ impl worker::SimpleWorker for GitHubCommentWorker {
             return vec![worker::Action::Ack];
         }
 
-        let instructions = commentparser::parse(&job.comment.body);
-        if instructions == None {
+        let (instructions, errors) = commentparser::parse_with_errors(&job.comment.body);
+        if instructions.is_empty() && errors.is_empty() {
+            return vec![worker::Action::Ack];
+        }
+
+        if self
+            .acl
+            .user_trust(&job.comment.user.login, &job.repository.full_name)
+            .is_none()
+        {
+            println!("Unknown user for: {:?}", job);
+            // Don't process comments from users who can't use ofborg
             return vec![worker::Action::Ack];
         }
 
@@ -60,17 +106,18 @@ impl worker::SimpleWorker for GitHubCommentWorker {
             &job.repository.full_name,
         );
 
-        if build_destinations.is_empty() {
-            println!("No build destinations for: {:?}", job);
-            // Don't process comments if they can't build anything
-            return vec![worker::Action::Ack];
-        }
-
         println!("Got job: {:?}", job);
-
-        let instructions = commentparser::parse(&job.comment.body);
         println!("Instructions: {:?}", instructions);
 
+        if !errors.is_empty() {
+            println!("Unparseable commands: {:?}", errors);
+            self.reply_with_errors(job, &errors);
+        }
+
+        if instructions.is_empty() {
+            return vec![worker::Action::Ack];
+        }
+
         let pr = self
             .github
             .repo(
@@ -105,59 +152,141 @@ impl worker::SimpleWorker for GitHubCommentWorker {
         };
 
         let mut response: Vec<worker::Action> = vec![];
-        if let Some(instructions) = instructions {
-            for instruction in instructions {
-                match instruction {
-                    commentparser::Instruction::Build(subset, attrs) => {
-                        let build_destinations = match subset {
-                            commentparser::Subset::NixOS => build_destinations
-                                .clone()
-                                .into_iter()
-                                .filter(|x| x.can_run_nixos_tests())
-                                .collect(),
-                            _ => build_destinations.clone(),
-                        };
-
-                        let msg = buildjob::BuildJob::new(
-                            repo_msg.clone(),
-                            pr_msg.clone(),
-                            subset,
-                            attrs,
-                            None,
-                            None,
-                            format!("{}", Uuid::new_v4()),
+        for instruction in instructions {
+            let command = match instruction {
+                commentparser::Instruction::Build(commentparser::Subset::NixOS, _, _) => {
+                    acl::Command::Test
+                }
+                commentparser::Instruction::Build(_, _, _) => acl::Command::Build,
+                commentparser::Instruction::Eval => acl::Command::Eval,
+                commentparser::Instruction::Cancel => acl::Command::Cancel,
+            };
+
+            if !self.acl.can_use_command(
+                &job.comment.user.login,
+                &job.repository.full_name,
+                command,
+            ) {
+                info!(
+                    "{} may not use {:?} on {}",
+                    job.comment.user.login, command, job.repository.full_name
+                );
+                self.reply(
+                    job,
+                    forbidden_command_reply(command, &job.repository.full_name),
+                );
+                continue;
+            }
+
+            match instruction {
+                commentparser::Instruction::Build(subset, attrs, systems) => {
+                    let build_destinations = match subset {
+                        commentparser::Subset::NixOS => build_destinations
+                            .clone()
+                            .into_iter()
+                            .filter(|x| x.can_run_nixos_tests())
+                            .collect(),
+                        _ => build_destinations.clone(),
+                    };
+
+                    let available = build_destinations;
+                    let build_destinations: Vec<systems::System> = match systems {
+                        Some(ref systems) => available
+                            .iter()
+                            .filter(|x| systems.contains(&x.to_string()))
+                            .cloned()
+                            .collect(),
+                        None => available.clone(),
+                    };
+
+                    if build_destinations.is_empty() {
+                        info!(
+                            "None of the requested systems {:?} are available for {:?}",
+                            systems, attrs
+                        );
+                        self.reply(
+                            job,
+                            unavailable_systems_reply(&attrs, systems.as_ref(), &available),
                         );
+                        continue;
+                    }
 
-                        for arch in build_destinations.iter() {
-                            let (exchange, routingkey) = arch.as_build_destination();
-                            response.push(worker::publish_serde_action(exchange, routingkey, &msg));
+                    if let Some(policy) = self
+                        .acl
+                        .quota_for_user_repo(&job.comment.user.login, &job.repository.full_name)
+                    {
+                        if let Err(throttled) = self.quotas.take(
+                            policy,
+                            &job.comment.user.login,
+                            &job.repository.full_name,
+                            job.issue.number,
+                            Instant::now(),
+                        ) {
+                            info!(
+                                "Throttled the build of {:?} for {}: {}",
+                                attrs, job.comment.user.login, throttled
+                            );
+                            self.events
+                                .notify(Event::BuildRequestThrottled(throttled.limit_name()));
+                            self.reply(job, throttled_reply(&attrs, &throttled));
+                            continue;
                         }
-
-                        response.push(worker::publish_serde_action(
-                            Some("build-results".to_string()),
-                            None,
-                            &buildjob::QueuedBuildJobs {
-                                job: msg,
-                                architectures: build_destinations
-                                    .iter()
-                                    .cloned()
-                                    .map(|arch| arch.to_string())
-                                    .collect(),
-                            },
-                        ));
                     }
-                    commentparser::Instruction::Eval => {
-                        let msg = evaluationjob::EvaluationJob {
-                            repo: repo_msg.clone(),
-                            pr: pr_msg.clone(),
-                        };
-
-                        response.push(worker::publish_serde_action(
-                            None,
-                            Some("mass-rebuild-check-jobs".to_owned()),
-                            &msg,
-                        ));
+
+                    let mut msg = buildjob::BuildJob::new(
+                        repo_msg.clone(),
+                        pr_msg.clone(),
+                        subset,
+                        attrs,
+                        None,
+                        None,
+                        format!("{}", Uuid::new_v4()),
+                    );
+                    msg.systems = systems;
+
+                    for arch in build_destinations.iter() {
+                        let (exchange, routingkey) = arch.as_build_destination();
+                        response.push(worker::publish_serde_action(exchange, routingkey, &msg));
                     }
+
+                    response.push(worker::publish_serde_action(
+                        Some("build-results".to_string()),
+                        None,
+                        &buildjob::QueuedBuildJobs {
+                            job: msg,
+                            architectures: build_destinations
+                                .iter()
+                                .cloned()
+                                .map(|arch| arch.to_string())
+                                .collect(),
+                        },
+                    ));
+                }
+                commentparser::Instruction::Eval => {
+                    let msg = evaluationjob::EvaluationJob {
+                        repo: repo_msg.clone(),
+                        pr: pr_msg.clone(),
+                    };
+
+                    response.push(worker::publish_serde_action(
+                        None,
+                        Some("mass-rebuild-check-jobs".to_owned()),
+                        &msg,
+                    ));
+                }
+                commentparser::Instruction::Cancel => {
+                    let msg = buildcancel::BuildCancel {
+                        repo: repo_msg.clone(),
+                        pr: pr_msg.clone(),
+                        request_id: None,
+                        requested_at: Some(unix_time()),
+                    };
+
+                    response.push(worker::publish_serde_action(
+                        Some("build-cancels".to_owned()),
+                        None,
+                        &msg,
+                    ));
                 }
             }
         }
@@ -166,3 +295,72 @@ impl worker::SimpleWorker for GitHubCommentWorker {
         response
     }
 }
+
+fn unavailable_systems_reply(
+    attrs: &[String],
+    requested: Option<&Vec<String>>,
+    available: &[systems::System],
+) -> String {
+    let quote = |names: &[String]| {
+        names
+            .iter()
+            .map(|name| format!("`{}`", name))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+
+    let mut reply = format!("I didn't start the build of `{}`: ", attrs.join(" "));
+    match requested {
+        Some(requested) => {
+            reply.push_str(&format!("you can't build on {} here.", quote(requested)))
+        }
+        None => reply.push_str("there is no system you can build on here."),
+    }
+
+    if !available.is_empty() {
+        reply.push_str(&format!(
+            " The systems you can build on are {}.",
+            quote(
+                &available
+                    .iter()
+                    .map(|system| system.to_string())
+                    .collect::<Vec<String>>()
+            )
+        ));
+    }
+
+    reply
+}
+
+fn forbidden_command_reply(command: acl::Command, repo: &str) -> String {
+    format!(
+        "I didn't run `{}`: you may not use it on {}.",
+        command, repo
+    )
+}
+
+fn throttled_reply(attrs: &[String], throttled: &quota::Throttled) -> String {
+    format!(
+        "I didn't start the build of `{}`: {}. Please try again later.",
+        attrs.join(" "),
+        throttled
+    )
+}
+
+fn parse_errors_reply(errors: &[commentparser::ParseError]) -> String {
+    let mut reply: Vec<String> = vec![
+        String::from("I didn't understand all of your comment:"),
+        String::from(""),
+    ];
+    reply.extend(errors.iter().map(|err| format!("- {}", err)));
+    reply.push(String::from(""));
+    reply.push(String::from("The commands I know are:"));
+    reply.push(String::from(""));
+    reply.extend(
+        commentparser::USAGE
+            .iter()
+            .map(|usage| format!("- {}", usage)),
+    );
+
+    reply.join("\n")
+}
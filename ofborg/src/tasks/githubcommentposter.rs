@@ -7,10 +7,14 @@ use amqp::protocol::basic::{BasicProperties, Deliver};
 use chrono::{DateTime, Utc};
 use hubcaps::checks::{CheckRunOptions, CheckRunState, Conclusion, Output};
 use message::buildjob::{BuildJob, QueuedBuildJobs};
+use ofborg::checkrun::{human_size, nix_errors, size_change};
 use ofborg::config::GithubAppVendingMachine;
-use ofborg::message::buildresult::{BuildResult, BuildStatus, LegacyBuildResult};
+use ofborg::message::buildresult::{
+    AttrResult, BuildResult, BuildStatus, LegacyBuildResult, PathSizes,
+};
 use ofborg::message::Repo;
 use ofborg::worker;
+use std::collections::BTreeMap;
 
 pub struct GitHubCommentPoster {
     github_vend: GithubAppVendingMachine,
@@ -137,12 +141,37 @@ fn result_to_check(result: &LegacyBuildResult, timestamp: DateTime<Utc>) -> Chec
     let conclusion: Conclusion = result.status.clone().into();
 
     let mut summary: Vec<String> = vec![];
-    if let Some(ref attempted) = result.attempted_attrs {
+    if !result.attr_results.is_empty() {
+        summary.extend(attr_results_segment(&result.attr_results));
+    } else if let Some(ref attempted) = result.attempted_attrs {
         summary.extend(list_segment("Attempted", &attempted));
     }
 
-    if result.status == BuildStatus::TimedOut {
-        summary.push(String::from("Build timed out."));
+    summary.extend(nix_errors(&result.errors));
+
+    match result.status {
+        BuildStatus::TimedOut => summary.push(String::from("Build timed out.")),
+        BuildStatus::MaxSilentTimedOut => summary.push(String::from(
+            "Build timed out, as it printed nothing for too long.",
+        )),
+        BuildStatus::OutOfDisk => summary.push(String::from("The builder ran out of disk space.")),
+        BuildStatus::KilledBySignal { signal } => {
+            summary.push(format!("Build was killed by signal {}.", signal))
+        }
+        BuildStatus::DependencyFailed => summary.push(String::from(
+            "A dependency of the attributes failed to build.",
+        )),
+        _ => {}
+    }
+
+    if result.status == BuildStatus::Cancelled {
+        summary.push(String::from("Build was cancelled."));
+    }
+
+    if result.status == BuildStatus::Superseded {
+        summary.push(String::from(
+            "A newer commit was pushed, so this build was not finished.",
+        ));
     }
 
     if let Some(ref skipped) = result.skipped_attrs {
@@ -196,6 +225,66 @@ fn result_to_check(result: &LegacyBuildResult, timestamp: DateTime<Utc>) -> Chec
     }
 }
 
+/// One line per attribute with its status, and its out paths and their
+/// sizes below it.
+fn attr_results_segment(results: &BTreeMap<String, AttrResult>) -> Vec<String> {
+    let mut reply: Vec<String> = vec![];
+
+    for (attr, result) in results {
+        let status: String = result.status.clone().into();
+        reply.push(format!("- `{}`: {}", attr, status));
+
+        for path in &result.out_paths {
+            match result
+                .sizes
+                .as_ref()
+                .and_then(|sizes| sizes.nar_sizes.get(path))
+            {
+                Some(size) => reply.push(format!("  - `{}`: {}", path, human_size(*size))),
+                None => reply.push(format!("  - `{}`", path)),
+            }
+        }
+
+        if let Some(ref cache) = result.binary_cache {
+            reply.push(format!("  - Cached in `{}`", cache));
+        }
+
+        if let Some(ref sizes) = result.sizes {
+            reply.push(format!(
+                "  - Size: {}{}",
+                human_size(sizes.nar_size()),
+                compared(&result.target_sizes, sizes.nar_size(), |t| t.nar_size())
+            ));
+            reply.push(format!(
+                "  - Closure size: {}{}",
+                human_size(sizes.closure_size),
+                compared(&result.target_sizes, sizes.closure_size, |t| t.closure_size)
+            ));
+        }
+    }
+    reply.push("".to_owned());
+
+    reply
+}
+
+/// How the size compares to the target branch's, if it was built.
+fn compared<F>(target: &Option<PathSizes>, size: u64, target_size: F) -> String
+where
+    F: Fn(&PathSizes) -> u64,
+{
+    match *target {
+        Some(ref target) => {
+            let before = target_size(target);
+            format!(
+                ", {} on the target branch ({})",
+                human_size(before),
+                size_change(before, size)
+            )
+        }
+        None => String::new(),
+    }
+}
+
 fn list_segment(name: &str, things: &[String]) -> Vec<String> {
     let mut reply: Vec<String> = vec![];
 
@@ -212,6 +301,7 @@ mod tests {
     use super::*;
     use chrono::TimeZone;
     use message::{Pr, Repo};
+    use ofborg::nix::NixError;
 
     #[test]
     pub fn test_queued_build() {
@@ -230,8 +320,10 @@ mod tests {
             logs: None,
             statusreport: None,
             subset: None,
+            systems: None,
 
             request_id: "bogus-request-id".to_owned(),
+            requested_at: None,
             attrs: vec!["foo".to_owned(), "bar".to_owned()],
         };
 
@@ -285,6 +377,8 @@ mod tests {
             attempted_attrs: Some(vec!["foo".to_owned()]),
             skipped_attrs: Some(vec!["bar".to_owned()]),
             status: BuildStatus::Success,
+            attr_results: BTreeMap::new(),
+            errors: vec![],
         };
 
         let timestamp = Utc.ymd(2023, 4, 20).and_hms(13, 37, 42);
@@ -335,6 +429,79 @@ patching script interpreter paths in /nix/store/pcja75y9isdvgz5i00pkrpif9rxzxc29
         );
     }
 
+    #[test]
+    pub fn test_check_attr_results() {
+        let foo = "/nix/store/pcja75y9isdvgz5i00pkrpif9rxzxc29-foo".to_owned();
+        let foo_man = "/nix/store/l1limh50lx2cx45yb2gqpv7k8xl1mik2-foo-man".to_owned();
+
+        let mut sizes = PathSizes::default();
+        sizes.nar_sizes.insert(foo.clone(), 3 * 1024 * 1024);
+        sizes.nar_sizes.insert(foo_man.clone(), 512 * 1024);
+        sizes.closure_size = 40 * 1024 * 1024;
+        let mut target_sizes = PathSizes::default();
+        target_sizes.nar_sizes.insert(
+            "/nix/store/5a88zk3jgimdmzg8rfhvm93kxib3njf9-foo".to_owned(),
+            3 * 1024 * 1024,
+        );
+        target_sizes.closure_size = 30 * 1024 * 1024;
+
+        let mut attr_results = BTreeMap::new();
+        attr_results.insert(
+            "bar".to_owned(),
+            AttrResult::new(BuildStatus::Failure, vec![]),
+        );
+        attr_results.insert(
+            "foo".to_owned(),
+            AttrResult {
+                status: BuildStatus::Success,
+                out_paths: vec![foo, foo_man],
+                sizes: Some(sizes),
+                target_sizes: Some(target_sizes),
+                binary_cache: Some("https://cache.example.com".to_owned()),
+            },
+        );
+
+        let result = LegacyBuildResult {
+            repo: Repo {
+                clone_url: "https://github.com/nixos/nixpkgs.git".to_owned(),
+                full_name: "NixOS/nixpkgs".to_owned(),
+                owner: "NixOS".to_owned(),
+                name: "nixpkgs".to_owned(),
+            },
+            pr: Pr {
+                head_sha: "abc123".to_owned(),
+                number: 2345,
+                target_branch: Some("master".to_owned()),
+            },
+            output: vec![],
+            attempt_id: "neatattemptid".to_owned(),
+            request_id: "bogus-request-id".to_owned(),
+            system: "x86_64-linux".to_owned(),
+            attempted_attrs: Some(vec!["foo".to_owned(), "bar".to_owned()]),
+            skipped_attrs: Some(vec![]),
+            status: BuildStatus::Failure,
+            attr_results,
+            errors: vec![],
+        };
+
+        let timestamp = Utc.ymd(2023, 4, 20).and_hms(13, 37, 42);
+
+        let check = result_to_check(&result, timestamp);
+        assert_eq!(check.name, "bar, foo on x86_64-linux");
+        assert_eq!(check.conclusion, Some(Conclusion::Neutral));
+        assert_eq!(
+            check.output.unwrap().summary,
+            "- `bar`: Failure
+- `foo`: Success
+  - `/nix/store/pcja75y9isdvgz5i00pkrpif9rxzxc29-foo`: 3.0 MiB
+  - `/nix/store/l1limh50lx2cx45yb2gqpv7k8xl1mik2-foo-man`: 512.0 KiB
+  - Cached in `https://cache.example.com`
+  - Size: 3.5 MiB, 3.0 MiB on the target branch (+512.0 KiB)
+  - Closure size: 40.0 MiB, 30.0 MiB on the target branch (+10.0 MiB)
+"
+        );
+    }
+
     #[test]
     pub fn test_check_failing_build() {
         let result = LegacyBuildResult {
@@ -367,6 +534,8 @@ patching script interpreter paths in /nix/store/pcja75y9isdvgz5i00pkrpif9rxzxc29
             attempted_attrs: Some(vec!["foo".to_owned()]),
             skipped_attrs: None,
             status: BuildStatus::Failure,
+            attr_results: BTreeMap::new(),
+            errors: vec![],
         };
 
         let timestamp = Utc.ymd(2023, 4, 20).and_hms(13, 37, 42);
@@ -446,6 +615,11 @@ patching script interpreter paths in /nix/store/pcja75y9isdvgz5i00pkrpif9rxzxc29
             attempted_attrs: Some(vec!["foo".to_owned()]),
             skipped_attrs: None,
             status: BuildStatus::TimedOut,
+            attr_results: BTreeMap::new(),
+            errors: vec![NixError::Other {
+                message: "build of '/nix/store/l1limh50lx2cx45yb2gqpv7k8xl1mik2-gdb-8.1.drv' failed"
+                    .to_owned(),
+            }],
         };
 
         let timestamp = Utc.ymd(2023, 4, 20).and_hms(13, 37, 42);
@@ -458,7 +632,7 @@ patching script interpreter paths in /nix/store/pcja75y9isdvgz5i00pkrpif9rxzxc29
                 started_at: None,
                 completed_at: Some("2023-04-20T13:37:42Z".to_string()),
                 status: Some(CheckRunState::Completed),
-                conclusion: Some(Conclusion::Neutral),
+                conclusion: Some(Conclusion::TimedOut),
                 details_url: Some(
                     "https://logs.nix.ci/?key=nixos/nixpkgs.2345&attempt_id=neatattemptid"
                         .to_string()
@@ -469,6 +643,10 @@ patching script interpreter paths in /nix/store/pcja75y9isdvgz5i00pkrpif9rxzxc29
                     title: "Timed out, unknown build status".to_string(),
                     summary: "Attempted: foo
 
+Nix reported:
+
+- build of '/nix/store/l1limh50lx2cx45yb2gqpv7k8xl1mik2-gdb-8.1.drv' failed
+
 Build timed out."
                         .to_string(),
                     text: Some(
@@ -494,6 +672,69 @@ error: build of '/nix/store/l1limh50lx2cx45yb2gqpv7k8xl1mik2-gdb-8.1.drv' failed
         );
     }
 
+    #[test]
+    pub fn test_check_cancelled_build() {
+        let result = LegacyBuildResult {
+            repo: Repo {
+                clone_url: "https://github.com/nixos/nixpkgs.git".to_owned(),
+                full_name: "NixOS/nixpkgs".to_owned(),
+                owner: "NixOS".to_owned(),
+                name: "nixpkgs".to_owned(),
+            },
+            pr: Pr {
+                head_sha: "abc123".to_owned(),
+                number: 2345,
+                target_branch: Some("master".to_owned()),
+            },
+            output: vec!["post-installation fixup".to_owned()],
+            attempt_id: "neatattemptid".to_owned(),
+            request_id: "bogus-request-id".to_owned(),
+            system: "x86_64-linux".to_owned(),
+            attempted_attrs: Some(vec!["foo".to_owned()]),
+            skipped_attrs: None,
+            status: BuildStatus::Cancelled,
+            attr_results: BTreeMap::new(),
+            errors: vec![],
+        };
+
+        let timestamp = Utc.ymd(2023, 4, 20).and_hms(13, 37, 42);
+
+        assert_eq!(
+            result_to_check(&result, timestamp),
+            CheckRunOptions {
+                name: "foo on x86_64-linux".to_string(),
+                actions: None,
+                started_at: None,
+                completed_at: Some("2023-04-20T13:37:42Z".to_string()),
+                status: Some(CheckRunState::Completed),
+                conclusion: Some(Conclusion::Cancelled),
+                details_url: Some(
+                    "https://logs.nix.ci/?key=nixos/nixpkgs.2345&attempt_id=neatattemptid"
+                        .to_string()
+                ),
+                external_id: Some("neatattemptid".to_string()),
+                head_sha: "abc123".to_string(),
+                output: Some(Output {
+                    title: "Cancelled".to_string(),
+                    summary: "Attempted: foo
+
+Build was cancelled."
+                        .to_string(),
+                    text: Some(
+                        "## Partial log
+
+```
+post-installation fixup
+```"
+                        .to_string()
+                    ),
+                    annotations: None,
+                    images: None,
+                })
+            }
+        );
+    }
+
     #[test]
     pub fn test_check_passing_build_unspecified_attributes() {
         let result = LegacyBuildResult {
@@ -526,6 +767,8 @@ error: build of '/nix/store/l1limh50lx2cx45yb2gqpv7k8xl1mik2-gdb-8.1.drv' failed
             attempted_attrs: None,
             skipped_attrs: None,
             status: BuildStatus::Success,
+            attr_results: BTreeMap::new(),
+            errors: vec![],
         };
 
         let timestamp = Utc.ymd(2023, 4, 20).and_hms(13, 37, 42);
@@ -604,6 +847,8 @@ patching script interpreter paths in /nix/store/pcja75y9isdvgz5i00pkrpif9rxzxc29
             attempted_attrs: None,
             skipped_attrs: None,
             status: BuildStatus::Failure,
+            attr_results: BTreeMap::new(),
+            errors: vec![],
         };
 
         let timestamp = Utc.ymd(2023, 4, 20).and_hms(13, 37, 42);
@@ -671,6 +916,8 @@ patching script interpreter paths in /nix/store/pcja75y9isdvgz5i00pkrpif9rxzxc29
             attempted_attrs: None,
             skipped_attrs: Some(vec!["not-attempted".to_owned()]),
             status: BuildStatus::Skipped,
+            attr_results: BTreeMap::new(),
+            errors: vec![],
         };
 
         let timestamp = Utc.ymd(2023, 4, 20).and_hms(13, 37, 42);
@@ -724,6 +971,8 @@ foo
             attempted_attrs: None,
             skipped_attrs: Some(vec!["not-attempted".to_owned()]),
             status: BuildStatus::Skipped,
+            attr_results: BTreeMap::new(),
+            errors: vec![],
         };
 
         let timestamp = Utc.ymd(2023, 4, 20).and_hms(13, 37, 42);
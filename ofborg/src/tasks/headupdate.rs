@@ -0,0 +1,66 @@
+extern crate amqp;
+extern crate env_logger;
+
+use amqp::protocol::basic::{BasicProperties, Deliver};
+use ofborg::latestheads::LatestHeads;
+use ofborg::message::headupdate::{self, HeadUpdate};
+use ofborg::tasks::buildcancel::RunningBuilds;
+use ofborg::worker;
+
+/// Records the newest head of each PR, and optionally cancels the
+/// running builds of the commits it replaced.
+pub struct HeadUpdateWorker {
+    heads: LatestHeads,
+    running: Option<RunningBuilds>,
+}
+
+impl HeadUpdateWorker {
+    pub fn new(heads: LatestHeads) -> HeadUpdateWorker {
+        HeadUpdateWorker {
+            heads,
+            running: None,
+        }
+    }
+
+    pub fn cancelling_builds(heads: LatestHeads, running: RunningBuilds) -> HeadUpdateWorker {
+        HeadUpdateWorker {
+            heads,
+            running: Some(running),
+        }
+    }
+}
+
+impl worker::SimpleWorker for HeadUpdateWorker {
+    type J = HeadUpdate;
+
+    fn msg_to_job(
+        &mut self,
+        _: &Deliver,
+        _: &BasicProperties,
+        body: &[u8],
+    ) -> Result<Self::J, String> {
+        headupdate::from(body).map_err(|e| {
+            format!(
+                "Failed to deserialize HeadUpdate: {:?}, err: {:}",
+                String::from_utf8_lossy(body),
+                e
+            )
+        })
+    }
+
+    fn consumer(&mut self, job: &HeadUpdate) -> worker::Actions {
+        self.heads.update(&job.repo, &job.pr);
+
+        if let Some(ref running) = self.running {
+            let cancelled = running.supersede(job);
+            if cancelled > 0 {
+                info!(
+                    "Cancelled {} superseded build(s) for {}#{}",
+                    cancelled, job.repo.full_name, job.pr.number
+                );
+            }
+        }
+
+        vec![worker::Action::Ack]
+    }
+}
@@ -1,8 +1,10 @@
 pub mod build;
+pub mod buildcancel;
 pub mod eval;
 pub mod evaluate;
 pub mod evaluationfilter;
 pub mod githubcommentfilter;
 pub mod githubcommentposter;
+pub mod headupdate;
 pub mod log_message_collector;
 pub mod statscollector;